@@ -0,0 +1,116 @@
+//! Checks the region files of a world and optionally compacts them.
+//!
+//! ```text
+//! anvil-repair [--compact] [--headers-only] <world or region folder>...
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use cellophanemc_anvil::repair::{compact_region, region_files, scan_region, ScanOptions};
+
+const USAGE: &str = "usage: anvil-repair [--compact] [--headers-only] <world or region folder>...";
+
+/// Folders of a dimension that hold region files.
+const REGION_FOLDERS: [&str; 3] = ["region", "entities", "poi"];
+
+fn main() -> ExitCode {
+    let mut compact = false;
+    let mut options = ScanOptions::default();
+    let mut dirs = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--compact" => compact = true,
+            "--headers-only" => options.check_data = false,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("unknown option {arg}\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+            _ => dirs.push(PathBuf::from(arg)),
+        }
+    }
+    if dirs.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut failed = false;
+    for dir in dirs.iter().flat_map(|dir| region_dirs(dir)) {
+        let paths = match region_files(&dir) {
+            Ok(paths) => paths,
+            Err(e) => {
+                eprintln!("{}: {e}", dir.display());
+                failed = true;
+                continue;
+            }
+        };
+        for path in paths {
+            failed |= !check_region(&path, &options, compact);
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Returns the region folders of a world folder, or `dir` itself if it has
+/// none.
+fn region_dirs(dir: &Path) -> Vec<PathBuf> {
+    let dirs = REGION_FOLDERS
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|dir| dir.is_dir())
+        .collect::<Vec<_>>();
+    if dirs.is_empty() {
+        vec![dir.to_path_buf()]
+    } else {
+        dirs
+    }
+}
+
+/// Scans and, with `compact` set, compacts the region at `path`. Returns
+/// whether the region is valid afterwards.
+fn check_region(path: &Path, options: &ScanOptions, compact: bool) -> bool {
+    let report = match scan_region(path, options) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            return false;
+        }
+    };
+
+    println!(
+        "{}: {} chunks, {} of {} sectors free",
+        path.display(),
+        report.chunks,
+        report.free_sectors(),
+        report.file_sectors
+    );
+    for problem in &report.problems {
+        println!("  {problem}");
+    }
+
+    if !compact || !report.needs_compaction() {
+        return report.is_valid();
+    }
+    match compact_region(&report) {
+        Ok(stats) => {
+            println!(
+                "  compacted from {} to {} sectors, removed {} chunks",
+                stats.sectors_before, stats.sectors_after, stats.removed_chunks
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!("  failed to compact: {e}");
+            false
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Open region files of a world, with a bounded number of file handles.
+//!
+//! A [`RegionCache`] serves one folder of `r.<x>.<z>.mca` files and keeps at
+//! most [`max_open`](RegionCache::with_max_open) of them open, closing the
+//! least recently used region when another one is needed. [`WorldRegions`]
+//! bundles the caches of the three folders of a vanilla dimension.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cellophanemc_nbt::binary::decode::FromModifiedUtf8;
+use cellophanemc_nbt::binary::encode::ToModifiedUtf8;
+use cellophanemc_nbt::Compound;
+
+use crate::error::Result;
+use crate::region::{CompressionType, CustomCompression, RawChunk, Region};
+
+/// Number of regions kept open by default, the same as vanilla.
+pub const DEFAULT_MAX_OPEN_REGIONS: usize = 256;
+
+/// Coordinates of a region, each covering 32×32 chunks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct RegionPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl RegionPos {
+    pub const fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    /// Returns the region containing the chunk at `chunk_x`, `chunk_z`.
+    pub const fn from_chunk(chunk_x: i32, chunk_z: i32) -> Self {
+        Self::new(chunk_x >> 5, chunk_z >> 5)
+    }
+
+    /// Returns the name of the region file, `r.<x>.<z>.mca`.
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.mca", self.x, self.z)
+    }
+
+    /// Parses the coordinates out of a region file name, `r.<x>.<z>.mca`.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let coords = name.strip_prefix("r.")?.strip_suffix(".mca")?;
+        let (x, z) = coords.split_once('.')?;
+        Some(Self::new(x.parse().ok()?, z.parse().ok()?))
+    }
+}
+
+struct OpenRegion {
+    region: Region<File>,
+    last_used: u64,
+}
+
+/// The region files of one folder, opened on demand.
+pub struct RegionCache {
+    dir: PathBuf,
+    max_open: usize,
+    compression: CompressionType,
+    custom_compression: Option<Arc<dyn CustomCompression>>,
+    regions: HashMap<RegionPos, OpenRegion>,
+    clock: u64,
+}
+
+impl RegionCache {
+    /// Creates a cache of the region files in `dir`. Nothing is opened, and
+    /// the folder is only created once a chunk is written.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_open: DEFAULT_MAX_OPEN_REGIONS,
+            compression: CompressionType::default(),
+            custom_compression: None,
+            regions: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Sets the number of regions kept open at once, at least one.
+    pub fn with_max_open(mut self, max_open: usize) -> Self {
+        self.max_open = max_open.max(1);
+        self
+    }
+
+    /// Sets the compression of the regions opened by this cache, see
+    /// [`Region::with_compression`].
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the custom compression of the regions opened by this cache, see
+    /// [`Region::with_custom_compression`].
+    pub fn with_custom_compression(mut self, custom: Arc<dyn CustomCompression>) -> Self {
+        self.custom_compression = Some(custom);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the number of currently open regions.
+    pub fn open_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Returns the region at `pos`, opening it if needed.
+    ///
+    /// With `create` unset, returns `None` instead of creating a region file
+    /// that doesn't exist yet.
+    pub fn region(&mut self, pos: RegionPos, create: bool) -> Result<Option<&mut Region<File>>> {
+        self.clock += 1;
+        if !self.regions.contains_key(&pos) {
+            let path = self.dir.join(pos.file_name());
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            if self.regions.len() >= self.max_open {
+                self.evict()?;
+            }
+            fs::create_dir_all(&self.dir)?;
+            let mut region = Region::open(path)?.with_compression(self.compression);
+            if let Some(custom) = &self.custom_compression {
+                region = region.with_custom_compression(custom.clone());
+            }
+            self.regions.insert(
+                pos,
+                OpenRegion {
+                    region,
+                    last_used: 0,
+                },
+            );
+        }
+
+        let open = self.regions.get_mut(&pos).unwrap();
+        open.last_used = self.clock;
+        Ok(Some(&mut open.region))
+    }
+
+    /// Reads the chunk at `x`, `z`, or returns `None` if it, or its region,
+    /// doesn't exist.
+    pub fn read_chunk<S>(&mut self, x: i32, z: i32) -> Result<Option<RawChunk<S>>>
+    where
+        S: for<'de> FromModifiedUtf8<'de> + Hash + Ord,
+    {
+        match self.region(RegionPos::from_chunk(x, z), false)? {
+            Some(region) => region.read_chunk(x, z),
+            None => Ok(None),
+        }
+    }
+
+    /// Writes the chunk at `x`, `z`, creating its region if needed.
+    pub fn write_chunk<S>(&mut self, x: i32, z: i32, data: &Compound<S>) -> Result<()>
+    where
+        S: ToModifiedUtf8 + Hash + Ord,
+    {
+        self.region(RegionPos::from_chunk(x, z), true)?
+            .expect("region is created")
+            .write_chunk(x, z, data)
+    }
+
+    /// Deletes the chunk at `x`, `z`, if it exists.
+    pub fn delete_chunk(&mut self, x: i32, z: i32) -> Result<()> {
+        match self.region(RegionPos::from_chunk(x, z), false)? {
+            Some(region) => region.delete_chunk(x, z),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes all open regions to the disk, keeping them open.
+    pub fn flush(&mut self) -> Result<()> {
+        for open in self.regions.values_mut() {
+            open.region.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes all open regions.
+    pub fn close(&mut self) -> Result<()> {
+        self.flush()?;
+        self.regions.clear();
+        Ok(())
+    }
+
+    /// Flushes and closes the least recently used region.
+    fn evict(&mut self) -> Result<()> {
+        let Some(pos) = self
+            .regions
+            .iter()
+            .min_by_key(|(_, open)| open.last_used)
+            .map(|(pos, _)| *pos)
+        else {
+            return Ok(());
+        };
+        let mut open = self.regions.remove(&pos).unwrap();
+        open.region.sync()
+    }
+}
+
+/// The region caches of a dimension folder, such as the world folder for
+/// the overworld or `DIM-1` for the nether.
+pub struct WorldRegions {
+    /// Block data in `region/`.
+    pub chunks: RegionCache,
+    /// Entities in `entities/`.
+    pub entities: RegionCache,
+    /// Points of interest in `poi/`.
+    pub poi: RegionCache,
+}
+
+impl WorldRegions {
+    pub fn new(dimension_dir: impl AsRef<Path>) -> Self {
+        let dir = dimension_dir.as_ref();
+        Self {
+            chunks: RegionCache::new(dir.join("region")),
+            entities: RegionCache::new(dir.join("entities")),
+            poi: RegionCache::new(dir.join("poi")),
+        }
+    }
+
+    /// Sets the number of regions each of the caches keeps open.
+    pub fn with_max_open(self, max_open: usize) -> Self {
+        Self {
+            chunks: self.chunks.with_max_open(max_open),
+            entities: self.entities.with_max_open(max_open),
+            poi: self.poi.with_max_open(max_open),
+        }
+    }
+
+    /// Sets the compression each of the caches writes chunks with, see
+    /// [`RegionCache::with_compression`].
+    pub fn with_compression(self, compression: CompressionType) -> Self {
+        Self {
+            chunks: self.chunks.with_compression(compression),
+            entities: self.entities.with_compression(compression),
+            poi: self.poi.with_compression(compression),
+        }
+    }
+
+    /// Sets the custom compression of each of the caches, see
+    /// [`RegionCache::with_custom_compression`].
+    pub fn with_custom_compression(self, custom: Arc<dyn CustomCompression>) -> Self {
+        Self {
+            chunks: self.chunks.with_custom_compression(custom.clone()),
+            entities: self.entities.with_custom_compression(custom.clone()),
+            poi: self.poi.with_custom_compression(custom),
+        }
+    }
+
+    /// Flushes the open regions of all caches.
+    pub fn flush(&mut self) -> Result<()> {
+        self.chunks.flush()?;
+        self.entities.flush()?;
+        self.poi.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cellophanemc_nbt::{Compound, Value};
+
+    use crate::cache::{RegionCache, RegionPos, WorldRegions};
+    use crate::region::{CompressionType, HEADER_SECTORS, SECTOR_SIZE};
+
+    fn chunk(x: i32, z: i32) -> Compound {
+        let mut chunk = Compound::new();
+        chunk.insert("xPos", x);
+        chunk.insert("zPos", z);
+        chunk
+    }
+
+    #[test]
+    fn region_pos() {
+        assert_eq!(RegionPos::from_chunk(31, 32), RegionPos::new(0, 1));
+        assert_eq!(RegionPos::from_chunk(-1, -33), RegionPos::new(-1, -2));
+        assert_eq!(RegionPos::new(-1, 2).file_name(), "r.-1.2.mca");
+        assert_eq!(RegionPos::from_file_name("r.-1.2.mca"), Some(RegionPos::new(-1, 2)));
+        assert_eq!(RegionPos::from_file_name("r.1.mca"), None);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = RegionCache::new(dir.path().join("region")).with_max_open(2);
+
+        assert!(cache.read_chunk::<String>(0, 0).unwrap().is_none());
+        assert!(!cache.dir().exists());
+
+        let chunks = [(0, 0), (40, 0), (0, 40), (-40, -40)];
+        for (x, z) in chunks {
+            cache.write_chunk(x, z, &chunk(x, z)).unwrap();
+            assert!(cache.open_count() <= 2);
+        }
+        assert!(cache.region(RegionPos::new(0, 1), false).unwrap().is_some());
+        assert!(cache.regions.contains_key(&RegionPos::new(-2, -2)));
+
+        for (x, z) in chunks {
+            let read = cache.read_chunk::<String>(x, z).unwrap().unwrap();
+            assert_eq!(read.data.get("xPos"), Some(&Value::Int(x)));
+            assert_eq!(read.data.get("zPos"), Some(&Value::Int(z)));
+        }
+        assert_eq!(cache.open_count(), 2);
+
+        cache.delete_chunk(40, 0).unwrap();
+        cache.close().unwrap();
+        assert_eq!(cache.open_count(), 0);
+        assert!(cache.read_chunk::<String>(40, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn world_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut regions = WorldRegions::new(dir.path()).with_max_open(1);
+        regions.chunks.write_chunk(0, 0, &chunk(0, 0)).unwrap();
+        regions.entities.write_chunk(0, 0, &chunk(1, 1)).unwrap();
+        regions.flush().unwrap();
+
+        assert!(dir.path().join("region/r.0.0.mca").exists());
+        assert!(dir.path().join("entities/r.0.0.mca").exists());
+        assert!(!dir.path().join("poi").exists());
+    }
+
+    #[test]
+    fn world_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut regions = WorldRegions::new(dir.path()).with_compression(CompressionType::Lz4);
+        regions.chunks.write_chunk(0, 0, &chunk(0, 0)).unwrap();
+        regions.flush().unwrap();
+
+        // The only chunk directly follows the header, behind its length.
+        let data = std::fs::read(dir.path().join("region/r.0.0.mca")).unwrap();
+        let offset = HEADER_SECTORS * SECTOR_SIZE + 4;
+        assert_eq!(data[offset], CompressionType::Lz4 as u8);
+
+        let read = regions.chunks.read_chunk::<String>(0, 0).unwrap().unwrap();
+        assert_eq!(read.data, chunk(0, 0));
+    }
+}
@@ -1,8 +1,46 @@
-use serde_derive::Deserialize;
+//! NBT layout of the chunks stored in the three region folders of a
+//! dimension: block data in `region/`, entities in `entities/` and points of
+//! interest in `poi/`.
+//!
+//! Every field is optional or has a default when missing, so proto-chunks of
+//! any generation [`ChunkStatus`] can be read. Top-level keys that aren't
+//! modeled are kept in the `extra` field of each chunk by its `from_nbt` and
+//! written back by its `to_nbt`.
 
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use cellophanemc_nbt::serde::ser::CompoundSerializer;
 use cellophanemc_nbt::Compound;
 
-#[derive(Debug, Deserialize)]
+use crate::error::Result;
+
+/// Reads a `T` from `data`, returning it with the keys it didn't read.
+fn split_nbt<T>(mut data: Compound) -> Result<(T, Compound)>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let value = T::deserialize(data.clone())?;
+    // `#[serde(flatten)]` can't be used, as it reads arrays as lists.
+    for key in value.serialize(CompoundSerializer)?.keys() {
+        data.remove(key);
+    }
+    Ok((value, data))
+}
+
+/// Writes `value`, followed by the `extra` keys it doesn't write itself.
+fn merge_nbt<T: Serialize>(value: &T, extra: &Compound) -> Result<Compound> {
+    let mut data = value.serialize(CompoundSerializer)?;
+    for (key, value) in extra {
+        data.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+    Ok(data)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Chunk {
     /// Version of the chunk NBT structure.
     #[serde(rename = "DataVersion")]
@@ -19,7 +57,7 @@ pub struct Chunk {
     /// Defines the world generation status of this chunk.
     /// All status except `minecraft:full` are used for chunks called proto-chunks, in other words, for chunks with incomplete generation.
     #[serde(rename = "Status")]
-    pub status: String,
+    pub status: ChunkStatus,
     /// Tick when the chunk was last saved.
     #[serde(rename = "LastUpdate")]
     pub last_update: i64,
@@ -28,13 +66,13 @@ pub struct Chunk {
     /// even those who are empty (filled with air).
     pub sections: Vec<Section>,
     /// Each TAG_Compound in this list defines a block entity in the chunk.
-    pub block_entities: Option<Vec<Compound>>,
+    pub block_entities: Vec<Compound>,
     /// Several different heightmaps corresponding to 256 values compacted at 9 bits per value
     /// (lowest being 0, highest being 384, both values inclusive).
     /// The 9 bit values are stored in an array of 37 longs, each containing 7 values
     /// (long = 64 bits, 7×9 = 63; the last bit is unused).
     #[serde(rename = "Heightmaps")]
-    pub heightmaps: Option<Heightmaps>,
+    pub heightmaps: Heightmaps,
     /// List is an "active" liquid in this chunk waiting to be updated.
     pub fluid_ticks: Vec<SavedTick>,
     /// List is an "active" block in this chunk waiting to be updated.
@@ -42,13 +80,84 @@ pub struct Chunk {
     /// The cumulative number of ticks players have been in this chunk.
     #[serde(rename = "InhabitedTime")]
     pub inhabited_time: i64,
+    /// Whether the light of the chunk has been computed and can be trusted.
+    #[serde(rename = "isLightOn", skip_serializing_if = "Option::is_none")]
+    pub is_light_on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub blending_data: Option<BlendingData>,
+    /// Set on chunks generated before the world went below Y 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub below_zero_retrogen: Option<BelowZeroRetrogen>,
+    /// Block updates needed by chunks from before 1.13.
+    #[serde(rename = "UpgradeData", skip_serializing_if = "Option::is_none")]
+    pub upgrade_data: Option<Compound>,
+    /// Blocks of each section scheduled for a shape update, one list per section.
     #[serde(rename = "PostProcessing")]
     pub post_processing: Vec<Vec<i16>>,
     pub structures: Structures,
+    /// Proto-chunks only: entities spawned during generation.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<Compound>,
+    /// Proto-chunks only: the blocks carved by air and liquid carvers.
+    #[serde(rename = "CarvingMasks", skip_serializing_if = "Option::is_none")]
+    pub carving_masks: Option<Compound>,
+    /// Keys this struct doesn't model, such as `ToBeTicked` of old chunks or
+    /// those of mods, which [`from_nbt`](Self::from_nbt) keeps and
+    /// [`to_nbt`](Self::to_nbt) writes back unchanged.
+    #[serde(skip)]
+    pub extra: Compound,
 }
 
-#[derive(Debug, Deserialize)]
+impl Chunk {
+    pub fn from_nbt(data: Compound) -> Result<Self> {
+        let (mut chunk, extra) = split_nbt::<Self>(data)?;
+        chunk.extra = extra;
+        Ok(chunk)
+    }
+
+    pub fn to_nbt(&self) -> Result<Compound> {
+        merge_nbt(self, &self.extra)
+    }
+}
+
+/// Generation steps of a chunk, in order.
+#[derive(
+    Debug, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+pub enum ChunkStatus {
+    #[default]
+    #[serde(rename = "minecraft:empty", alias = "empty")]
+    Empty,
+    #[serde(rename = "minecraft:structure_starts", alias = "structure_starts")]
+    StructureStarts,
+    #[serde(
+        rename = "minecraft:structure_references",
+        alias = "structure_references"
+    )]
+    StructureReferences,
+    #[serde(rename = "minecraft:biomes", alias = "biomes")]
+    Biomes,
+    #[serde(rename = "minecraft:noise", alias = "noise")]
+    Noise,
+    #[serde(rename = "minecraft:surface", alias = "surface")]
+    Surface,
+    #[serde(rename = "minecraft:carvers", alias = "carvers")]
+    Carvers,
+    #[serde(rename = "minecraft:liquid_carvers", alias = "liquid_carvers")]
+    LiquidCarvers,
+    #[serde(rename = "minecraft:features", alias = "features")]
+    Features,
+    #[serde(rename = "minecraft:initialize_light", alias = "initialize_light")]
+    InitializeLight,
+    #[serde(rename = "minecraft:light", alias = "light")]
+    Light,
+    #[serde(rename = "minecraft:spawn", alias = "spawn")]
+    Spawn,
+    #[serde(rename = "minecraft:full", alias = "full")]
+    Full,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SavedTick {
     #[serde(rename = "i")]
     pub id: String,
@@ -61,47 +170,331 @@ pub struct SavedTick {
     pub delay: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Section {
     #[serde(rename = "Y")]
     pub y: i8,
-    #[serde(rename = "block_states")]
-    pub block_states: PackedPalettedContainer<Compound>,
-    #[serde(rename = "biomes")]
-    pub biomes: PackedPalettedContainer<String>,
-    #[serde(rename = "SkyLight")]
+    /// Missing on sections that only carry light, above and below the world.
+    #[serde(rename = "block_states", skip_serializing_if = "Option::is_none")]
+    pub block_states: Option<PackedPalettedContainer<Compound>>,
+    #[serde(rename = "biomes", skip_serializing_if = "Option::is_none")]
+    pub biomes: Option<PackedPalettedContainer<String>>,
+    #[serde(
+        rename = "SkyLight",
+        with = "cellophanemc_nbt::serde::byte_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub sky_light: Option<Vec<i8>>,
-    #[serde(rename = "BlockLight")]
+    #[serde(
+        rename = "BlockLight",
+        with = "cellophanemc_nbt::serde::byte_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub block_light: Option<Vec<i8>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PackedPalettedContainer<T> {
     pub palette: Vec<T>,
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub data: Option<Vec<i64>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct Heightmaps {
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub motion_blocking: Option<Vec<i64>>,
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub motion_blocking_no_leaves: Option<Vec<i64>>,
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub ocean_floor: Option<Vec<i64>>,
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub ocean_floor_wg: Option<Vec<i64>>,
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub world_surface: Option<Vec<i64>>,
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub world_surface_wg: Option<Vec<i64>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Structures {
     #[serde(rename = "References")]
     pub references: Compound,
     pub starts: Compound,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct BlendingData {
     pub min_section: i32,
     pub max_section: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub heights: Option<Vec<f64>>,
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BelowZeroRetrogen {
+    pub target_status: ChunkStatus,
+    /// One bit per column of the chunk without bedrock at its bottom.
+    #[serde(
+        with = "cellophanemc_nbt::serde::long_array",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub missing_bedrock: Option<Vec<i64>>,
+}
+
+/// A chunk of the `entities/` folder.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntityChunk {
+    #[serde(rename = "DataVersion")]
+    pub data_version: i32,
+    /// X and Z position of the chunk.
+    #[serde(rename = "Position", with = "cellophanemc_nbt::serde::int_array")]
+    pub position: [i32; 2],
+    #[serde(rename = "Entities")]
+    pub entities: Vec<Compound>,
+    /// Keys this struct doesn't model, which [`from_nbt`](Self::from_nbt)
+    /// keeps and [`to_nbt`](Self::to_nbt) writes back unchanged.
+    #[serde(skip)]
+    pub extra: Compound,
+}
+
+impl EntityChunk {
+    pub fn from_nbt(data: Compound) -> Result<Self> {
+        let (mut chunk, extra) = split_nbt::<Self>(data)?;
+        chunk.extra = extra;
+        Ok(chunk)
+    }
+
+    pub fn to_nbt(&self) -> Result<Compound> {
+        merge_nbt(self, &self.extra)
+    }
+}
+
+/// A chunk of the `poi/` folder.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoiChunk {
+    #[serde(rename = "DataVersion")]
+    pub data_version: i32,
+    /// Sections keyed by their Y position, as a string.
+    #[serde(rename = "Sections")]
+    pub sections: BTreeMap<String, PoiSection>,
+    /// Keys this struct doesn't model, which [`from_nbt`](Self::from_nbt)
+    /// keeps and [`to_nbt`](Self::to_nbt) writes back unchanged.
+    #[serde(skip)]
+    pub extra: Compound,
+}
+
+impl PoiChunk {
+    pub fn from_nbt(data: Compound) -> Result<Self> {
+        let (mut chunk, extra) = split_nbt::<Self>(data)?;
+        chunk.extra = extra;
+        Ok(chunk)
+    }
+
+    pub fn to_nbt(&self) -> Result<Compound> {
+        merge_nbt(self, &self.extra)
+    }
+
+    pub fn section(&self, y: i32) -> Option<&PoiSection> {
+        self.sections.get(&y.to_string())
+    }
+
+    pub fn section_mut(&mut self, y: i32) -> &mut PoiSection {
+        self.sections.entry(y.to_string()).or_default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoiSection {
+    /// Unset when the records have to be rebuilt from the blocks.
+    #[serde(rename = "Valid")]
+    pub valid: bool,
+    #[serde(rename = "Records")]
+    pub records: Vec<PoiRecord>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoiRecord {
+    /// Point of interest type, such as `minecraft:home`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(with = "cellophanemc_nbt::serde::int_array")]
+    pub pos: [i32; 3],
+    /// Number of villagers that can still claim this point.
+    pub free_tickets: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use cellophanemc_nbt::serde::ser::CompoundSerializer;
+    use cellophanemc_nbt::snbt::from_snbt_str;
+    use cellophanemc_nbt::{Compound, Tag, Value};
+
+    use crate::chunk::{Chunk, ChunkStatus, EntityChunk, PoiChunk};
+
+    fn compound(snbt: &str) -> Compound {
+        match from_snbt_str(snbt).unwrap() {
+            Value::Compound(compound) => compound,
+            value => panic!("not a compound: {value}"),
+        }
+    }
+
+    /// Deserializes `snbt` as a `T`, and checks it serializes back unchanged.
+    fn round_trip<T>(snbt: &str) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        let nbt = compound(snbt);
+        let value = T::deserialize(nbt.clone()).unwrap();
+        assert_eq!(value.serialize(CompoundSerializer).unwrap(), nbt);
+        value
+    }
+
+    #[test]
+    fn full_chunk() {
+        let chunk: Chunk = round_trip(
+            r#"{DataVersion: 3700, xPos: -3, zPos: 7, yPos: -4, Status: "minecraft:full",
+                LastUpdate: 1200L, InhabitedTime: 40L, isLightOn: 1b,
+                sections: [
+                    {Y: -5b, SkyLight: [B; 1b, 2b]},
+                    {Y: -4b, block_states: {palette: [{Name: "minecraft:air"}]},
+                        biomes: {palette: ["minecraft:plains", "minecraft:forest"], data: [L; 6L]},
+                        BlockLight: [B; 3b]}
+                ],
+                block_entities: [{id: "minecraft:chest", x: -48, y: 60, z: 112, keepPacked: 0b}],
+                Heightmaps: {MOTION_BLOCKING: [L; 1L, 2L], WORLD_SURFACE: [L; 3L]},
+                fluid_ticks: [],
+                block_ticks: [{i: "minecraft:sand", x: -47, y: 61, z: 113, p: 0, t: 2}],
+                PostProcessing: [[], [1s, 2s]],
+                structures: {References: {"minecraft:village_plains": [L; 42L]},
+                    starts: {"minecraft:village_plains": {id: "INVALID"}}}}"#,
+        );
+        assert_eq!(chunk.status, ChunkStatus::Full);
+        assert_eq!(chunk.sections[0].sky_light.as_deref(), Some(&[1, 2][..]));
+        assert!(chunk.sections[0].block_states.is_none());
+        assert_eq!(chunk.block_ticks[0].delay, 2);
+    }
+
+    #[test]
+    fn proto_chunks() {
+        let chunk = Chunk::deserialize(compound(
+            r#"{DataVersion: 3700, xPos: 1, zPos: 2, Status: "minecraft:structure_starts"}"#,
+        ))
+        .unwrap();
+        assert_eq!(chunk.status, ChunkStatus::StructureStarts);
+        assert!(chunk.sections.is_empty());
+        assert!(chunk.blending_data.is_none());
+
+        let chunk: Chunk = round_trip(
+            r#"{DataVersion: 3700, xPos: 1, zPos: 2, yPos: -4, Status: "minecraft:features",
+                LastUpdate: 0L, InhabitedTime: 0L, sections: [], block_entities: [],
+                Heightmaps: {OCEAN_FLOOR_WG: [L; 5L]}, fluid_ticks: [], block_ticks: [],
+                PostProcessing: [], structures: {References: {}, starts: {}},
+                blending_data: {min_section: -4, max_section: 20, heights: [1.0d, 2.5d]},
+                below_zero_retrogen: {target_status: "minecraft:spawn", missing_bedrock: [L; 7L]},
+                entities: [{id: "minecraft:pig", UUID: [I; 1, 2, 3, 4]}],
+                CarvingMasks: {AIR: [L; 1L]}}"#,
+        );
+        assert!(chunk.status < ChunkStatus::Full);
+        assert_eq!(
+            chunk.below_zero_retrogen.unwrap().target_status,
+            ChunkStatus::Spawn
+        );
+
+        let chunk = Chunk::deserialize(compound(r#"{Status: "full"}"#)).unwrap();
+        assert_eq!(chunk.status, ChunkStatus::Full);
+    }
+
+    #[test]
+    fn entity_and_poi_chunks() {
+        let entities: EntityChunk = round_trip(
+            r#"{DataVersion: 3700, Position: [I; 4, -2],
+                Entities: [{id: "minecraft:cow", UUID: [I; 5, 6, 7, 8], Pos: [64.5d, 70.0d, -31.5d]}]}"#,
+        );
+        assert_eq!(entities.position, [4, -2]);
+        assert_eq!(
+            entities.entities[0].get("UUID"),
+            Some(&Value::IntArray(vec![5, 6, 7, 8]))
+        );
+
+        let mut poi: PoiChunk = round_trip(
+            r#"{DataVersion: 3700, Sections: {"-1": {Valid: 1b, Records: [
+                {type: "minecraft:home", pos: [I; 1, -10, 3], free_tickets: 1}]}}}"#,
+        );
+        assert_eq!(poi.section(-1).unwrap().records[0].pos, [1, -10, 3]);
+        assert!(poi.section(0).is_none());
+        poi.section_mut(0).valid = true;
+        assert!(poi.section(0).unwrap().valid);
+    }
+
+    #[test]
+    fn unknown_keys_are_kept() {
+        let nbt = compound(
+            r#"{DataVersion: 3700, xPos: 1, zPos: 2, yPos: -4, Status: "minecraft:full",
+                LastUpdate: 0L, InhabitedTime: 0L, sections: [], block_entities: [],
+                Heightmaps: {}, fluid_ticks: [], block_ticks: [], PostProcessing: [],
+                structures: {References: {}, starts: {}},
+                ToBeTicked: [[], [5s]], "mod:data": {ids: [I; 1, 2], bits: [L; 3L]}}"#,
+        );
+        let chunk = Chunk::from_nbt(nbt.clone()).unwrap();
+        assert_eq!(chunk.extra.len(), 2);
+        assert_eq!(
+            chunk.extra.get("ToBeTicked").map(Value::tag),
+            Some(Tag::List)
+        );
+        assert_eq!(chunk.to_nbt().unwrap(), nbt);
+
+        let nbt = compound(
+            r#"{DataVersion: 3700, Position: [I; 0, 0], Entities: [], "mod:seen": [B; 1b]}"#,
+        );
+        let entities = EntityChunk::from_nbt(nbt.clone()).unwrap();
+        assert_eq!(
+            entities.extra.get("mod:seen"),
+            Some(&Value::ByteArray(vec![1]))
+        );
+        assert_eq!(entities.to_nbt().unwrap(), nbt);
+
+        let nbt = compound(r#"{DataVersion: 3700, Sections: {}, "mod:marks": [L; 9L]}"#);
+        let poi = PoiChunk::from_nbt(nbt.clone()).unwrap();
+        assert_eq!(poi.extra.len(), 1);
+        assert_eq!(poi.to_nbt().unwrap(), nbt);
+    }
+}
@@ -6,6 +6,30 @@ pub enum Error {
     UnknownCompression(u8),
     #[error("Invalid chunk header size: {0}")]
     InvalidChunkHeaderSize(usize),
+    #[error("Chunk location points into the region header at sector {0}")]
+    ChunkInHeader(u64),
+    #[error("Chunk of {0} bytes does not fit in a region file")]
+    ChunkTooLarge(usize),
+    #[error("Chunk is stored in an external file, but the region has no external directory")]
+    MissingExternalDir,
+    #[error("Custom compression is used, but the region has no custom compression algorithm")]
+    MissingCustomCompression,
+    #[error("Unknown custom compression algorithm: {0}")]
+    UnknownCustomCompression(String),
+    #[error("Region header is truncated to {0} bytes")]
+    InvalidRegionHeader(usize),
+    #[error("Region file changed since it was scanned: {0}")]
+    RegionChanged(std::path::PathBuf),
+    #[error("Not a region file name: {0}")]
+    InvalidRegionName(std::path::PathBuf),
+    #[error("Data has no DataVersion")]
+    MissingDataVersion,
+    #[error("Data version {0} can't be upgraded")]
+    UnsupportedDataVersion(i32),
+    #[error("level.dat has no Data compound")]
+    MissingLevelData,
+    #[error("Invalid LZ4 stream: {0}")]
+    InvalidLz4(&'static str),
     #[error("Failed to parse NBT: {0}")]
     Nbt(#[from] cellophanemc_nbt::error::Error),
 }
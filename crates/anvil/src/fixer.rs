@@ -0,0 +1,836 @@
+//! Upgrading chunk NBT saved by older game versions.
+//!
+//! A [`DataFixer`] holds [`DataFix`]es sorted by the data version they
+//! upgrade to. [`DataFixer::upgrade`] applies, in order, every fix newer than
+//! the `DataVersion` of a chunk, so the chunk can then be deserialized as a
+//! [`Chunk`](crate::chunk::Chunk) of the current layout.
+
+use std::collections::{BTreeMap, HashMap};
+
+use cellophanemc_nbt::{Compound, List, Value};
+
+use crate::error::{Error, Result};
+use crate::DATA_VERSION;
+
+/// Oldest data version that can be upgraded, 17w47a, where block states
+/// replaced numeric block ids.
+pub const MIN_DATA_VERSION: i32 = 1451;
+
+/// Block states and heightmaps are packed so values no longer span two longs.
+const ALIGNED_BIT_STORAGE: i32 = 2527;
+/// Biomes move into the sections, and the overworld grows to Y -64..320.
+const SECTION_BIOMES: i32 = 2832;
+/// Biomes of the 1.18 terrain update are renamed.
+const BIOME_RENAMES: i32 = 2838;
+/// The `Level` compound is flattened into the root of the chunk.
+const FLAT_CHUNK: i32 = 2842;
+/// `minecraft:grass` is renamed to `minecraft:short_grass`.
+const SHORT_GRASS: i32 = 3692;
+
+/// Biomes renamed by [`BIOME_RENAMES`], without their `minecraft:` prefix.
+const BIOME_RENAMES_1_18: [(&str, &str); 38] = [
+    ("badlands_plateau", "badlands"),
+    ("bamboo_jungle_hills", "bamboo_jungle"),
+    ("birch_forest_hills", "birch_forest"),
+    ("dark_forest_hills", "dark_forest"),
+    ("desert_hills", "desert"),
+    ("desert_lakes", "desert"),
+    ("giant_spruce_taiga_hills", "old_growth_spruce_taiga"),
+    ("giant_spruce_taiga", "old_growth_spruce_taiga"),
+    ("giant_tree_taiga_hills", "old_growth_pine_taiga"),
+    ("giant_tree_taiga", "old_growth_pine_taiga"),
+    ("gravelly_mountains", "windswept_gravelly_hills"),
+    ("jungle_edge", "sparse_jungle"),
+    ("jungle_hills", "jungle"),
+    ("modified_badlands_plateau", "badlands"),
+    ("modified_gravelly_mountains", "windswept_gravelly_hills"),
+    ("modified_jungle_edge", "sparse_jungle"),
+    ("modified_jungle", "jungle"),
+    ("modified_wooded_badlands_plateau", "wooded_badlands"),
+    ("mountain_edge", "windswept_hills"),
+    ("mountains", "windswept_hills"),
+    ("mushroom_field_shore", "mushroom_fields"),
+    ("shattered_savanna", "windswept_savanna"),
+    ("shattered_savanna_plateau", "windswept_savanna"),
+    ("snowy_mountains", "snowy_plains"),
+    ("snowy_taiga_hills", "snowy_taiga"),
+    ("snowy_taiga_mountains", "snowy_taiga"),
+    ("snowy_tundra", "snowy_plains"),
+    ("stone_shore", "stony_shore"),
+    ("swamp_hills", "swamp"),
+    ("taiga_hills", "taiga"),
+    ("taiga_mountains", "taiga"),
+    ("tall_birch_forest", "old_growth_birch_forest"),
+    ("tall_birch_hills", "old_growth_birch_forest"),
+    ("wooded_badlands_plateau", "wooded_badlands"),
+    ("wooded_hills", "forest"),
+    ("wooded_mountains", "windswept_forest"),
+    ("lofty_peaks", "jagged_peaks"),
+    ("snowcapped_peaks", "frozen_peaks"),
+];
+
+/// Upgrades data saved before [`version`](DataFix::version).
+pub trait DataFix: Send + Sync {
+    /// The data version that introduced the change this fix upgrades to.
+    fn version(&self) -> i32;
+
+    fn apply(&self, data: &mut Compound) -> Result<()>;
+}
+
+/// Fixes applied by data version, see the [module docs](self).
+pub struct DataFixer {
+    fixes: Vec<Box<dyn DataFix>>,
+    target: i32,
+}
+
+impl Default for DataFixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DataFixer {
+    /// Creates a fixer without fixes, upgrading to [`DATA_VERSION`].
+    pub fn new() -> Self {
+        Self {
+            fixes: Vec::new(),
+            target: DATA_VERSION,
+        }
+    }
+
+    /// Creates a fixer with the fixes for the chunks of `region/` folders.
+    ///
+    /// Chunks of `minecraft:overworld` are extended to the height of 1.18,
+    /// other dimensions keep theirs.
+    pub fn chunks(dimension: &str) -> Self {
+        Self::new()
+            .with_fix(AlignBitStorage)
+            .with_fix(SectionBiomes {
+                extend_height: dimension == "minecraft:overworld",
+            })
+            .with_fix(RenameBiomes::new(
+                BIOME_RENAMES,
+                BIOME_RENAMES_1_18
+                    .map(|(from, to)| (format!("minecraft:{from}"), format!("minecraft:{to}"))),
+            ))
+            .with_fix(FlattenChunk)
+            .with_fix(RenameBlocks::new(
+                SHORT_GRASS,
+                [("minecraft:grass", "minecraft:short_grass")],
+            ))
+            .with_fix(LegacyStatus)
+    }
+
+    /// Adds a fix, after the fixes of the same version already added.
+    pub fn with_fix(mut self, fix: impl DataFix + 'static) -> Self {
+        let index = self
+            .fixes
+            .partition_point(|other| other.version() <= fix.version());
+        self.fixes.insert(index, Box::new(fix));
+        self
+    }
+
+    /// Sets the data version to upgrade to, skipping the fixes after it.
+    pub fn with_target(mut self, target: i32) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target(&self) -> i32 {
+        self.target
+    }
+
+    /// Upgrades `data` to the target version, and returns whether it was
+    /// older.
+    ///
+    /// Data newer than the target, or older than [`MIN_DATA_VERSION`], can't
+    /// be upgraded.
+    pub fn upgrade(&self, data: &mut Compound) -> Result<bool> {
+        let version = data
+            .get("DataVersion")
+            .and_then(Value::as_i32)
+            .ok_or(Error::MissingDataVersion)?;
+        if version == self.target {
+            return Ok(false);
+        }
+        if version > self.target || version < MIN_DATA_VERSION {
+            return Err(Error::UnsupportedDataVersion(version));
+        }
+
+        for fix in &self.fixes {
+            if fix.version() > version && fix.version() <= self.target {
+                fix.apply(data)?;
+            }
+        }
+        data.insert("DataVersion", self.target);
+        Ok(true)
+    }
+}
+
+/// Renames the blocks of the block state palettes and scheduled ticks.
+pub struct RenameBlocks {
+    version: i32,
+    renames: HashMap<String, String>,
+}
+
+impl RenameBlocks {
+    pub fn new<I, K, V>(version: i32, renames: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            version,
+            renames: renames
+                .into_iter()
+                .map(|(from, to)| (from.into(), to.into()))
+                .collect(),
+        }
+    }
+}
+
+impl DataFix for RenameBlocks {
+    fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn apply(&self, data: &mut Compound) -> Result<()> {
+        let chunk = chunk_mut(data);
+        for section in compounds_mut(chunk, &["sections", "Sections"]) {
+            let palette = match section.get_mut("block_states") {
+                Some(Value::Compound(states)) => states.get_mut("palette"),
+                _ => section.get_mut("Palette"),
+            };
+            if let Some(Value::List(List::Compound(palette))) = palette {
+                for state in palette {
+                    rename(state.get_mut("Name"), &self.renames);
+                }
+            }
+        }
+        for tick in compounds_mut(chunk, &["block_ticks", "TileTicks"]) {
+            rename(tick.get_mut("i"), &self.renames);
+        }
+        Ok(())
+    }
+}
+
+/// Renames the biomes of the section palettes.
+pub struct RenameBiomes {
+    version: i32,
+    renames: HashMap<String, String>,
+}
+
+impl RenameBiomes {
+    pub fn new<I, K, V>(version: i32, renames: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            version,
+            renames: renames
+                .into_iter()
+                .map(|(from, to)| (from.into(), to.into()))
+                .collect(),
+        }
+    }
+}
+
+impl DataFix for RenameBiomes {
+    fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn apply(&self, data: &mut Compound) -> Result<()> {
+        for section in compounds_mut(chunk_mut(data), &["sections", "Sections"]) {
+            let Some(Value::Compound(biomes)) = section.get_mut("biomes") else {
+                continue;
+            };
+            if let Some(Value::List(List::String(palette))) = biomes.get_mut("palette") {
+                for biome in palette {
+                    if let Some(to) = self.renames.get(biome) {
+                        *biome = to.clone();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Repacks the block states of the sections, and the heightmaps, so values
+/// don't span two longs.
+struct AlignBitStorage;
+
+impl DataFix for AlignBitStorage {
+    fn version(&self) -> i32 {
+        ALIGNED_BIT_STORAGE
+    }
+
+    fn apply(&self, data: &mut Compound) -> Result<()> {
+        let chunk = chunk_mut(data);
+        for section in compounds_mut(chunk, &["Sections"]) {
+            if let Some(Value::LongArray(states)) = section.get_mut("BlockStates") {
+                realign(states, 4096);
+            }
+        }
+        if let Some(Value::Compound(heightmaps)) = chunk.get_mut("Heightmaps") {
+            for heightmap in heightmaps.values_mut() {
+                if let Value::LongArray(heightmap) = heightmap {
+                    realign(heightmap, 256);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Moves the biomes of the chunk into its sections, next to the block
+/// states, and adds the sections missing from the height of the dimension.
+///
+/// With `extend_height`, the chunk grows from 16 to the 24 sections of the
+/// 1.18 overworld. The new sections are air, with the biomes of the closest
+/// old section, and generated chunks are marked for vanilla to generate the
+/// terrain below Y 0.
+struct SectionBiomes {
+    extend_height: bool,
+}
+
+impl DataFix for SectionBiomes {
+    fn version(&self) -> i32 {
+        SECTION_BIOMES
+    }
+
+    fn apply(&self, data: &mut Compound) -> Result<()> {
+        let Some(Value::Compound(level)) = data.get_mut("Level") else {
+            return Ok(());
+        };
+        let (min_y, max_y) = if self.extend_height {
+            (-4, 19)
+        } else {
+            (0, 15)
+        };
+        let old_biomes = match level.remove("Biomes") {
+            Some(Value::IntArray(biomes)) => biomes,
+            _ => Vec::new(),
+        };
+
+        let mut sections = BTreeMap::new();
+        if let Some(Value::List(List::Compound(old))) = level.remove("Sections") {
+            for section in old {
+                let y = section.get("Y").and_then(Value::as_i8).unwrap_or_default();
+                sections.insert(y, section);
+            }
+        }
+        for y in min_y..=max_y {
+            let section = sections.entry(y).or_insert_with(|| {
+                let mut section = Compound::new();
+                section.insert("Y", y);
+                section
+            });
+            let block_states = block_states(section);
+            section.insert("block_states", block_states);
+            section.insert("biomes", section_biomes(&old_biomes, y));
+        }
+        for section in sections.values_mut() {
+            section.remove("Palette");
+            section.remove("BlockStates");
+        }
+        let sections = sections.into_values().collect::<Vec<_>>();
+        level.insert("Sections", List::from(sections));
+        level.insert("yPos", min_y as i32);
+
+        if !self.extend_height {
+            return Ok(());
+        }
+        if let Some(Value::Compound(heightmaps)) = level.get_mut("Heightmaps") {
+            for heightmap in heightmaps.values_mut() {
+                if let Value::LongArray(heightmap) = heightmap {
+                    let heights = unpack(heightmap, 9, 256);
+                    *heightmap = pack(heights.iter().map(|height| height + 64), 9);
+                }
+            }
+        }
+        if let Some(Value::List(List::List(post_processing))) = level.get_mut("PostProcessing") {
+            post_processing.splice(0..0, [List::End, List::End, List::End, List::End]);
+        }
+        let status = match level.get("Status") {
+            Some(Value::String(status)) => status.clone(),
+            _ => String::new(),
+        };
+        if !matches!(status.as_str(), "" | "empty" | "minecraft:empty") {
+            let mut retrogen = Compound::new();
+            retrogen.insert("target_status", status);
+            level.insert("below_zero_retrogen", retrogen);
+        }
+        Ok(())
+    }
+}
+
+/// Moves the content of the `Level` compound to the root of the chunk,
+/// renaming the keys that changed along the way.
+struct FlattenChunk;
+
+impl DataFix for FlattenChunk {
+    fn version(&self) -> i32 {
+        FLAT_CHUNK
+    }
+
+    fn apply(&self, data: &mut Compound) -> Result<()> {
+        let Some(Value::Compound(level)) = data.remove("Level") else {
+            return Ok(());
+        };
+        for (key, mut value) in level {
+            let key = match key.as_str() {
+                "Sections" => "sections",
+                "TileEntities" => "block_entities",
+                "TileTicks" => "block_ticks",
+                "LiquidTicks" => "fluid_ticks",
+                "Entities" => "entities",
+                "Structures" => {
+                    if let Value::Compound(structures) = &mut value {
+                        if let Some(starts) = structures.remove("Starts") {
+                            structures.insert("starts", starts);
+                        }
+                    }
+                    "structures"
+                }
+                key => key,
+            };
+            data.insert(key, value);
+        }
+        Ok(())
+    }
+}
+
+/// Renames the generation statuses of older versions to the ones of
+/// [`ChunkStatus`](crate::chunk::ChunkStatus).
+///
+/// Runs on anything older than [`DATA_VERSION`], as the names are otherwise
+/// left as-is.
+struct LegacyStatus;
+
+impl DataFix for LegacyStatus {
+    fn version(&self) -> i32 {
+        DATA_VERSION
+    }
+
+    fn apply(&self, data: &mut Compound) -> Result<()> {
+        upgrade_status(data.get_mut("Status"));
+        if let Some(Value::Compound(retrogen)) = data.get_mut("below_zero_retrogen") {
+            upgrade_status(retrogen.get_mut("target_status"));
+        }
+        Ok(())
+    }
+}
+
+fn upgrade_status(status: Option<&mut Value>) {
+    let Some(Value::String(status)) = status else {
+        return;
+    };
+    let name = status.strip_prefix("minecraft:").unwrap_or(status);
+    let name = match name {
+        "base" => "surface",
+        "carved" => "carvers",
+        "liquid_carved" => "liquid_carvers",
+        "decorated" => "features",
+        "lighted" => "light",
+        "mobs_spawned" | "finalized" | "heightmaps" => "spawn",
+        "fullchunk" | "postprocessed" => "full",
+        name => name,
+    };
+    *status = format!("minecraft:{name}");
+}
+
+/// Returns the compound holding the chunk data, `Level` before
+/// [`FLAT_CHUNK`].
+fn chunk_mut(data: &mut Compound) -> &mut Compound {
+    if !matches!(data.get("Level"), Some(Value::Compound(_))) {
+        return data;
+    }
+    match data.get_mut("Level") {
+        Some(Value::Compound(level)) => level,
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the elements of the first compound list found at `keys`.
+fn compounds_mut<'a>(chunk: &'a mut Compound, keys: &[&str]) -> &'a mut [Compound] {
+    let Some(key) = keys
+        .iter()
+        .find(|key| matches!(chunk.get(**key), Some(Value::List(List::Compound(_)))))
+    else {
+        return &mut [];
+    };
+    match chunk.get_mut(*key) {
+        Some(Value::List(List::Compound(compounds))) => compounds,
+        _ => unreachable!(),
+    }
+}
+
+fn rename(name: Option<&mut Value>, renames: &HashMap<String, String>) {
+    if let Some(Value::String(name)) = name {
+        if let Some(to) = renames.get(name) {
+            *name = to.clone();
+        }
+    }
+}
+
+/// Moves the `Palette` and `BlockStates` of a section into a `block_states`
+/// compound, or returns a palette of air for sections without blocks.
+fn block_states(section: &mut Compound) -> Compound {
+    let mut block_states = Compound::new();
+    match section.remove("Palette") {
+        Some(Value::List(palette)) if !palette.is_empty() => {
+            let data = section.remove("BlockStates").filter(|_| palette.len() > 1);
+            block_states.insert("palette", palette);
+            if let Some(data) = data {
+                block_states.insert("data", data);
+            }
+        }
+        _ => {
+            let mut air = Compound::new();
+            air.insert("Name", "minecraft:air");
+            block_states.insert("palette", List::from(vec![air]));
+        }
+    }
+    block_states
+}
+
+/// Returns the biomes of section `y` out of the biomes of a whole chunk
+/// before [`SECTION_BIOMES`], 4×4×4 blocks each.
+///
+/// Sections out of the old height take the biomes of the closest section.
+fn section_biomes(old_biomes: &[i32], section_y: i8) -> Compound {
+    let mut palette = Vec::<String>::new();
+    let mut indices = Vec::with_capacity(64);
+    for index in 0..64 {
+        let layer = (section_y as i32 * 4 + (index >> 4)).clamp(0, 63) as usize;
+        let column = index as usize & 15;
+        let name = match old_biomes.len() {
+            // Before 19w36a, biomes were two-dimensional.
+            256 => old_biomes.get((column >> 2) * 64 + (column & 3) * 4),
+            _ => old_biomes.get(layer << 4 | column),
+        }
+        .map_or("minecraft:plains", |id| legacy_biome(*id));
+
+        let palette_index = match palette.iter().position(|biome| biome == name) {
+            Some(palette_index) => palette_index,
+            None => {
+                palette.push(name.to_string());
+                palette.len() - 1
+            }
+        };
+        indices.push(palette_index as u64);
+    }
+
+    let mut biomes = Compound::new();
+    if palette.len() > 1 {
+        let bits = usize::BITS - (palette.len() - 1).leading_zeros();
+        biomes.insert("data", pack(indices, bits as usize));
+    }
+    biomes.insert("palette", List::String(palette));
+    biomes
+}
+
+/// Repacks `data`, holding `len` values spanning longs, so they don't.
+fn realign(data: &mut Vec<i64>, len: usize) {
+    let bits = data.len() * 64 / len;
+    if bits == 0 || 64 % bits == 0 {
+        return;
+    }
+    let values = (0..len).map(|index| {
+        let start = index * bits;
+        let (long, offset) = (start / 64, start % 64);
+        let mut value = data[long] as u64 >> offset;
+        if offset + bits > 64 {
+            value |= (data[long + 1] as u64) << (64 - offset);
+        }
+        value & ((1 << bits) - 1)
+    });
+    *data = pack(values.collect::<Vec<_>>(), bits);
+}
+
+/// Unpacks `len` values of `bits` bits that don't span longs.
+fn unpack(data: &[i64], bits: usize, len: usize) -> Vec<u64> {
+    let per_long = 64 / bits;
+    (0..len)
+        .map(|index| {
+            let long = data.get(index / per_long).copied().unwrap_or_default() as u64;
+            (long >> (index % per_long * bits)) & ((1 << bits) - 1)
+        })
+        .collect()
+}
+
+/// Packs values of `bits` bits so they don't span longs.
+fn pack(values: impl IntoIterator<Item = u64>, bits: usize) -> Vec<i64> {
+    let per_long = 64 / bits;
+    let mut data = Vec::new();
+    for (index, value) in values.into_iter().enumerate() {
+        if index % per_long == 0 {
+            data.push(0);
+        }
+        *data.last_mut().unwrap() |= (value << (index % per_long * bits)) as i64;
+    }
+    data
+}
+
+/// Returns the name of a biome by its id before 1.18.
+fn legacy_biome(id: i32) -> &'static str {
+    match id {
+        0 => "minecraft:ocean",
+        2 => "minecraft:desert",
+        3 => "minecraft:mountains",
+        4 => "minecraft:forest",
+        5 => "minecraft:taiga",
+        6 => "minecraft:swamp",
+        7 => "minecraft:river",
+        8 => "minecraft:nether_wastes",
+        9 => "minecraft:the_end",
+        10 => "minecraft:frozen_ocean",
+        11 => "minecraft:frozen_river",
+        12 => "minecraft:snowy_tundra",
+        13 => "minecraft:snowy_mountains",
+        14 => "minecraft:mushroom_fields",
+        15 => "minecraft:mushroom_field_shore",
+        16 => "minecraft:beach",
+        17 => "minecraft:desert_hills",
+        18 => "minecraft:wooded_hills",
+        19 => "minecraft:taiga_hills",
+        20 => "minecraft:mountain_edge",
+        21 => "minecraft:jungle",
+        22 => "minecraft:jungle_hills",
+        23 => "minecraft:jungle_edge",
+        24 => "minecraft:deep_ocean",
+        25 => "minecraft:stone_shore",
+        26 => "minecraft:snowy_beach",
+        27 => "minecraft:birch_forest",
+        28 => "minecraft:birch_forest_hills",
+        29 => "minecraft:dark_forest",
+        30 => "minecraft:snowy_taiga",
+        31 => "minecraft:snowy_taiga_hills",
+        32 => "minecraft:giant_tree_taiga",
+        33 => "minecraft:giant_tree_taiga_hills",
+        34 => "minecraft:wooded_mountains",
+        35 => "minecraft:savanna",
+        36 => "minecraft:savanna_plateau",
+        37 => "minecraft:badlands",
+        38 => "minecraft:wooded_badlands_plateau",
+        39 => "minecraft:badlands_plateau",
+        40 => "minecraft:small_end_islands",
+        41 => "minecraft:end_midlands",
+        42 => "minecraft:end_highlands",
+        43 => "minecraft:end_barrens",
+        44 => "minecraft:warm_ocean",
+        45 => "minecraft:lukewarm_ocean",
+        46 => "minecraft:cold_ocean",
+        47 => "minecraft:deep_warm_ocean",
+        48 => "minecraft:deep_lukewarm_ocean",
+        49 => "minecraft:deep_cold_ocean",
+        50 => "minecraft:deep_frozen_ocean",
+        127 => "minecraft:the_void",
+        129 => "minecraft:sunflower_plains",
+        130 => "minecraft:desert_lakes",
+        131 => "minecraft:gravelly_mountains",
+        132 => "minecraft:flower_forest",
+        133 => "minecraft:taiga_mountains",
+        134 => "minecraft:swamp_hills",
+        140 => "minecraft:ice_spikes",
+        149 => "minecraft:modified_jungle",
+        151 => "minecraft:modified_jungle_edge",
+        155 => "minecraft:tall_birch_forest",
+        156 => "minecraft:tall_birch_hills",
+        157 => "minecraft:dark_forest_hills",
+        158 => "minecraft:snowy_taiga_mountains",
+        160 => "minecraft:giant_spruce_taiga",
+        161 => "minecraft:giant_spruce_taiga_hills",
+        162 => "minecraft:modified_gravelly_mountains",
+        163 => "minecraft:shattered_savanna",
+        164 => "minecraft:shattered_savanna_plateau",
+        165 => "minecraft:eroded_badlands",
+        166 => "minecraft:modified_wooded_badlands_plateau",
+        167 => "minecraft:modified_badlands_plateau",
+        168 => "minecraft:bamboo_jungle",
+        169 => "minecraft:bamboo_jungle_hills",
+        170 => "minecraft:soul_sand_valley",
+        171 => "minecraft:crimson_forest",
+        172 => "minecraft:warped_forest",
+        173 => "minecraft:basalt_deltas",
+        174 => "minecraft:dripstone_caves",
+        175 => "minecraft:lush_caves",
+        _ => "minecraft:plains",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use cellophanemc_nbt::snbt::from_snbt_str;
+    use cellophanemc_nbt::{Compound, List, Value};
+
+    use crate::chunk::{Chunk, ChunkStatus};
+    use crate::error::{Error, Result};
+    use crate::fixer::{pack, realign, unpack, DataFix, DataFixer, RenameBlocks};
+    use crate::DATA_VERSION;
+
+    fn compound(snbt: &str) -> Compound {
+        match from_snbt_str(snbt).unwrap() {
+            Value::Compound(compound) => compound,
+            value => panic!("not a compound: {value}"),
+        }
+    }
+
+    struct Append(i32);
+
+    impl DataFix for Append {
+        fn version(&self) -> i32 {
+            self.0
+        }
+
+        fn apply(&self, data: &mut Compound) -> Result<()> {
+            match data.get_mut("applied") {
+                Some(Value::List(List::Int(applied))) => applied.push(self.0),
+                _ => {
+                    data.insert("applied", List::Int(vec![self.0]));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn applies_newer_fixes_in_order() {
+        let fixer = DataFixer::new()
+            .with_fix(Append(3000))
+            .with_fix(Append(2000))
+            .with_fix(Append(3500))
+            .with_target(3500);
+
+        let mut data = compound("{DataVersion: 2500}");
+        assert!(fixer.upgrade(&mut data).unwrap());
+        assert_eq!(
+            data.get("applied"),
+            Some(&Value::List(List::Int(vec![3000, 3500])))
+        );
+        assert_eq!(data.get("DataVersion"), Some(&Value::Int(3500)));
+        assert!(!fixer.upgrade(&mut data).unwrap());
+
+        let res = fixer.upgrade(&mut compound("{DataVersion: 3600}"));
+        assert!(matches!(res, Err(Error::UnsupportedDataVersion(3600))));
+        let res = fixer.upgrade(&mut compound("{DataVersion: 1343}"));
+        assert!(matches!(res, Err(Error::UnsupportedDataVersion(1343))));
+        let res = fixer.upgrade(&mut Compound::new());
+        assert!(matches!(res, Err(Error::MissingDataVersion)));
+    }
+
+    #[test]
+    fn realigns_bit_storage() {
+        let values = (0..4096).map(|i| i * 7 % 17).collect::<Vec<u64>>();
+        let mut spanning = vec![0i64; 4096 * 5 / 64];
+        for (index, value) in values.iter().enumerate() {
+            let start = index * 5;
+            spanning[start / 64] |= (value << (start % 64)) as i64;
+            if start % 64 + 5 > 64 {
+                spanning[start / 64 + 1] |= (value >> (64 - start % 64)) as i64;
+            }
+        }
+
+        realign(&mut spanning, 4096);
+        assert_eq!(spanning, pack(values.iter().copied(), 5));
+        assert_eq!(unpack(&spanning, 5, 4096), values);
+    }
+
+    #[test]
+    fn upgrades_1_17_chunks() {
+        let biomes = (0..1024)
+            .map(|i| if i < 520 { "1" } else { "3" })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let heightmap = pack([70; 256], 9)
+            .iter()
+            .map(|long| format!("{long}L"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut data = compound(&format!(
+            r#"{{DataVersion: 2730, Level: {{xPos: 2, zPos: -1, Status: "full",
+                LastUpdate: 10L, InhabitedTime: 0L, Biomes: [I; {biomes}],
+                Heightmaps: {{WORLD_SURFACE: [L; {heightmap}]}},
+                Sections: [
+                    {{Y: -1b, SkyLight: [B; 1b]}},
+                    {{Y: 0b, Palette: [{{Name: "minecraft:bedrock"}}, {{Name: "minecraft:grass"}}],
+                        BlockStates: [L; {}], SkyLight: [B; 2b]}}
+                ],
+                TileEntities: [{{id: "minecraft:chest", x: 32, y: 5, z: -16}}],
+                TileTicks: [{{i: "minecraft:grass", x: 32, y: 6, z: -16, p: 0, t: 1}}],
+                LiquidTicks: [], PostProcessing: [[], [1s]],
+                Structures: {{References: {{}}, Starts: {{}}}}}}}}"#,
+            vec!["0L"; 256].join(", ")
+        ));
+
+        assert!(DataFixer::chunks("minecraft:overworld")
+            .upgrade(&mut data)
+            .unwrap());
+        let chunk = Chunk::deserialize(data).unwrap();
+
+        assert_eq!(chunk.data_version, DATA_VERSION);
+        assert_eq!((chunk.x_pos, chunk.z_pos, chunk.y_pos), (2, -1, -4));
+        assert_eq!(chunk.status, ChunkStatus::Full);
+        assert_eq!(
+            chunk.below_zero_retrogen.unwrap().target_status,
+            ChunkStatus::Full
+        );
+        assert_eq!(chunk.block_entities.len(), 1);
+        assert_eq!(chunk.block_ticks[0].id, "minecraft:short_grass");
+        assert_eq!(chunk.post_processing.len(), 6);
+        let heightmap = chunk.heightmaps.world_surface.unwrap();
+        assert_eq!(unpack(&heightmap, 9, 256), [134; 256]);
+
+        let sections = chunk.sections;
+        assert_eq!(sections.len(), 24);
+        assert_eq!(sections[0].y, -4);
+        let palette = |y: usize| sections[y].biomes.as_ref().unwrap().palette.clone();
+        assert_eq!(palette(0), ["minecraft:plains"]);
+        assert_eq!(palette(11), ["minecraft:plains"]);
+        assert_eq!(
+            palette(12),
+            ["minecraft:plains", "minecraft:windswept_hills"]
+        );
+        assert_eq!(palette(23), ["minecraft:windswept_hills"]);
+        assert_eq!(sections[3].sky_light.as_deref(), Some(&[1][..]));
+
+        let block_states = sections[4].block_states.as_ref().unwrap();
+        assert_eq!(
+            block_states.palette[1].get("Name"),
+            Some(&"minecraft:short_grass".into())
+        );
+        assert_eq!(block_states.data.as_ref().unwrap().len(), 256);
+        let block_states = sections[5].block_states.as_ref().unwrap();
+        assert_eq!(
+            block_states.palette[0].get("Name"),
+            Some(&"minecraft:air".into())
+        );
+        assert!(block_states.data.is_none());
+    }
+
+    #[test]
+    fn renames_blocks() {
+        let mut data = compound(
+            r#"{DataVersion: 3600, sections: [{Y: 0b, block_states: {palette: [
+                {Name: "minecraft:grass"}, {Name: "minecraft:stone"}]}}]}"#,
+        );
+        DataFixer::new()
+            .with_fix(RenameBlocks::new(
+                3650,
+                [("minecraft:stone", "minecraft:dirt")],
+            ))
+            .upgrade(&mut data)
+            .unwrap();
+        let chunk = Chunk::deserialize(data).unwrap();
+        let palette = &chunk.sections[0].block_states.as_ref().unwrap().palette;
+        assert_eq!(palette[0].get("Name"), Some(&"minecraft:grass".into()));
+        assert_eq!(palette[1].get("Name"), Some(&"minecraft:dirt".into()));
+    }
+}
@@ -0,0 +1,399 @@
+//! The `level.dat` file at the root of a world folder.
+//!
+//! It holds a single `Data` compound with the world-wide state: seed and
+//! generator settings, spawn point, time, weather, world border, game rules
+//! and enabled data packs. Fields missing from the file, as in worlds from
+//! older versions, take the values vanilla would create a world with, except
+//! for a missing `DataVersion`. Keys that aren't modeled are kept in
+//! [`LevelData::extra`].
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use cellophanemc_nbt::file::{read_file, write_file};
+use cellophanemc_nbt::serde::ser::CompoundSerializer;
+use cellophanemc_nbt::{Compound, Value};
+
+use crate::error::{Error, Result};
+use crate::DATA_VERSION;
+
+/// Version of the `level.dat` format, the `version` field.
+pub const ANVIL_VERSION: i32 = 19133;
+
+/// Data version of a `level.dat` without one, written before 1.9, the same
+/// as vanilla assumes.
+pub const NO_DATA_VERSION: i32 = -1;
+
+fn no_data_version() -> i32 {
+    NO_DATA_VERSION
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelData {
+    #[serde(rename = "LevelName")]
+    pub level_name: String,
+    /// [`NO_DATA_VERSION`] if the world predates data versions.
+    #[serde(rename = "DataVersion", default = "no_data_version")]
+    pub data_version: i32,
+    /// Always [`ANVIL_VERSION`].
+    #[serde(rename = "version")]
+    pub storage_version: i32,
+    /// The game version that last saved the world.
+    #[serde(rename = "Version", skip_serializing_if = "Option::is_none")]
+    pub version: Option<VersionInfo>,
+    #[serde(rename = "WorldGenSettings")]
+    pub world_gen_settings: WorldGenSettings,
+    #[serde(rename = "DataPacks")]
+    pub data_packs: DataPacks,
+    #[serde(rename = "GameRules")]
+    pub game_rules: BTreeMap<String, String>,
+    /// Server brands that have loaded the world, such as `vanilla`.
+    #[serde(rename = "ServerBrands")]
+    pub server_brands: Vec<String>,
+    #[serde(rename = "WasModded")]
+    pub was_modded: bool,
+    pub initialized: bool,
+    /// Unix time of the last save, in milliseconds.
+    #[serde(rename = "LastPlayed")]
+    pub last_played: i64,
+
+    #[serde(rename = "GameType")]
+    pub game_type: i32,
+    pub hardcore: bool,
+    /// 0 for peaceful up to 3 for hard.
+    #[serde(rename = "Difficulty")]
+    pub difficulty: i8,
+    #[serde(rename = "DifficultyLocked")]
+    pub difficulty_locked: bool,
+    #[serde(rename = "allowCommands")]
+    pub allow_commands: bool,
+
+    #[serde(rename = "SpawnX")]
+    pub spawn_x: i32,
+    #[serde(rename = "SpawnY")]
+    pub spawn_y: i32,
+    #[serde(rename = "SpawnZ")]
+    pub spawn_z: i32,
+    #[serde(rename = "SpawnAngle")]
+    pub spawn_angle: f32,
+
+    /// Ticks since the world was created.
+    #[serde(rename = "Time")]
+    pub time: i64,
+    /// Time of day, which commands and sleeping can change.
+    #[serde(rename = "DayTime")]
+    pub day_time: i64,
+    pub raining: bool,
+    #[serde(rename = "rainTime")]
+    pub rain_time: i32,
+    pub thundering: bool,
+    #[serde(rename = "thunderTime")]
+    pub thunder_time: i32,
+    /// Ticks of clear weather left after `/weather clear`.
+    #[serde(rename = "clearWeatherTime")]
+    pub clear_weather_time: i32,
+
+    #[serde(rename = "BorderCenterX")]
+    pub border_center_x: f64,
+    #[serde(rename = "BorderCenterZ")]
+    pub border_center_z: f64,
+    #[serde(rename = "BorderSize")]
+    pub border_size: f64,
+    #[serde(rename = "BorderSafeZone")]
+    pub border_safe_zone: f64,
+    #[serde(rename = "BorderDamagePerBlock")]
+    pub border_damage_per_block: f64,
+    #[serde(rename = "BorderWarningBlocks")]
+    pub border_warning_blocks: f64,
+    #[serde(rename = "BorderWarningTime")]
+    pub border_warning_time: f64,
+    #[serde(rename = "BorderSizeLerpTarget")]
+    pub border_size_lerp_target: f64,
+    #[serde(rename = "BorderSizeLerpTime")]
+    pub border_size_lerp_time: i64,
+
+    #[serde(
+        rename = "WanderingTraderId",
+        with = "cellophanemc_nbt::serde::int_array",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub wandering_trader_id: Option<Vec<i32>>,
+    #[serde(rename = "WanderingTraderSpawnChance")]
+    pub wandering_trader_spawn_chance: i32,
+    #[serde(rename = "WanderingTraderSpawnDelay")]
+    pub wandering_trader_spawn_delay: i32,
+
+    #[serde(rename = "ScheduledEvents")]
+    pub scheduled_events: Vec<Compound>,
+    #[serde(rename = "CustomBossEvents", skip_serializing_if = "Option::is_none")]
+    pub custom_boss_events: Option<Compound>,
+    #[serde(rename = "DragonFight", skip_serializing_if = "Option::is_none")]
+    pub dragon_fight: Option<Compound>,
+    /// The player of a singleplayer world.
+    #[serde(rename = "Player", skip_serializing_if = "Option::is_none")]
+    pub player: Option<Compound>,
+    /// Keys this struct doesn't model, such as those of mods, which
+    /// [`load`](Self::load) keeps and [`save`](Self::save) writes back
+    /// unchanged.
+    #[serde(skip)]
+    pub extra: Compound,
+}
+
+impl Default for LevelData {
+    fn default() -> Self {
+        Self {
+            level_name: "world".to_string(),
+            data_version: DATA_VERSION,
+            storage_version: ANVIL_VERSION,
+            version: Some(VersionInfo::default()),
+            world_gen_settings: WorldGenSettings::default(),
+            data_packs: DataPacks::default(),
+            game_rules: BTreeMap::new(),
+            server_brands: Vec::new(),
+            was_modded: false,
+            initialized: false,
+            last_played: 0,
+            game_type: 0,
+            hardcore: false,
+            difficulty: 2,
+            difficulty_locked: false,
+            allow_commands: false,
+            spawn_x: 0,
+            spawn_y: 0,
+            spawn_z: 0,
+            spawn_angle: 0.0,
+            time: 0,
+            day_time: 0,
+            raining: false,
+            rain_time: 0,
+            thundering: false,
+            thunder_time: 0,
+            clear_weather_time: 0,
+            border_center_x: 0.0,
+            border_center_z: 0.0,
+            border_size: 59_999_968.0,
+            border_safe_zone: 5.0,
+            border_damage_per_block: 0.2,
+            border_warning_blocks: 5.0,
+            border_warning_time: 15.0,
+            border_size_lerp_target: 59_999_968.0,
+            border_size_lerp_time: 0,
+            wandering_trader_id: None,
+            wandering_trader_spawn_chance: 25,
+            wandering_trader_spawn_delay: 24_000,
+            scheduled_events: Vec::new(),
+            custom_boss_events: None,
+            dragon_fight: None,
+            player: None,
+            extra: Compound::new(),
+        }
+    }
+}
+
+impl LevelData {
+    /// Reads the `level.dat` file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let (mut root, _) = read_file::<String>(path)?;
+        let Some(Value::Compound(mut data)) = root.remove("Data") else {
+            return Err(Error::MissingLevelData);
+        };
+        let mut level = Self::deserialize(data.clone())?;
+        // Every key that was read is written back, unlike unknown ones.
+        // `#[serde(flatten)]` can't be used, as it reads arrays as lists.
+        for key in level.serialize(CompoundSerializer)?.keys() {
+            data.remove(key);
+        }
+        level.extra = data;
+        Ok(level)
+    }
+
+    /// Saves this as the `level.dat` file at `path`, keeping the previous
+    /// file as `level.dat_old`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut data = self.serialize(CompoundSerializer)?;
+        for (key, value) in &self.extra {
+            data.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        let mut root = Compound::new();
+        root.insert("Data", data);
+        write_file(path, &root, "")?;
+        Ok(())
+    }
+
+    pub fn game_rule(&self, name: &str) -> Option<&str> {
+        self.game_rules.get(name).map(String::as_str)
+    }
+
+    pub fn set_game_rule(&mut self, name: impl Into<String>, value: impl ToString) {
+        self.game_rules.insert(name.into(), value.to_string());
+    }
+}
+
+/// The game version that last saved a world.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    /// The data version of the game.
+    #[serde(rename = "Id")]
+    pub id: i32,
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// `main` for releases, or the name of an experimental branch.
+    #[serde(rename = "Series")]
+    pub series: String,
+    #[serde(rename = "Snapshot")]
+    pub snapshot: bool,
+}
+
+impl Default for VersionInfo {
+    fn default() -> Self {
+        Self {
+            id: DATA_VERSION,
+            name: "1.20.4".to_string(),
+            series: "main".to_string(),
+            snapshot: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldGenSettings {
+    pub seed: i64,
+    pub generate_features: bool,
+    pub bonus_chest: bool,
+    /// Generator settings of every dimension, keyed by dimension id.
+    pub dimensions: Compound,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            generate_features: true,
+            bonus_chest: false,
+            dimensions: Compound::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataPacks {
+    #[serde(rename = "Enabled")]
+    pub enabled: Vec<String>,
+    #[serde(rename = "Disabled")]
+    pub disabled: Vec<String>,
+}
+
+impl Default for DataPacks {
+    fn default() -> Self {
+        Self {
+            enabled: vec!["vanilla".to_string()],
+            disabled: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cellophanemc_nbt::file::{read_file, write_file};
+    use cellophanemc_nbt::snbt::from_snbt_str;
+    use cellophanemc_nbt::{Compound, Value};
+
+    use crate::level::{LevelData, NO_DATA_VERSION};
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("level.dat");
+
+        let mut level = LevelData::default();
+        level.world_gen_settings.seed = -4_172_144_997_902_289_642;
+        level.spawn_y = 64;
+        level.set_game_rule("doDaylightCycle", false);
+        level.wandering_trader_id = Some(vec![1, 2, 3, 4]);
+        level.save(&path).unwrap();
+        level.raining = true;
+        level.save(&path).unwrap();
+
+        assert_eq!(LevelData::load(&path).unwrap(), level);
+        let (root, _) = read_file::<String>(&path).unwrap();
+        let Some(Value::Compound(data)) = root.get("Data") else {
+            panic!("no Data compound");
+        };
+        assert_eq!(
+            data.get("WanderingTraderId"),
+            Some(&Value::IntArray(vec![1, 2, 3, 4]))
+        );
+
+        let old = LevelData::load(dir.path().join("level.dat_old")).unwrap();
+        assert!(!old.raining);
+        assert_eq!(old.game_rule("doDaylightCycle"), Some("false"));
+    }
+
+    #[test]
+    fn fills_missing_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("level.dat");
+        let data = match from_snbt_str(
+            r#"{LevelName: "old", SpawnX: 10, raining: 1b, WorldGenSettings: {seed: 42L},
+                GameRules: {keepInventory: "true"}, DataPacks: {Enabled: ["vanilla", "file/x"]}}"#,
+        )
+        .unwrap()
+        {
+            Value::Compound(data) => data,
+            value => panic!("not a compound: {value}"),
+        };
+        let mut root = Compound::new();
+        root.insert("Data", data);
+        write_file(&path, &root, "").unwrap();
+
+        let level = LevelData::load(&path).unwrap();
+        assert_eq!(level.level_name, "old");
+        assert_eq!(level.spawn_x, 10);
+        assert!(level.raining);
+        assert_eq!(level.world_gen_settings.seed, 42);
+        assert!(level.world_gen_settings.generate_features);
+        assert_eq!(level.game_rule("keepInventory"), Some("true"));
+        assert_eq!(level.data_packs.enabled, ["vanilla", "file/x"]);
+        assert_eq!(level.data_version, NO_DATA_VERSION);
+        assert_eq!(level.border_size, 59_999_968.0);
+    }
+
+    #[test]
+    fn keeps_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("level.dat");
+        let data = match from_snbt_str(
+            r#"{DataVersion: 3700, raining: 1b, ModState: {ids: [I; 1, 2]}, ModFlag: 1b}"#,
+        )
+        .unwrap()
+        {
+            Value::Compound(data) => data,
+            value => panic!("not a compound: {value}"),
+        };
+        let mut root = Compound::new();
+        root.insert("Data", data);
+        write_file(&path, &root, "").unwrap();
+
+        let level = LevelData::load(&path).unwrap();
+        assert_eq!(level.data_version, 3700);
+        assert!(level.raining);
+        assert_eq!(level.extra.len(), 2);
+        level.save(&path).unwrap();
+
+        let (root, _) = read_file::<String>(&path).unwrap();
+        let Some(Value::Compound(data)) = root.get("Data") else {
+            panic!("no Data compound");
+        };
+        let Some(Value::Compound(state)) = data.get("ModState") else {
+            panic!("no ModState compound");
+        };
+        assert_eq!(state.get("ids"), Some(&Value::IntArray(vec![1, 2])));
+        assert_eq!(data.get("ModFlag"), Some(&Value::Byte(1)));
+        assert_eq!(LevelData::load(&path).unwrap(), level);
+    }
+}
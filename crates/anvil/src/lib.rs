@@ -1,3 +1,12 @@
-mod chunk;
+pub mod cache;
+pub mod chunk;
 pub mod error;
+pub mod fixer;
+pub mod level;
+mod lz4;
 pub mod region;
+pub mod repair;
+
+/// Data version of the saves written by this crate, matching Minecraft
+/// 1.20.4.
+pub const DATA_VERSION: i32 = 3700;
@@ -0,0 +1,143 @@
+//! The LZ4 block stream format written by lz4-java's `LZ4BlockOutputStream`,
+//! which vanilla uses for chunks with compression type 4.
+//!
+//! The stream is a sequence of blocks, each made of the `LZ4Block` magic, a
+//! token, the compressed and original lengths, an xxHash32 checksum of the
+//! original data and the data itself. An empty block ends the stream.
+
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::{Error, Result};
+
+const MAGIC: &[u8; 8] = b"LZ4Block";
+const METHOD_RAW: u8 = 0x10;
+const METHOD_LZ4: u8 = 0x20;
+/// Block size used by vanilla, the lz4-java default.
+const BLOCK_SIZE: usize = 1 << 16;
+/// `log2(BLOCK_SIZE) - 10`, stored in the low bits of every token.
+const COMPRESSION_LEVEL: u8 = 6;
+const CHECKSUM_SEED: u32 = 0x9747_b28c;
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2);
+    for block in data.chunks(BLOCK_SIZE) {
+        let compressed = lz4_flex::block::compress(block);
+        let (method, payload) = if compressed.len() < block.len() {
+            (METHOD_LZ4, compressed.as_slice())
+        } else {
+            (METHOD_RAW, block)
+        };
+        write_block_header(
+            &mut out,
+            method,
+            payload.len(),
+            block.len(),
+            checksum(block),
+        );
+        out.extend_from_slice(payload);
+    }
+    write_block_header(&mut out, METHOD_RAW, 0, 0, 0);
+    out
+}
+
+pub(crate) fn decompress(mut src: impl Read) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut compressed = Vec::new();
+    loop {
+        let mut magic = [0; MAGIC.len()];
+        src.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidLz4("bad block magic"));
+        }
+        let token = src.read_u8()?;
+        let method = token & 0xf0;
+        let block_size = 1 << ((token & 0x0f) + 10);
+        let compressed_len = read_len(&mut src)?;
+        let original_len = read_len(&mut src)?;
+        let expected_checksum = src.read_u32::<LittleEndian>()?;
+
+        if original_len == 0 {
+            if compressed_len != 0 || expected_checksum != 0 {
+                return Err(Error::InvalidLz4("bad end block"));
+            }
+            return Ok(out);
+        }
+        if original_len > block_size
+            || compressed_len > lz4_flex::block::get_maximum_output_size(original_len)
+        {
+            return Err(Error::InvalidLz4("block too large"));
+        }
+
+        let start = out.len();
+        out.resize(start + original_len, 0);
+        match method {
+            METHOD_RAW if compressed_len == original_len => src.read_exact(&mut out[start..])?,
+            METHOD_LZ4 => {
+                compressed.resize(compressed_len, 0);
+                src.read_exact(&mut compressed)?;
+                let len = lz4_flex::block::decompress_into(&compressed, &mut out[start..])
+                    .map_err(|_| Error::InvalidLz4("corrupt block"))?;
+                if len != original_len {
+                    return Err(Error::InvalidLz4("block length mismatch"));
+                }
+            }
+            _ => return Err(Error::InvalidLz4("unknown block method")),
+        }
+
+        if checksum(&out[start..]) != expected_checksum {
+            return Err(Error::InvalidLz4("checksum mismatch"));
+        }
+    }
+}
+
+fn write_block_header(
+    out: &mut Vec<u8>,
+    method: u8,
+    compressed_len: usize,
+    original_len: usize,
+    checksum: u32,
+) {
+    out.extend_from_slice(MAGIC);
+    out.push(method | COMPRESSION_LEVEL);
+    // Writing to a `Vec` can't fail.
+    out.write_i32::<LittleEndian>(compressed_len as i32)
+        .unwrap();
+    out.write_i32::<LittleEndian>(original_len as i32).unwrap();
+    out.write_u32::<LittleEndian>(checksum).unwrap();
+}
+
+fn read_len(src: &mut impl Read) -> Result<usize> {
+    usize::try_from(src.read_i32::<LittleEndian>()?)
+        .map_err(|_| Error::InvalidLz4("negative block length"))
+}
+
+/// lz4-java only keeps the low 28 bits of the checksum.
+fn checksum(data: &[u8]) -> u32 {
+    xxhash_rust::xxh32::xxh32(data, CHECKSUM_SEED) & 0x0fff_ffff
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lz4::{compress, decompress};
+
+    #[test]
+    fn round_trip() {
+        let data = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_be_bytes())
+            .collect::<Vec<_>>();
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(compressed.as_slice()).unwrap(), data);
+
+        assert!(decompress(compress(&[]).as_slice()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut compressed = compress(b"hello hello hello hello");
+        compressed[25] ^= 1;
+        assert!(decompress(compressed.as_slice()).is_err());
+    }
+}
@@ -1,10 +1,15 @@
+use std::fs::File;
 use std::hash::Hash;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use bitfield_struct::bitfield;
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use cellophanemc_nbt::binary::decode::FromModifiedUtf8;
+use cellophanemc_nbt::binary::encode::ToModifiedUtf8;
 use cellophanemc_nbt::Compound;
 
 use crate::error::{Error, Result};
@@ -13,22 +18,167 @@ pub const SECTOR_SIZE: usize = 4096;
 pub const REGION_HEADER_SIZE: usize = SECTOR_SIZE * 2;
 pub const CHUNK_HEADER_SIZE: usize = 5;
 
+/// Sectors taken up by the location and timestamp tables.
+pub(crate) const HEADER_SECTORS: usize = REGION_HEADER_SIZE / SECTOR_SIZE;
+/// Largest number of sectors a chunk can occupy, as the sector count in a
+/// location is a single byte.
+const MAX_CHUNK_SECTORS: usize = u8::MAX as usize;
+/// Set in the compression byte of chunks too large for the region file,
+/// whose data is stored in a separate `c.<x>.<z>.mcc` file instead.
+pub(crate) const EXTERNAL_CHUNK_FLAG: u8 = 0x80;
+
 #[derive(Clone)]
 pub struct Region<S> {
     stream: S,
     locations: [Location; 1024],
     timestamps: [u32; 1024],
     used_sectors: bitvec::vec::BitVec,
+    external_dir: Option<PathBuf>,
+    compression: CompressionType,
+    custom_compression: Option<Arc<dyn CustomCompression>>,
+}
+
+impl Region<File> {
+    /// Opens the region file at `path` for reading and writing, creating an
+    /// empty region if it doesn't exist yet.
+    ///
+    /// Oversized chunks are kept in `.mcc` files next to the region file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let region = Self::from_stream(file)?;
+        Ok(match path.parent() {
+            Some(dir) => region.with_external_dir(dir),
+            None => region,
+        })
+    }
+
+    /// Flushes all writes to the region file down to the disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.stream.flush()?;
+        self.stream.sync_data()?;
+        Ok(())
+    }
+}
+
+impl<F> Region<F> {
+    /// Sets the directory of the `c.<x>.<z>.mcc` files that hold chunks too
+    /// large for the region file.
+    ///
+    /// Without it, reading such a chunk fails with
+    /// [`Error::MissingExternalDir`] and writing one fails with
+    /// [`Error::ChunkTooLarge`].
+    pub fn with_external_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.external_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the compression used by [`write_chunk`](Region::write_chunk).
+    /// Defaults to [`CompressionType::Zlib`], like vanilla.
+    ///
+    /// Chunks are always read with the compression they were written with,
+    /// so this can be changed for existing regions.
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the algorithm behind [`CompressionType::Custom`], both for
+    /// writing and for reading chunks that name it.
+    pub fn with_custom_compression(mut self, custom: Arc<dyn CustomCompression>) -> Self {
+        self.custom_compression = Some(custom);
+        self
+    }
+
+    fn custom_compression(&self) -> Result<&dyn CustomCompression> {
+        self.custom_compression
+            .as_deref()
+            .ok_or(Error::MissingCustomCompression)
+    }
+
+    fn compress(&self, nbt_data: &[u8], mut buf: Vec<u8>) -> Result<Vec<u8>> {
+        match self.compression {
+            CompressionType::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(buf, flate2::Compression::default());
+                encoder.write_all(nbt_data)?;
+                buf = encoder.finish()?;
+            }
+            CompressionType::Zlib => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(buf, flate2::Compression::default());
+                encoder.write_all(nbt_data)?;
+                buf = encoder.finish()?;
+            }
+            CompressionType::Uncompressed => buf.extend_from_slice(nbt_data),
+            CompressionType::Lz4 => buf.extend_from_slice(&crate::lz4::compress(nbt_data)),
+            CompressionType::Custom => {
+                let custom = self.custom_compression()?;
+                let name = custom.name().as_bytes();
+                buf.write_u16::<BigEndian>(name.len() as u16)?;
+                buf.extend_from_slice(name);
+                buf.extend_from_slice(&custom.compress(nbt_data)?);
+            }
+        }
+        Ok(buf)
+    }
+
+    fn decompress(&self, compression_type: CompressionType, mut src: impl Read) -> Result<Vec<u8>> {
+        let mut nbt_data = Vec::new();
+        match compression_type {
+            CompressionType::Gzip => {
+                let mut decoder = flate2::read::GzDecoder::new(src);
+                std::io::copy(&mut decoder, &mut nbt_data)?;
+            }
+            CompressionType::Zlib => {
+                let mut decoder = flate2::read::ZlibDecoder::new(src);
+                std::io::copy(&mut decoder, &mut nbt_data)?;
+            }
+            CompressionType::Uncompressed => {
+                std::io::copy(&mut src, &mut nbt_data)?;
+            }
+            CompressionType::Lz4 => nbt_data = crate::lz4::decompress(src)?,
+            CompressionType::Custom => {
+                let mut name = vec![0; src.read_u16::<BigEndian>()? as usize];
+                src.read_exact(&mut name)?;
+                let custom = self.custom_compression()?;
+                if name != custom.name().as_bytes() {
+                    let name = String::from_utf8_lossy(&name).into_owned();
+                    return Err(Error::UnknownCustomCompression(name));
+                }
+                let mut compressed = Vec::new();
+                src.read_to_end(&mut compressed)?;
+                nbt_data = custom.decompress(&compressed)?;
+            }
+        }
+        Ok(nbt_data)
+    }
+
+    fn external_chunk_path(&self, x: i32, z: i32) -> Option<PathBuf> {
+        self.external_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("c.{x}.{z}.mcc")))
+    }
 }
 
 impl<F> Region<F>
 where
     F: Read + Seek,
 {
+    /// Reads the region header from `stream`. An empty stream is treated as
+    /// an empty region.
     pub fn from_stream(stream: F) -> Result<Self> {
         let mut stream = stream;
         let mut header = [0u8; REGION_HEADER_SIZE];
-        stream.read_exact(&mut header)?;
+        if stream.seek(SeekFrom::End(0))? != 0 {
+            stream.seek(SeekFrom::Start(0))?;
+            stream.read_exact(&mut header)?;
+        }
 
         let locations = std::array::from_fn(|i| {
             Location(u32::from_be_bytes(
@@ -43,13 +193,13 @@ where
             )
         });
 
-        let mut used_sectors = bitvec::vec::BitVec::repeat(true, 2);
+        let mut used_sectors = bitvec::vec::BitVec::repeat(true, HEADER_SECTORS);
         for location in locations {
             if location.is_empty() {
                 continue;
             }
             let (sector_offset, sector_count) = location.offset_and_count();
-            if sector_offset < 2 {
+            if sector_offset < HEADER_SECTORS as u64 {
                 continue;
             }
             if sector_count == 0 {
@@ -64,6 +214,9 @@ where
             locations,
             timestamps,
             used_sectors,
+            external_dir: None,
+            compression: CompressionType::default(),
+            custom_compression: None,
         })
     }
 
@@ -75,16 +228,26 @@ where
         let start_index = sector_offset as usize;
         let end_index = sector_offset as usize + sector_count;
         if used_sectors.len() < end_index {
-            used_sectors.resize(start_index, false);
-            used_sectors.resize(end_index, true);
-        } else {
-            used_sectors[start_index..end_index].fill(true);
+            used_sectors.resize(end_index, false);
         }
+        used_sectors[start_index..end_index].fill(true);
     }
 
-    fn read_raw_chunk<S>(&mut self, x: i32, z: i32) -> Result<Option<RawChunk<S>>>
+    /// Returns whether the chunk at `x`, `z` is present in this region. Only
+    /// the lower 5 bits of the coordinates are used.
+    pub fn has_chunk(&self, x: i32, z: i32) -> bool {
+        !self.locations[chunk_idx(x, z)].is_empty()
+    }
+
+    /// Reads and decompresses the chunk at `x`, `z`, or returns `None` if it
+    /// has not been generated.
+    ///
+    /// The coordinates are absolute: their lower 5 bits select the chunk
+    /// within this region, while the full values name its `.mcc` file if the
+    /// chunk is stored externally.
+    pub fn read_chunk<S>(&mut self, x: i32, z: i32) -> Result<Option<RawChunk<S>>>
     where
-        S: FromModifiedUtf8 + Hash + Ord,
+        S: for<'de> FromModifiedUtf8<'de> + Hash + Ord,
     {
         let chunk_idx = chunk_idx(x, z);
         let location = self.locations[chunk_idx];
@@ -94,26 +257,33 @@ where
         }
 
         let (sector_offset, sector_count) = location.offset_and_count();
+        if sector_offset < HEADER_SECTORS as u64 {
+            return Err(Error::ChunkInHeader(sector_offset));
+        }
 
         self.stream
             .seek(SeekFrom::Start(sector_offset * SECTOR_SIZE as u64))?;
 
+        // The length is untrusted, so it has to fit in the chunk's sectors
+        // before anything is allocated for it.
         let exact_chunk_size = self.stream.read_u32::<BigEndian>()? as usize;
-        let compression_type = CompressionType::try_from(self.stream.read_u8()?)?;
-
-        let mut src = (&mut self.stream).take((exact_chunk_size - 1) as u64);
-        let mut nbt_data = Vec::new();
-
-        match compression_type {
-            CompressionType::Gzip => {
-                let mut decoder = flate2::read::GzDecoder::new(src);
-                std::io::copy(&mut decoder, &mut nbt_data)?
-            }
-            CompressionType::Zlib => {
-                let mut decoder = flate2::read::ZlibDecoder::new(src);
-                std::io::copy(&mut decoder, &mut nbt_data)?
-            }
-            CompressionType::Uncompressed => std::io::copy(&mut src, &mut nbt_data)?,
+        if exact_chunk_size == 0
+            || exact_chunk_size > (sector_count * SECTOR_SIZE).saturating_sub(4)
+        {
+            return Err(Error::InvalidChunkHeaderSize(exact_chunk_size));
+        }
+        let compression_byte = self.stream.read_u8()?;
+        let compression_type = CompressionType::try_from(compression_byte & !EXTERNAL_CHUNK_FLAG)?;
+
+        let nbt_data = if compression_byte & EXTERNAL_CHUNK_FLAG != 0 {
+            let path = self
+                .external_chunk_path(x, z)
+                .ok_or(Error::MissingExternalDir)?;
+            self.decompress(compression_type, File::open(path)?)?
+        } else {
+            let mut compressed = vec![0; exact_chunk_size - 1];
+            self.stream.read_exact(&mut compressed)?;
+            self.decompress(compression_type, compressed.as_slice())?
         };
 
         let (nbt, _) = cellophanemc_nbt::binary::decode::from_binary(&mut nbt_data.as_slice())?;
@@ -125,29 +295,169 @@ where
     }
 }
 
+impl<F> Region<F>
+where
+    F: Read + Write + Seek,
+{
+    /// Compresses `data` with the region's
+    /// [compression](Region::with_compression) and stores it as the chunk at
+    /// `x`, `z`, replacing
+    /// any previous version and setting its timestamp to the current time.
+    /// The coordinates are absolute, as in [`read_chunk`](Self::read_chunk).
+    ///
+    /// The new data is written to free sectors before the header is updated,
+    /// so an interrupted write leaves the previous version of the chunk
+    /// intact. Chunks that would take up more than 255 sectors are written to
+    /// an external `.mcc` file, with only a stub left in the region.
+    pub fn write_chunk<S>(&mut self, x: i32, z: i32, data: &Compound<S>) -> Result<()>
+    where
+        S: ToModifiedUtf8 + Hash + Ord,
+    {
+        let mut nbt_data = Vec::new();
+        cellophanemc_nbt::binary::encode::to_binary(data, "", &mut nbt_data)?;
+        let mut buf = self.compress(&nbt_data, vec![0; CHUNK_HEADER_SIZE])?;
+
+        let external_path = self.external_chunk_path(x, z);
+        let mut compression_byte = self.compression as u8;
+        if buf.len().div_ceil(SECTOR_SIZE) > MAX_CHUNK_SECTORS {
+            let path = external_path
+                .as_ref()
+                .ok_or(Error::ChunkTooLarge(buf.len()))?;
+            write_external_chunk(path, &buf[CHUNK_HEADER_SIZE..])?;
+            buf.truncate(CHUNK_HEADER_SIZE);
+            compression_byte |= EXTERNAL_CHUNK_FLAG;
+        }
+
+        let exact_chunk_size = (buf.len() - 4) as u32;
+        buf[..4].copy_from_slice(&exact_chunk_size.to_be_bytes());
+        buf[4] = compression_byte;
+
+        let sector_count = buf.len().div_ceil(SECTOR_SIZE);
+        buf.resize(sector_count * SECTOR_SIZE, 0);
+
+        let sector_offset = self.allocate_sectors(sector_count);
+        self.stream
+            .seek(SeekFrom::Start(sector_offset as u64 * SECTOR_SIZE as u64))?;
+        self.stream.write_all(&buf)?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        let location = Location::new()
+            .with_offset(sector_offset as u32)
+            .with_count(sector_count as u8);
+        self.update_header(chunk_idx(x, z), location, timestamp)?;
+
+        match external_path {
+            Some(path) if compression_byte & EXTERNAL_CHUNK_FLAG == 0 => remove_if_exists(&path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the chunk at `x`, `z` from this region, freeing its sectors
+    /// and deleting its external file, if any. Does nothing if the chunk is
+    /// not present.
+    pub fn delete_chunk(&mut self, x: i32, z: i32) -> Result<()> {
+        let chunk_idx = chunk_idx(x, z);
+        if self.locations[chunk_idx].is_empty() {
+            return Ok(());
+        }
+        self.update_header(chunk_idx, Location(0), 0)?;
+        match self.external_chunk_path(x, z) {
+            Some(path) => remove_if_exists(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the location and timestamp of a chunk and frees the sectors it
+    /// previously occupied.
+    fn update_header(
+        &mut self,
+        chunk_idx: usize,
+        location: Location,
+        timestamp: u32,
+    ) -> Result<()> {
+        let previous = std::mem::replace(&mut self.locations[chunk_idx], location);
+        self.timestamps[chunk_idx] = timestamp;
+
+        self.stream.seek(SeekFrom::Start(chunk_idx as u64 * 4))?;
+        self.stream.write_u32::<BigEndian>(location.0)?;
+        self.stream
+            .seek(SeekFrom::Start((SECTOR_SIZE + chunk_idx * 4) as u64))?;
+        self.stream.write_u32::<BigEndian>(timestamp)?;
+        self.stream.flush()?;
+
+        let (sector_offset, sector_count) = previous.offset_and_count();
+        if !previous.is_empty() && sector_offset >= HEADER_SECTORS as u64 {
+            let start_index = sector_offset as usize;
+            let end_index = (start_index + sector_count).min(self.used_sectors.len());
+            self.used_sectors[start_index..end_index].fill(false);
+        }
+        Ok(())
+    }
+
+    /// Finds and reserves the first run of `sector_count` free sectors,
+    /// growing the file if there is none.
+    fn allocate_sectors(&mut self, sector_count: usize) -> usize {
+        let mut start_index = HEADER_SECTORS;
+        let mut free = 0;
+        for (index, used) in self.used_sectors.iter().by_vals().enumerate() {
+            if used {
+                start_index = index + 1;
+                free = 0;
+            } else {
+                free += 1;
+                if free == sector_count {
+                    break;
+                }
+            }
+        }
+        Self::reserve_sectors(&mut self.used_sectors, start_index as u64, sector_count);
+        start_index
+    }
+}
+
+/// Writes the compressed data of an oversized chunk to `path`, going through
+/// a temporary file so that a crash never leaves a truncated chunk behind.
+fn write_external_chunk(path: &Path, data: &[u8]) -> Result<()> {
+    let temp = path.with_extension("mcc.tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(temp, path)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
 #[derive(Debug)]
 pub struct RawChunk<S = String> {
     pub data: Compound<S>,
     pub timestamp: u32,
 }
 
-const fn chunk_idx(chunk_x: i32, chunk_z: i32) -> usize {
+pub(crate) const fn chunk_idx(chunk_x: i32, chunk_z: i32) -> usize {
     (chunk_x & 31) as usize + (chunk_z & 31) as usize * 32
 }
 
 #[bitfield(u32)]
-struct Location {
-    count: u8,
+pub(crate) struct Location {
+    pub(crate) count: u8,
     #[bits(24)]
-    offset: u32,
+    pub(crate) offset: u32,
 }
 
 impl Location {
-    fn is_empty(self) -> bool {
+    pub(crate) fn is_empty(self) -> bool {
         self.0 == 0
     }
 
-    fn offset_and_count(self) -> (u64, usize) {
+    pub(crate) fn offset_and_count(self) -> (u64, usize) {
         (self.offset() as u64, self.count() as usize)
     }
 }
@@ -177,12 +487,30 @@ impl ChunkHeader {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum CompressionType {
     Gzip = 1,
     #[default]
     Zlib = 2,
     Uncompressed = 3,
+    /// LZ4 in the block stream format of lz4-java, supported by vanilla since
+    /// 1.20.5.
+    Lz4 = 4,
+    /// An algorithm that isn't part of the format, named by the identifier
+    /// that precedes the chunk data. See [`CustomCompression`].
+    Custom = 127,
+}
+
+/// A compression algorithm for chunks written with
+/// [`CompressionType::Custom`].
+pub trait CustomCompression: Send + Sync {
+    /// Identifier written in front of each chunk, such as
+    /// `"cellophanemc:zstd"`. Only chunks with a matching identifier are read.
+    fn name(&self) -> &str;
+
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
+
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
 }
 
 impl TryFrom<u8> for CompressionType {
@@ -193,6 +521,8 @@ impl TryFrom<u8> for CompressionType {
             1 => Ok(CompressionType::Gzip),
             2 => Ok(CompressionType::Zlib),
             3 => Ok(CompressionType::Uncompressed),
+            4 => Ok(CompressionType::Lz4),
+            127 => Ok(CompressionType::Custom),
             _ => Err(Error::UnknownCompression(value)),
         }
     }
@@ -201,11 +531,158 @@ impl TryFrom<u8> for CompressionType {
 #[cfg(test)]
 mod test {
     use std::fs::File;
+    use std::io::Cursor;
+    use std::sync::Arc;
 
     use serde::Deserialize;
 
+    use cellophanemc_nbt::Compound;
+
     use crate::chunk::Chunk;
-    use crate::region::Region;
+    use crate::error::Error;
+    use crate::region::{
+        chunk_idx, CompressionType, CustomCompression, Location, Region, HEADER_SECTORS,
+        SECTOR_SIZE,
+    };
+
+    fn chunk(x: i32, z: i32, len: usize) -> Compound {
+        let mut chunk = Compound::new();
+        chunk.insert("xPos", x);
+        chunk.insert("zPos", z);
+        // Incompressible enough to span several sectors when large.
+        let data = (0..len as i64)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as i64))
+            .collect::<Vec<_>>();
+        chunk.insert("data", data);
+        chunk
+    }
+
+    #[test]
+    fn oversized_chunks_are_external() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut region = Region::open(dir.path().join("r.1.-1.mca"))
+            .unwrap()
+            .with_compression(CompressionType::Uncompressed);
+        let external = dir.path().join("c.33.-2.mcc");
+
+        // Uncompressed, 256 sectors of longs don't fit in the region file.
+        let large = chunk(33, -2, 256 * SECTOR_SIZE / 8);
+        region.write_chunk(33, -2, &large).unwrap();
+        assert!(external.exists());
+        assert_eq!(region.locations[chunk_idx(33, -2)].count(), 1);
+        let read = region.read_chunk::<String>(33, -2).unwrap().unwrap();
+        assert_eq!(read.data, large);
+
+        region = region.with_compression(CompressionType::Zlib);
+        region.write_chunk(33, -2, &chunk(33, -2, 1)).unwrap();
+        assert!(!external.exists());
+
+        let mut region = Region::from_stream(region.stream)
+            .unwrap()
+            .with_compression(CompressionType::Uncompressed);
+        let res = region.write_chunk(33, -2, &large);
+        assert!(matches!(res, Err(Error::ChunkTooLarge(_))));
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+
+        let mut region = Region::open(&path).unwrap();
+        region.write_chunk(0, 0, &chunk(0, 0, 16)).unwrap();
+        region.write_chunk(1, 0, &chunk(1, 0, 2048)).unwrap();
+        region.write_chunk(31, 31, &chunk(31, 31, 16)).unwrap();
+        // Grow the first chunk so it has to move, then shrink the second.
+        region.write_chunk(0, 0, &chunk(0, 0, 4096)).unwrap();
+        region.write_chunk(1, 0, &chunk(1, 0, 1)).unwrap();
+        region.delete_chunk(31, 31).unwrap();
+
+        // The shrunk chunk reuses the sector freed when the first chunk moved,
+        // and no sectors are leaked.
+        assert_eq!(region.locations[1].offset(), 2);
+        let used = region
+            .locations
+            .iter()
+            .map(|l| l.count() as usize)
+            .sum::<usize>();
+        assert_eq!(region.used_sectors.count_ones(), HEADER_SECTORS + used);
+        drop(region);
+
+        let mut region = Region::open(&path).unwrap();
+        let read = region.read_chunk::<String>(0, 0).unwrap().unwrap();
+        assert_eq!(read.data, chunk(0, 0, 4096));
+        assert_ne!(read.timestamp, 0);
+        let read = region.read_chunk::<String>(1, 0).unwrap().unwrap();
+        assert_eq!(read.data, chunk(1, 0, 1));
+        assert!(region.read_chunk::<String>(31, 31).unwrap().is_none());
+        assert!(!region.has_chunk(31, 31));
+
+        let len = std::fs::metadata(&path).unwrap().len() as usize;
+        assert_eq!(len % SECTOR_SIZE, 0);
+    }
+
+    #[test]
+    fn rejects_corrupt_locations() {
+        let mut region = Region::from_stream(Cursor::new(Vec::new())).unwrap();
+        region.write_chunk(0, 0, &chunk(0, 0, 16)).unwrap();
+        let location = region.locations[0];
+
+        // A length far beyond the chunk's single sector.
+        let start = location.offset() as usize * SECTOR_SIZE;
+        region.stream.get_mut()[start..start + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let res = region.read_chunk::<String>(0, 0);
+        assert!(matches!(res, Err(Error::InvalidChunkHeaderSize(_))));
+
+        region.locations[0] = Location::new().with_offset(1).with_count(1);
+        let res = region.read_chunk::<String>(0, 0);
+        assert!(matches!(res, Err(Error::ChunkInHeader(1))));
+    }
+
+    struct Reverse;
+
+    impl CustomCompression for Reverse {
+        fn name(&self) -> &str {
+            "test:reverse"
+        }
+
+        fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            Ok(data.iter().rev().copied().collect())
+        }
+
+        fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            self.compress(data)
+        }
+    }
+
+    #[test]
+    fn compression_types() {
+        let mut region = Region::from_stream(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_custom_compression(Arc::new(Reverse));
+        let types = [
+            CompressionType::Gzip,
+            CompressionType::Zlib,
+            CompressionType::Uncompressed,
+            CompressionType::Lz4,
+            CompressionType::Custom,
+        ];
+        for (x, compression) in types.into_iter().enumerate() {
+            region = region.with_compression(compression);
+            region
+                .write_chunk(x as i32, 0, &chunk(x as i32, 0, 512))
+                .unwrap();
+        }
+        for x in 0..types.len() as i32 {
+            let read = region.read_chunk::<String>(x, 0).unwrap().unwrap();
+            assert_eq!(read.data, chunk(x, 0, 512));
+        }
+
+        // Custom chunks can't be read without the algorithm.
+        let mut region = Region::from_stream(region.stream).unwrap();
+        let res = region.read_chunk::<String>(4, 0);
+        assert!(matches!(res, Err(Error::MissingCustomCompression)));
+    }
 
     #[test]
     fn foo() {
@@ -216,7 +693,7 @@ mod test {
         // println!("Chunk len: {:?}", chunk_data.len());
         // println!("read len: {:?}", read);
         let raw_chunk = region
-            .read_raw_chunk::<String>(0, 0)
+            .read_chunk::<String>(0, 0)
             .expect("Failed to read raw chunk")
             .unwrap();
         let data = raw_chunk.data;
@@ -0,0 +1,523 @@
+//! Validation and compaction of region files.
+//!
+//! [`scan_region`] checks the header entry of every chunk in a region file:
+//! that its sectors lie inside the file without overlapping the header or
+//! another chunk, that its length fits in those sectors and that its
+//! compression is known. It can also decompress and parse every chunk.
+//! [`compact_region`] then rewrites the file with the valid chunks stored
+//! back to back, dropping the corrupt ones and every unused sector.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cache::RegionPos;
+use crate::error::{Error, Result};
+use crate::region::{
+    chunk_idx, CompressionType, CustomCompression, Location, Region, CHUNK_HEADER_SIZE,
+    EXTERNAL_CHUNK_FLAG, HEADER_SECTORS, REGION_HEADER_SIZE, SECTOR_SIZE,
+};
+
+/// Options of [`scan_region`].
+#[derive(Clone)]
+pub struct ScanOptions {
+    /// Also decompress and parse every chunk, instead of only checking the
+    /// headers. Enabled by default.
+    pub check_data: bool,
+    /// The algorithm needed to read chunks written with
+    /// [`CompressionType::Custom`].
+    pub custom_compression: Option<Arc<dyn CustomCompression>>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            check_data: true,
+            custom_compression: None,
+        }
+    }
+}
+
+/// Why a chunk of a region file can't be read.
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkProblem {
+    #[error("location points into the region header")]
+    InHeader,
+    #[error("location has no sectors")]
+    NoSectors,
+    #[error("sectors {start}..{end} are past the end of the file, which has {file_sectors}")]
+    OutOfBounds {
+        start: usize,
+        end: usize,
+        file_sectors: usize,
+    },
+    #[error("sectors overlap with chunk {x}, {z}")]
+    Overlapping { x: i32, z: i32 },
+    #[error("length of {length} bytes does not fit in {sectors} sectors")]
+    InvalidLength { length: u32, sectors: usize },
+    #[error("unknown compression scheme {0}")]
+    UnknownCompression(u8),
+    #[error("external chunk file is missing")]
+    MissingExternalFile,
+    #[error("corrupt data: {0}")]
+    Corrupt(Error),
+}
+
+/// A problem with the chunk at `x`, `z`, in absolute chunk coordinates.
+#[derive(Debug)]
+pub struct ChunkReport {
+    pub x: i32,
+    pub z: i32,
+    pub problem: ChunkProblem,
+}
+
+impl fmt::Display for ChunkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chunk {}, {}: {}", self.x, self.z, self.problem)
+    }
+}
+
+/// The result of [`scan_region`].
+#[derive(Debug)]
+pub struct RegionReport {
+    pub path: PathBuf,
+    pub pos: RegionPos,
+    /// Number of chunks in the header, valid or not.
+    pub chunks: usize,
+    pub problems: Vec<ChunkReport>,
+    /// Sectors taken up by the header and the valid chunks.
+    pub used_sectors: usize,
+    /// Size of the file in sectors, counting a partial last sector.
+    pub file_sectors: usize,
+}
+
+impl RegionReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Returns the number of sectors that hold no valid chunk.
+    pub fn free_sectors(&self) -> usize {
+        self.file_sectors.saturating_sub(self.used_sectors)
+    }
+
+    /// Returns whether [`compact_region`] would change the file.
+    pub fn needs_compaction(&self) -> bool {
+        !self.is_valid() || self.free_sectors() > 0
+    }
+
+    fn has_problem(&self, x: i32, z: i32) -> bool {
+        self.problems
+            .iter()
+            .any(|report| report.x == x && report.z == z)
+    }
+}
+
+/// The result of [`compact_region`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactStats {
+    pub chunks: usize,
+    pub removed_chunks: usize,
+    pub sectors_before: usize,
+    pub sectors_after: usize,
+}
+
+/// Returns the paths of the `r.<x>.<z>.mca` files in `dir`, sorted by name.
+pub fn region_files(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_region = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(RegionPos::from_file_name)
+            .is_some();
+        if is_region && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Checks every chunk of the region file at `path`.
+///
+/// The file must be named `r.<x>.<z>.mca`, so that chunk coordinates and
+/// external chunk files can be resolved. Only I/O errors and a missing or
+/// truncated header fail the scan; everything else is reported per chunk.
+pub fn scan_region(path: impl AsRef<Path>, options: &ScanOptions) -> Result<RegionReport> {
+    let path = path.as_ref();
+    let pos = region_pos(path)?;
+    let data = fs::read(path)?;
+    if data.len() < REGION_HEADER_SIZE {
+        return Err(Error::InvalidRegionHeader(data.len()));
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let file_sectors = data.len().div_ceil(SECTOR_SIZE);
+    let mut owners: Vec<Option<(i32, i32)>> = vec![None; file_sectors];
+    let mut report = RegionReport {
+        path: path.to_path_buf(),
+        pos,
+        chunks: 0,
+        problems: Vec::new(),
+        used_sectors: HEADER_SECTORS.min(file_sectors),
+        file_sectors,
+    };
+
+    for (x, z) in chunk_coords(pos) {
+        let location = location(&data, chunk_idx(x, z));
+        if location.is_empty() {
+            continue;
+        }
+        report.chunks += 1;
+        match check_location(&data, dir, x, z, location, &owners) {
+            Ok((start, count)) => {
+                owners[start..start + count].fill(Some((x, z)));
+                report.used_sectors += count;
+            }
+            Err(problem) => report.problems.push(ChunkReport { x, z, problem }),
+        }
+    }
+
+    if options.check_data {
+        let mut region = Region::from_stream(Cursor::new(data.as_slice()))?.with_external_dir(dir);
+        if let Some(custom) = &options.custom_compression {
+            region = region.with_custom_compression(custom.clone());
+        }
+        for (x, z) in chunk_coords(pos) {
+            if !region.has_chunk(x, z) || report.has_problem(x, z) {
+                continue;
+            }
+            if let Err(e) = region.read_chunk::<String>(x, z) {
+                let (_, count) = location(&data, chunk_idx(x, z)).offset_and_count();
+                report.used_sectors -= count;
+                report.problems.push(ChunkReport {
+                    x,
+                    z,
+                    problem: ChunkProblem::Corrupt(e),
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Rewrites the region file scanned into `report` with its valid chunks
+/// stored back to back after the header, in the order of their location
+/// entries.
+///
+/// Chunks with problems are removed, along with their external chunk files.
+/// The new file is written next to the old one and renamed over it once
+/// complete. Fails if a valid chunk no longer fits in the file, because it
+/// changed since the scan.
+pub fn compact_region(report: &RegionReport) -> Result<CompactStats> {
+    let data = fs::read(&report.path)?;
+    if data.len() < REGION_HEADER_SIZE {
+        return Err(Error::InvalidRegionHeader(data.len()));
+    }
+    let dir = report.path.parent().unwrap_or(Path::new(""));
+    let mut removed = Vec::new();
+    let mut header = vec![0u8; REGION_HEADER_SIZE];
+    let mut chunks = Vec::with_capacity(data.len());
+    let mut stats = CompactStats {
+        sectors_before: data.len().div_ceil(SECTOR_SIZE),
+        ..CompactStats::default()
+    };
+
+    for (x, z) in chunk_coords(report.pos) {
+        let idx = chunk_idx(x, z);
+        let location = location(&data, idx);
+        if location.is_empty() {
+            continue;
+        }
+        if report.has_problem(x, z) {
+            stats.removed_chunks += 1;
+            removed.push((x, z));
+            continue;
+        }
+
+        let (offset, _) = location.offset_and_count();
+        let start = offset as usize * SECTOR_SIZE;
+        let chunk = data
+            .get(start..start + 4)
+            .map(|_| chunk_length(&data, start) as usize)
+            .and_then(|length| data.get(start..start + length + 4))
+            .filter(|chunk| chunk.len() <= u8::MAX as usize * SECTOR_SIZE)
+            .ok_or_else(|| Error::RegionChanged(report.path.clone()))?;
+        let sector_count = chunk.len().div_ceil(SECTOR_SIZE);
+        let sector_offset = HEADER_SECTORS + chunks.len() / SECTOR_SIZE;
+        chunks.extend_from_slice(chunk);
+        chunks.resize(chunks.len().next_multiple_of(SECTOR_SIZE), 0);
+
+        let location = Location::new()
+            .with_offset(sector_offset as u32)
+            .with_count(sector_count as u8);
+        header[idx * 4..idx * 4 + 4].copy_from_slice(&u32::from(location).to_be_bytes());
+        let timestamp = SECTOR_SIZE + idx * 4;
+        header[timestamp..timestamp + 4].copy_from_slice(&data[timestamp..timestamp + 4]);
+        stats.chunks += 1;
+    }
+    stats.sectors_after = HEADER_SECTORS + chunks.len() / SECTOR_SIZE;
+
+    let temp = report.path.with_extension("mca.tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(&header)?;
+    file.write_all(&chunks)?;
+    file.sync_all()?;
+    fs::rename(temp, &report.path)?;
+
+    for (x, z) in removed {
+        match fs::remove_file(dir.join(format!("c.{x}.{z}.mcc"))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+fn region_pos(path: &Path) -> Result<RegionPos> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(RegionPos::from_file_name)
+        .ok_or_else(|| Error::InvalidRegionName(path.to_path_buf()))
+}
+
+fn chunk_coords(pos: RegionPos) -> impl Iterator<Item = (i32, i32)> {
+    (0..32).flat_map(move |z| (0..32).map(move |x| (pos.x * 32 + x, pos.z * 32 + z)))
+}
+
+fn location(data: &[u8], idx: usize) -> Location {
+    Location::from(u32::from_be_bytes(
+        data[idx * 4..idx * 4 + 4].try_into().unwrap(),
+    ))
+}
+
+fn chunk_length(data: &[u8], start: usize) -> u32 {
+    u32::from_be_bytes(data[start..start + 4].try_into().unwrap())
+}
+
+/// Checks the location of a chunk and the header in front of its data,
+/// returning the range of sectors it takes up.
+fn check_location(
+    data: &[u8],
+    dir: &Path,
+    x: i32,
+    z: i32,
+    location: Location,
+    owners: &[Option<(i32, i32)>],
+) -> std::result::Result<(usize, usize), ChunkProblem> {
+    let (offset, count) = location.offset_and_count();
+    let start = offset as usize;
+    if start < HEADER_SECTORS {
+        return Err(ChunkProblem::InHeader);
+    }
+    if count == 0 {
+        return Err(ChunkProblem::NoSectors);
+    }
+    if start + count > owners.len() {
+        return Err(ChunkProblem::OutOfBounds {
+            start,
+            end: start + count,
+            file_sectors: owners.len(),
+        });
+    }
+    if let Some((x, z)) = owners[start..start + count].iter().find_map(|owner| *owner) {
+        return Err(ChunkProblem::Overlapping { x, z });
+    }
+
+    let byte_offset = start * SECTOR_SIZE;
+    if data.len() < byte_offset + CHUNK_HEADER_SIZE {
+        return Err(ChunkProblem::InvalidLength {
+            length: 0,
+            sectors: count,
+        });
+    }
+    let length = chunk_length(data, byte_offset);
+    if length == 0
+        || byte_offset + 4 + length as usize > data.len().min(byte_offset + count * SECTOR_SIZE)
+    {
+        return Err(ChunkProblem::InvalidLength {
+            length,
+            sectors: count,
+        });
+    }
+
+    let compression_byte = data[byte_offset + 4];
+    if CompressionType::try_from(compression_byte & !EXTERNAL_CHUNK_FLAG).is_err() {
+        return Err(ChunkProblem::UnknownCompression(compression_byte));
+    }
+    if compression_byte & EXTERNAL_CHUNK_FLAG != 0 && !dir.join(format!("c.{x}.{z}.mcc")).is_file()
+    {
+        return Err(ChunkProblem::MissingExternalFile);
+    }
+    Ok((start, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+
+    use cellophanemc_nbt::Compound;
+
+    use crate::error::Error;
+    use crate::region::{CompressionType, Region, SECTOR_SIZE};
+    use crate::repair::{compact_region, region_files, scan_region, ChunkProblem, ScanOptions};
+
+    fn chunk(x: i32, len: usize) -> Compound {
+        let mut chunk = Compound::new();
+        chunk.insert("xPos", x);
+        // Random bytes so that the chunk doesn't compress.
+        let mut state = x as u64;
+        let noise = (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (state >> 56) as i8
+            })
+            .collect::<Vec<_>>();
+        chunk.insert("noise", noise);
+        chunk
+    }
+
+    fn set_location(path: &std::path::Path, idx: usize, offset: u32, count: u8) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(idx as u64 * 4)).unwrap();
+        file.write_all(&(offset << 8 | count as u32).to_be_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn reports_and_compacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.1.-1.mca");
+        {
+            let mut region = Region::open(&path).unwrap();
+            for x in 0..4 {
+                region.write_chunk(32 + x, -32, &chunk(x, 5000)).unwrap();
+            }
+            // Leaves a hole of two sectors behind.
+            region.write_chunk(33, -32, &chunk(1, 10_000)).unwrap();
+            region.delete_chunk(35, -32).unwrap();
+        }
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        assert_eq!(region_files(dir.path()).unwrap(), vec![path.clone()]);
+
+        let report = scan_region(&path, &ScanOptions::default()).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.chunks, 3);
+        assert_eq!(report.free_sectors(), 4);
+
+        // Point chunk 2 at the sectors of chunk 0, and chunk 3 past the end.
+        set_location(&path, 2, 2, 2);
+        set_location(&path, 3, 100, 1);
+        let report = scan_region(&path, &ScanOptions::default()).unwrap();
+        let problems = report
+            .problems
+            .iter()
+            .map(|report| (report.x, report.z, report.problem.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            [
+                (34, -32, "sectors overlap with chunk 32, -32".to_string()),
+                (
+                    35,
+                    -32,
+                    "sectors 100..101 are past the end of the file, which has 13".to_string()
+                ),
+            ]
+        );
+
+        let stats = compact_region(&report).unwrap();
+        assert_eq!(stats.chunks, 2);
+        assert_eq!(stats.removed_chunks, 2);
+        assert_eq!(stats.sectors_after, 2 + 2 + 3);
+
+        let report = scan_region(&path, &ScanOptions::default()).unwrap();
+        assert!(report.is_valid());
+        assert!(!report.needs_compaction());
+        let mut region = Region::open(&path).unwrap();
+        assert!(region.read_chunk::<String>(32, -32).unwrap().is_some());
+        assert!(region.read_chunk::<String>(33, -32).unwrap().is_some());
+        assert!(!region.has_chunk(34, -32));
+    }
+
+    #[test]
+    fn detects_corrupt_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+        Region::open(&path)
+            .unwrap()
+            .write_chunk(0, 0, &chunk(0, 100))
+            .unwrap();
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(2 * SECTOR_SIZE as u64 + 10))
+            .unwrap();
+        file.write_all(&[0xff; 16]).unwrap();
+        drop(file);
+
+        let header_only = ScanOptions {
+            check_data: false,
+            ..ScanOptions::default()
+        };
+        assert!(scan_region(&path, &header_only).unwrap().is_valid());
+        let report = scan_region(&path, &ScanOptions::default()).unwrap();
+        assert!(matches!(
+            report.problems[0].problem,
+            ChunkProblem::Corrupt(_)
+        ));
+        assert_eq!(report.used_sectors, 2);
+    }
+
+    #[test]
+    fn compaction_removes_external_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+        let external = dir.path().join("c.1.0.mcc");
+        {
+            let mut region = Region::open(&path)
+                .unwrap()
+                .with_compression(CompressionType::Uncompressed);
+            region.write_chunk(0, 0, &chunk(0, 100)).unwrap();
+            region
+                .write_chunk(1, 0, &chunk(1, 256 * SECTOR_SIZE))
+                .unwrap();
+        }
+        assert!(external.exists());
+
+        // Point the external chunk at the sector of chunk 0.
+        set_location(&path, 1, 2, 1);
+        let report = scan_region(&path, &ScanOptions::default()).unwrap();
+        assert_eq!(report.problems.len(), 1);
+        let stats = compact_region(&report).unwrap();
+        assert_eq!(stats.removed_chunks, 1);
+        assert!(!external.exists());
+    }
+
+    #[test]
+    fn compaction_fails_on_changed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("r.0.0.mca");
+        Region::open(&path)
+            .unwrap()
+            .write_chunk(0, 0, &chunk(0, 5000))
+            .unwrap();
+        let report = scan_region(&path, &ScanOptions::default()).unwrap();
+        assert!(report.is_valid());
+
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(3 * SECTOR_SIZE as u64).unwrap();
+        drop(file);
+        assert!(matches!(
+            compact_region(&report),
+            Err(Error::RegionChanged(_))
+        ));
+    }
+}
@@ -14,6 +14,10 @@ pub struct Block {
 }
 
 impl Block {
+    pub fn states(&self) -> &[BlockState] {
+        &self.states
+    }
+
     pub fn default_block_state(&self) -> Option<&BlockState> {
         self.states.iter().find(|&x| {
             x.is_default()
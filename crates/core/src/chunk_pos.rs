@@ -121,6 +121,40 @@ impl<T: Copy + Eq + Hash + std::fmt::Debug> PaletteContainer<T> {
         }
     }
 
+    /// Builds a container from a non-empty palette and a storage holding an
+    /// index into `palette` for every entry, as read from a saved section.
+    pub fn from_palette(strategy: Strategy, palette: Vec<T>, storage: PackedArray) -> Self {
+        debug_assert!(!palette.is_empty());
+        debug_assert_eq!(storage.len(), strategy.max_entries());
+        match palette.len() {
+            1 => Self::new(strategy, palette[0]),
+            len => Self {
+                strategy,
+                palette: if len <= 16 {
+                    DynPalette::Liner(LinerPalette::from(palette))
+                } else {
+                    DynPalette::Hash(HashPalette::from(palette))
+                },
+                storage,
+            },
+        }
+    }
+
+    /// Returns every entry of the container in storage order. Indices past
+    /// the end of the palette read as its first entry, and every entry of an
+    /// empty palette as `T::default()`.
+    pub fn values(&self) -> impl Iterator<Item=T> + '_
+    where
+        T: Default,
+    {
+        (0..self.storage.len()).map(|i| {
+            let id = self.storage.get(i).unwrap_or(0);
+            self.palette.get(id as usize)
+                .or_else(|| self.palette.get(0))
+                .unwrap_or_default()
+        })
+    }
+
     pub fn get(&self, x: usize, y: usize, z: usize) -> Option<T> {
         let index = self.strategy.index(x, y, z);
         if let Some(id) = self.storage.get(index) {
@@ -244,7 +278,7 @@ pub enum Strategy {
 }
 
 impl Strategy {
-    const fn min_bits_per_entry(&self) -> usize {
+    pub const fn min_bits_per_entry(&self) -> usize {
         match self {
             Self::Biome => 1,
             Self::Chunk => 4
@@ -258,7 +292,7 @@ impl Strategy {
         }
     }
 
-    const fn max_entries(&self) -> usize {
+    pub const fn max_entries(&self) -> usize {
         match self {
             Self::Biome => 64,
             Self::Chunk => 4096
@@ -304,6 +338,29 @@ mod tests {
         println!("{:?}", a)
     }
 
+    #[test]
+    fn values_with_out_of_range_index() {
+        let mut storage = PackedArray::new(Strategy::Biome.max_entries(), 1);
+        storage.set(3, 1);
+        let container = PaletteContainer::from_palette(Strategy::Biome, vec![7, 8], storage);
+        assert_eq!(container.values().nth(3), Some(8));
+
+        let mut storage = PackedArray::new(Strategy::Biome.max_entries(), 2);
+        storage.set(3, 3);
+        let container = PaletteContainer::from_palette(Strategy::Biome, vec![7, 8], storage);
+        let values = container.values().collect::<Vec<_>>();
+        assert_eq!(values.len(), Strategy::Biome.max_entries());
+        assert!(values.iter().all(|&value| value == 7));
+
+        let container = PaletteContainer {
+            strategy: Strategy::Biome,
+            palette: DynPalette::Empty(EmptyPalette::new()),
+            storage: PackedArray::new(Strategy::Biome.max_entries(), 1),
+        };
+        assert_eq!(container.values().count(), Strategy::Biome.max_entries());
+        assert!(container.values().all(|value: i32| value == 0));
+    }
+
     #[test]
     fn add_blocks() {
         let mut palette = DynPalette::new();
@@ -18,7 +18,7 @@ impl PackedArray {
         let index_scale = INDEX_PARAMETERS[i] as u32;
         let index_offset = INDEX_PARAMETERS[i + 1] as u32;
         let index_shift = INDEX_PARAMETERS[i + 2] as usize;
-        let need_u64s = (length * bits_per_value + 63) / 64;
+        let need_u64s = length.div_ceil(values_per_u64);
 
         Self {
             length,
@@ -98,4 +98,14 @@ mod test {
         assert_eq!(0x0020863148418841, array.bits[0]);
         assert_eq!(0x01018A7260F68C87, array.bits[1]);
     }
+
+    #[test]
+    fn values_do_not_span_longs() {
+        // 12 values fit in a long at 5 bits, so 4096 of them need 342 longs.
+        let mut array = PackedArray::new(4096, 5);
+        assert_eq!(array.bits.len(), 342);
+        array.set(4095, 31);
+        assert_eq!(array.get(4095), Some(31));
+        assert_eq!(array.bits[341], 31 << 15);
+    }
 }
@@ -1,2 +1,8 @@
+#[cfg(feature = "serde")]
+pub mod de;
 pub mod decode;
 pub mod encode;
+pub mod visit;
+mod input;
+#[cfg(feature = "serde")]
+pub mod ser;
@@ -0,0 +1,663 @@
+use std::borrow::Cow;
+use std::io;
+
+use byteorder::{BigEndian, ReadBytesExt};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::binary::decode::MAX_DEPTH;
+use crate::binary::input::{Input, IoInput, Reference, SliceInput};
+use crate::error::{Error, Result};
+use crate::serde::de::array_enum;
+use crate::serde::{BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN, VALUE_TOKEN};
+use crate::Tag;
+
+/// Deserializes `T` from binary NBT read from `reader`, without building an
+/// intermediate [`Compound`](crate::Compound). The root name is discarded.
+pub fn from_reader<R, T>(reader: R) -> Result<T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    Deserializer::new(IoInput(reader)).deserialize_root()
+}
+
+/// Deserializes `T` from binary NBT stored in `slice`.
+///
+/// Strings and byte arrays are borrowed from `slice` when possible, so `T`
+/// may contain `&'de str` or `Cow<'de, str>` fields.
+pub fn from_slice<'de, T>(slice: &'de [u8]) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    Deserializer::new(SliceInput(slice)).deserialize_root()
+}
+
+struct Deserializer<I> {
+    input: I,
+    scratch: Vec<u8>,
+    depth: usize,
+}
+
+impl<'de, I: Input<'de>> Deserializer<I> {
+    fn new(input: I) -> Self {
+        Deserializer {
+            input,
+            scratch: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn deserialize_root<T>(mut self) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        let root_tag = self.read_tag()?;
+        if root_tag != Tag::Compound {
+            return Err(Error::RootTagNotCompound(root_tag));
+        }
+        self.skip_string()?;
+
+        T::deserialize(PayloadDeserializer {
+            de: &mut self,
+            tag: Tag::Compound,
+        })
+    }
+
+    #[inline]
+    fn check_depth<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::RecursionLimitExceeded);
+        }
+
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
+    fn read_tag(&mut self) -> Result<Tag> {
+        self.input.read_u8()?.try_into()
+    }
+
+    fn read_len(&mut self) -> Result<usize> {
+        let len = self.input.read_i32::<BigEndian>()?;
+        if len.is_negative() {
+            return Err(Error::InvalidArrayLength(len));
+        }
+        Ok(len as usize)
+    }
+
+    fn deserialize_string<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let len = self.input.read_u16::<BigEndian>()? as usize;
+        match self.input.read_slice(len, &mut self.scratch)? {
+            Reference::Borrowed(bytes) => match cesu8::from_java_cesu8(bytes)? {
+                Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
+                Cow::Owned(string) => visitor.visit_string(string),
+            },
+            Reference::Copied(bytes) => match cesu8::from_java_cesu8(bytes)? {
+                Cow::Borrowed(str) => visitor.visit_str(str),
+                Cow::Owned(string) => visitor.visit_string(string),
+            },
+        }
+    }
+
+    fn skip_string(&mut self) -> Result<()> {
+        let len = self.input.read_u16::<BigEndian>()?;
+        self.input.skip(len as u64)
+    }
+
+    fn skip_payload(&mut self, tag: Tag) -> Result<()> {
+        match tag {
+            Tag::End => Err(Error::EndTagInValue),
+            Tag::String => self.skip_string(),
+            Tag::ByteArray | Tag::IntArray | Tag::LongArray => {
+                let len = self.read_len()? as u64;
+                self.input.skip(len * fixed_size(array_element_tag(tag)))
+            }
+            Tag::List => self.check_depth(|de| {
+                let tag = de.read_tag()?;
+                let len = de.read_len()?;
+                if tag == Tag::End {
+                    return match len {
+                        0 => Ok(()),
+                        len => Err(Error::TagEndListWithNonZeroLength(len as i32)),
+                    };
+                }
+                match fixed_size(tag) {
+                    0 => (0..len).try_for_each(|_| de.skip_payload(tag)),
+                    size => de.input.skip(len as u64 * size),
+                }
+            }),
+            Tag::Compound => self.check_depth(|de| loop {
+                let tag = de.read_tag()?;
+                if tag == Tag::End {
+                    return Ok(());
+                }
+                de.skip_string()?;
+                de.skip_payload(tag)?;
+            }),
+            _ => self.input.skip(fixed_size(tag)),
+        }
+    }
+}
+
+/// Size in bytes of a payload of the given type, or `0` if it's variable.
+fn fixed_size(tag: Tag) -> u64 {
+    match tag {
+        Tag::Byte => 1,
+        Tag::Short => 2,
+        Tag::Int | Tag::Float => 4,
+        Tag::Long | Tag::Double => 8,
+        _ => 0,
+    }
+}
+
+fn array_element_tag(tag: Tag) -> Tag {
+    match tag {
+        Tag::ByteArray => Tag::Byte,
+        Tag::IntArray => Tag::Int,
+        _ => Tag::Long,
+    }
+}
+
+/// [`Deserializer`](de::Deserializer) for a payload whose tag has already
+/// been read.
+struct PayloadDeserializer<'a, I> {
+    de: &'a mut Deserializer<I>,
+    tag: Tag,
+}
+
+impl<'de, 'a, I: Input<'de>> PayloadDeserializer<'a, I> {
+    fn visit_list<V>(self, tag: Tag, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.check_depth(|de| {
+            let mut access = ListAccess {
+                de,
+                tag,
+                remaining: len,
+            };
+            let value = visitor.visit_seq(&mut access)?;
+            // Keep the input in sync if the visitor stopped early.
+            while access.remaining > 0 {
+                access.remaining -= 1;
+                access.de.skip_payload(tag)?;
+            }
+            Ok(value)
+        })
+    }
+}
+
+impl<'de, 'a, I: Input<'de>> de::Deserializer<'de> for PayloadDeserializer<'a, I> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let input = &mut self.de.input;
+        match self.tag {
+            Tag::End => Err(Error::EndTagInValue),
+            Tag::Byte => visitor.visit_i8(input.read_i8()?),
+            Tag::Short => visitor.visit_i16(input.read_i16::<BigEndian>()?),
+            Tag::Int => visitor.visit_i32(input.read_i32::<BigEndian>()?),
+            Tag::Long => visitor.visit_i64(input.read_i64::<BigEndian>()?),
+            Tag::Float => visitor.visit_f32(input.read_f32::<BigEndian>()?),
+            Tag::Double => visitor.visit_f64(input.read_f64::<BigEndian>()?),
+            Tag::String => self.de.deserialize_string(visitor),
+            Tag::ByteArray | Tag::IntArray | Tag::LongArray => {
+                let tag = array_element_tag(self.tag);
+                let len = self.de.read_len()?;
+                self.visit_list(tag, len, visitor)
+            }
+            Tag::List => {
+                let tag = self.de.read_tag()?;
+                let len = self.de.read_len()?;
+                if tag == Tag::End && len != 0 {
+                    return Err(Error::TagEndListWithNonZeroLength(len as i32));
+                }
+                self.visit_list(tag, len, visitor)
+            }
+            Tag::Compound => self.de.check_depth(|de| {
+                let mut access = CompoundAccess {
+                    de,
+                    tag: None,
+                    done: false,
+                };
+                let value = visitor.visit_map(&mut access)?;
+                // Keep the input in sync if the visitor stopped early.
+                if let Some(tag) = access.tag.take() {
+                    access.de.skip_payload(tag)?;
+                }
+                while let Some(tag) = access.next_tag()? {
+                    access.de.skip_string()?;
+                    access.de.skip_payload(tag)?;
+                }
+                Ok(value)
+            }),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.tag {
+            Tag::Byte => visitor.visit_bool(self.de.input.read_i8()? != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.tag {
+            Tag::ByteArray => {
+                let len = self.de.read_len()?;
+                match self.de.input.read_slice(len, &mut self.de.scratch)? {
+                    Reference::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                    Reference::Copied(bytes) => visitor.visit_bytes(bytes),
+                }
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // Arrays are told apart from lists for `Value`, see `array_enum`.
+        match (name, self.tag) {
+            (VALUE_TOKEN, Tag::ByteArray) => {
+                array_enum(BYTE_ARRAY_TOKEN, Vec::<i8>::deserialize(self)?, visitor)
+            }
+            (VALUE_TOKEN, Tag::IntArray) => {
+                array_enum(INT_ARRAY_TOKEN, Vec::<i32>::deserialize(self)?, visitor)
+            }
+            (VALUE_TOKEN, Tag::LongArray) => {
+                array_enum(LONG_ARRAY_TOKEN, Vec::<i64>::deserialize(self)?, visitor)
+            }
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.tag {
+            Tag::String => {
+                let variant = self.de.deserialize_string(StringVisitor)?;
+                visitor.visit_enum(variant.into_deserializer()) // Unit variant.
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.de.skip_payload(self.tag)?;
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct StringVisitor;
+
+impl<'de> Visitor<'de> for StringVisitor {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "a string")
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(v.to_owned())
+    }
+
+    fn visit_string<E>(self, v: String) -> std::result::Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(v)
+    }
+}
+
+struct ListAccess<'a, I> {
+    de: &'a mut Deserializer<I>,
+    tag: Tag,
+    remaining: usize,
+}
+
+impl<'de, 'a, I: Input<'de>> de::SeqAccess<'de> for ListAccess<'a, I> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        seed.deserialize(PayloadDeserializer {
+            de: &mut *self.de,
+            tag: self.tag,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+struct CompoundAccess<'a, I> {
+    de: &'a mut Deserializer<I>,
+    /// Tag of the entry whose key was read but whose value wasn't yet.
+    tag: Option<Tag>,
+    /// Whether the closing `TAG_End` was read.
+    done: bool,
+}
+
+impl<'de, 'a, I: Input<'de>> CompoundAccess<'a, I> {
+    fn next_tag(&mut self) -> Result<Option<Tag>> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.de.read_tag()? {
+            Tag::End => {
+                self.done = true;
+                Ok(None)
+            }
+            tag => Ok(Some(tag)),
+        }
+    }
+}
+
+impl<'de, 'a, I: Input<'de>> de::MapAccess<'de> for CompoundAccess<'a, I> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if let Some(tag) = self.tag.take() {
+            // The previous value was never requested.
+            self.de.skip_payload(tag)?;
+        }
+
+        let Some(tag) = self.next_tag()? else {
+            return Ok(None);
+        };
+        self.tag = Some(tag);
+
+        seed.deserialize(PayloadDeserializer {
+            de: &mut *self.de,
+            tag: Tag::String,
+        })
+        .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let tag = self
+            .tag
+            .take()
+            .expect("missing previous call to `next_key_seed`");
+
+        seed.deserialize(PayloadDeserializer {
+            de: &mut *self.de,
+            tag,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::binary::decode::from_binary;
+    use crate::binary::encode::to_binary;
+    use crate::{from_reader, from_slice, to_writer, Compound, List, Value};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Player<'a> {
+        #[serde(rename = "DataVersion")]
+        data_version: i32,
+        #[serde(borrow)]
+        name: Cow<'a, str>,
+        health: f32,
+        on_ground: bool,
+        pos: Vec<f64>,
+        #[serde(with = "crate::serde::int_array")]
+        uuid: Vec<i32>,
+        #[serde(with = "crate::serde::long_array")]
+        packed: Vec<i64>,
+        #[serde(with = "crate::serde::byte_array")]
+        bytes: Vec<i8>,
+        inventory: Vec<Item>,
+        empty: Vec<Item>,
+        attributes: BTreeMap<String, f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        spawn: Option<i64>,
+        missing: Option<String>,
+        mode: GameMode,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        id: String,
+        #[serde(rename = "Count")]
+        count: i8,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum GameMode {
+        Survival,
+        Creative,
+    }
+
+    fn example_player() -> Player<'static> {
+        Player {
+            data_version: 3700,
+            name: Cow::Borrowed("Notch"),
+            health: 20.0,
+            on_ground: true,
+            pos: vec![0.5, 64.0, -0.5],
+            uuid: vec![1, 2, 3, 4],
+            packed: vec![i64::MIN, 0, i64::MAX],
+            bytes: vec![-1, 0, 1],
+            inventory: vec![
+                Item {
+                    id: "minecraft:stone".to_owned(),
+                    count: 64,
+                },
+                Item {
+                    id: "minecraft:dirt".to_owned(),
+                    count: 1,
+                },
+            ],
+            empty: Vec::new(),
+            attributes: BTreeMap::from([("speed".to_owned(), 0.1)]),
+            spawn: None,
+            missing: None,
+            mode: GameMode::Creative,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let player = example_player();
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, "", &player).unwrap();
+
+        assert_eq!(from_slice::<Player>(&buf).unwrap(), player);
+
+        let compound = from_reader::<_, Compound>(buf.as_slice()).unwrap();
+        assert_eq!(
+            compound.get("name"),
+            Some(&Value::String("Notch".to_owned()))
+        );
+    }
+
+    #[test]
+    fn matches_compound_encoding() {
+        let mut buf = Vec::new();
+        to_writer(&mut buf, "player", &example_player()).unwrap();
+
+        let (compound, root_name) = from_binary::<_, String>(buf.as_slice()).unwrap();
+        assert_eq!(root_name, "player");
+        assert_eq!(compound.get("DataVersion"), Some(&Value::Int(3700)));
+        assert_eq!(compound.get("on_ground"), Some(&Value::Byte(1)));
+        assert_eq!(
+            compound.get("uuid"),
+            Some(&Value::IntArray(vec![1, 2, 3, 4]))
+        );
+        assert_eq!(
+            compound.get("bytes"),
+            Some(&Value::ByteArray(vec![-1, 0, 1]))
+        );
+        assert_eq!(compound.get("empty"), Some(&Value::List(List::End)));
+        assert_eq!(
+            compound.get("pos"),
+            Some(&Value::List(List::Double(vec![0.5, 64.0, -0.5])))
+        );
+        assert_eq!(
+            compound.get("mode"),
+            Some(&Value::String("Creative".to_owned()))
+        );
+        assert!(compound.get("spawn").is_none());
+        assert!(compound.get("missing").is_none());
+
+        let mut reencoded = Vec::new();
+        to_binary(&compound, &root_name, &mut reencoded).unwrap();
+        assert_eq!(from_slice::<Player>(&reencoded).unwrap(), example_player());
+    }
+
+    #[test]
+    fn borrows_from_slice() {
+        let mut buf = Vec::new();
+        to_writer(&mut buf, "", &example_player()).unwrap();
+
+        let player = from_slice::<Player>(&buf).unwrap();
+        assert!(matches!(player.name, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn skips_unknown_fields() {
+        #[derive(Deserialize)]
+        struct Partial {
+            health: f32,
+            mode: GameMode,
+        }
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, "", &example_player()).unwrap();
+
+        let partial = from_slice::<Partial>(&buf).unwrap();
+        assert_eq!(partial.health, 20.0);
+        assert_eq!(partial.mode, GameMode::Creative);
+    }
+
+    #[test]
+    fn value_round_trip() {
+        let mut compound = Compound::new();
+        compound.insert("long_array", vec![1i64, 2, 3]);
+        compound.insert("int_array", vec![1i32, 2, 3]);
+        compound.insert("list", List::Long(vec![1, 2, 3]));
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, "", &compound).unwrap();
+
+        let (decoded, _) = from_binary::<_, String>(buf.as_slice()).unwrap();
+        assert_eq!(decoded, compound);
+    }
+
+    #[test]
+    fn compound_keeps_arrays() {
+        let mut compound = Compound::new();
+        compound.insert("bytes", Value::ByteArray(vec![-1, 0, 1]));
+        compound.insert("ints", Value::IntArray(vec![1, 2, 3]));
+        compound.insert("longs", Value::LongArray(vec![i64::MIN, i64::MAX]));
+        compound.insert("empty", Value::IntArray(Vec::new()));
+        compound.insert("list", List::Int(vec![1, 2, 3]));
+
+        let mut buf = Vec::new();
+        to_binary(&compound, "", &mut buf).unwrap();
+
+        let decoded = from_slice::<Compound>(&buf).unwrap();
+        assert_eq!(decoded, compound);
+        assert_eq!(
+            from_reader::<_, Compound>(buf.as_slice()).unwrap(),
+            compound
+        );
+
+        let mut reencoded = Vec::new();
+        to_binary(&decoded, "", &mut reencoded).unwrap();
+        assert_eq!(reencoded, buf);
+    }
+
+    #[test]
+    fn root_must_be_compound() {
+        let mut buf = Vec::new();
+        assert!(to_writer(&mut buf, "", &1i32).is_err());
+        assert!(from_slice::<i32>(&[3, 0, 0, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn heterogeneous_list() {
+        #[derive(Serialize)]
+        struct Mixed {
+            list: Vec<Value>,
+        }
+
+        let mixed = Mixed {
+            list: vec![Value::Int(1), Value::Byte(2)],
+        };
+        let mut buf = Vec::new();
+        assert!(to_writer(&mut buf, "", &mixed).is_err());
+    }
+}
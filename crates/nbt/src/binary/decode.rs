@@ -1,44 +1,180 @@
+use std::borrow::Cow;
 use std::hash::Hash;
 use std::io::Read;
 
 use byteorder::{BigEndian, ReadBytesExt};
 
+use crate::binary::input::{Input, IoInput, Reference, SliceInput};
 use crate::error::{Error, Result};
 use crate::{Compound, List, Tag, Value};
 
-pub fn from_binary<R, S>(slice: R) -> Result<(Compound<S>, S)>
+/// Reads a named root compound, returning it together with its name.
+///
+/// Every string is copied out of `reader`. Use [`from_binary_slice`] to
+/// borrow strings from a byte slice instead.
+pub fn from_binary<'de, R, S>(reader: R) -> Result<(Compound<S>, S)>
 where
     R: Read,
-    S: FromModifiedUtf8 + Hash + Ord,
+    S: FromModifiedUtf8<'de> + Hash + Ord,
 {
-    let mut state = DecodeState::new(slice);
-    let root_tag = state.read_tag()?;
+    DecodeState::new(IoInput(reader)).read_root()
+}
 
-    if root_tag != Tag::Compound {
-        return Err(Error::RootTagNotCompound(root_tag));
-    }
+/// Reads a named root compound from `slice`.
+///
+/// With `S = Cow<'de, str>`, strings that are already valid UTF-8 are
+/// borrowed from `slice` rather than copied.
+pub fn from_binary_slice<'de, S>(slice: &'de [u8]) -> Result<(Compound<S>, S)>
+where
+    S: FromModifiedUtf8<'de> + Hash + Ord,
+{
+    DecodeState::new(SliceInput(slice)).read_root()
+}
 
-    let root_name = state.read_string::<S>()?;
-    let root_value = state.read_compound()?;
+/// Reads a compound in the network format used by the protocol since 1.20.2,
+/// where the root tag is not followed by a name.
+pub fn from_network_binary<'de, R, S>(reader: R) -> Result<Compound<S>>
+where
+    R: Read,
+    S: FromModifiedUtf8<'de> + Hash + Ord,
+{
+    from_network_binary_with_limits(reader, NbtReadLimits::default())
+}
 
-    Ok((root_value, root_name))
+/// Like [`from_network_binary`], but fails as soon as the input exceeds
+/// `limits`.
+pub fn from_network_binary_with_limits<'de, R, S>(
+    reader: R,
+    limits: NbtReadLimits,
+) -> Result<Compound<S>>
+where
+    R: Read,
+    S: FromModifiedUtf8<'de> + Hash + Ord,
+{
+    match value_from_network_binary_with_limits(reader, limits)? {
+        Some(Value::Compound(compound)) => Ok(compound),
+        Some(value) => Err(Error::RootTagNotCompound(value.tag())),
+        None => Err(Error::RootTagNotCompound(Tag::End)),
+    }
 }
 
-const MAX_DEPTH: usize = 512;
+/// Reads a nameless root value of any type in the network format.
+///
+/// A lone `TAG_End` is how the protocol encodes absent NBT (e.g. an item
+/// without tags), and is returned as `None`.
+pub fn value_from_network_binary<'de, R, S>(reader: R) -> Result<Option<Value<S>>>
+where
+    R: Read,
+    S: FromModifiedUtf8<'de> + Hash + Ord,
+{
+    value_from_network_binary_with_limits(reader, NbtReadLimits::default())
+}
 
-struct DecodeState<R: Read> {
+/// Like [`value_from_network_binary`], but fails as soon as the input
+/// exceeds `limits`.
+pub fn value_from_network_binary_with_limits<'de, R, S>(
     reader: R,
+    limits: NbtReadLimits,
+) -> Result<Option<Value<S>>>
+where
+    R: Read,
+    S: FromModifiedUtf8<'de> + Hash + Ord,
+{
+    let mut state = DecodeState::with_limits(IoInput(reader), limits);
+    match state.read_tag()? {
+        Tag::End => Ok(None),
+        tag => state.read_value(tag).map(Some),
+    }
+}
+
+pub(crate) const MAX_DEPTH: usize = 512;
+
+/// Bounds on how much NBT a single read may consume, the equivalent of
+/// vanilla's `NbtAccounter`.
+///
+/// Sizes are checked before anything is allocated, so a length prefix
+/// claiming billions of elements fails immediately instead of exhausting
+/// memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NbtReadLimits {
+    /// Maximum number of bytes read, including skipped values.
+    pub max_bytes: u64,
+    /// Maximum nesting depth of lists and compounds.
+    pub max_depth: usize,
+    /// Maximum combined number of list elements, array elements and
+    /// compound entries.
+    pub max_elements: u64,
+}
+
+impl NbtReadLimits {
+    /// No limits besides the default nesting depth.
+    pub const UNLIMITED: Self = Self {
+        max_bytes: u64::MAX,
+        max_depth: MAX_DEPTH,
+        max_elements: u64::MAX,
+    };
+
+    /// Limits for NBT received from clients, matching the 2 MiB quota
+    /// vanilla applies to packets.
+    pub const fn network() -> Self {
+        Self {
+            max_bytes: 2 * 1024 * 1024,
+            max_depth: MAX_DEPTH,
+            max_elements: 512 * 1024,
+        }
+    }
+}
+
+impl Default for NbtReadLimits {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+pub(crate) struct DecodeState<I> {
+    pub(crate) reader: I,
+    scratch: Vec<u8>,
     depth: usize,
+    limits: NbtReadLimits,
+    bytes: u64,
+    elements: u64,
 }
 
-impl<R: Read> DecodeState<R> {
-    fn new(reader: R) -> Self {
-        DecodeState { reader, depth: 0 }
+impl<'de, I: Input<'de>> DecodeState<I> {
+    fn new(reader: I) -> Self {
+        Self::with_limits(reader, NbtReadLimits::default())
+    }
+
+    pub(crate) fn with_limits(reader: I, limits: NbtReadLimits) -> Self {
+        DecodeState {
+            reader,
+            scratch: Vec::new(),
+            depth: 0,
+            limits,
+            bytes: 0,
+            elements: 0,
+        }
+    }
+
+    fn read_root<S>(&mut self) -> Result<(Compound<S>, S)>
+    where
+        S: FromModifiedUtf8<'de> + Hash + Ord,
+    {
+        let root_tag = self.read_tag()?;
+
+        if root_tag != Tag::Compound {
+            return Err(Error::RootTagNotCompound(root_tag));
+        }
+
+        let root_name = self.read_string::<S>()?;
+        let root_value = self.check_depth(|st| st.read_compound())?;
+
+        Ok((root_value, root_name))
     }
 
     #[inline]
-    fn check_depth<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
-        if self.depth >= MAX_DEPTH {
+    pub(crate) fn check_depth<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.limits.max_depth {
             return Err(Error::RecursionLimitExceeded);
         }
 
@@ -48,13 +184,47 @@ impl<R: Read> DecodeState<R> {
         res
     }
 
-    fn read_tag(&mut self) -> Result<Tag> {
+    /// Charges `len` bytes against the size limit before they are read.
+    pub(crate) fn account_bytes(&mut self, len: u64) -> Result<()> {
+        self.bytes = self.bytes.saturating_add(len);
+        if self.bytes > self.limits.max_bytes {
+            return Err(Error::SizeLimitExceeded(self.limits.max_bytes));
+        }
+        Ok(())
+    }
+
+    /// Charges `len` list, array or compound elements against the element
+    /// limit.
+    pub(crate) fn account_elements(&mut self, len: u64) -> Result<()> {
+        self.elements = self.elements.saturating_add(len);
+        if self.elements > self.limits.max_elements {
+            return Err(Error::ElementLimitExceeded(self.limits.max_elements));
+        }
+        Ok(())
+    }
+
+    /// Reads a list or array length of elements that take at least
+    /// `min_size` bytes each, failing if they cannot fit in the limits.
+    pub(crate) fn read_len(&mut self, min_size: u64) -> Result<usize> {
+        let len = self.read_int()?;
+        if len.is_negative() {
+            return Err(Error::InvalidArrayLength(len));
+        }
+        self.account_elements(len as u64)?;
+        if self.bytes.saturating_add(len as u64 * min_size) > self.limits.max_bytes {
+            return Err(Error::SizeLimitExceeded(self.limits.max_bytes));
+        }
+        Ok(len as usize)
+    }
+
+    pub(crate) fn read_tag(&mut self) -> Result<Tag> {
+        self.account_bytes(1)?;
         self.reader.read_u8()?.try_into()
     }
 
     fn read_value<S>(&mut self, tag: Tag) -> Result<Value<S>>
     where
-        S: FromModifiedUtf8 + Hash + Ord,
+        S: FromModifiedUtf8<'de> + Hash + Ord,
     {
         match tag {
             Tag::Byte => Ok(self.read_byte()?.into()),
@@ -73,67 +243,82 @@ impl<R: Read> DecodeState<R> {
         }
     }
 
-    fn read_byte(&mut self) -> Result<i8> {
+    pub(crate) fn read_byte(&mut self) -> Result<i8> {
+        self.account_bytes(1)?;
         Ok(self.reader.read_i8()?)
     }
 
-    fn read_short(&mut self) -> Result<i16> {
+    pub(crate) fn read_short(&mut self) -> Result<i16> {
+        self.account_bytes(2)?;
         Ok(self.reader.read_i16::<BigEndian>()?)
     }
 
-    fn read_int(&mut self) -> Result<i32> {
+    pub(crate) fn read_int(&mut self) -> Result<i32> {
+        self.account_bytes(4)?;
         Ok(self.reader.read_i32::<BigEndian>()?)
     }
 
-    fn read_long(&mut self) -> Result<i64> {
+    pub(crate) fn read_long(&mut self) -> Result<i64> {
+        self.account_bytes(8)?;
         Ok(self.reader.read_i64::<BigEndian>()?)
     }
 
-    fn read_float(&mut self) -> Result<f32> {
+    pub(crate) fn read_float(&mut self) -> Result<f32> {
+        self.account_bytes(4)?;
         Ok(self.reader.read_f32::<BigEndian>()?)
     }
 
-    fn read_double(&mut self) -> Result<f64> {
+    pub(crate) fn read_double(&mut self) -> Result<f64> {
+        self.account_bytes(8)?;
         Ok(self.reader.read_f64::<BigEndian>()?)
     }
 
-    fn read_byte_array(&mut self) -> Result<Vec<i8>> {
-        let len = self.read_int()?;
-        let mut buf = vec![0i8; len as usize];
+    pub(crate) fn read_byte_array(&mut self) -> Result<Vec<i8>> {
+        let len = self.read_len(1)?;
+        self.account_bytes(len as u64)?;
+        let mut buf = vec![0i8; len];
         self.reader.read_i8_into(&mut buf)?;
         Ok(buf)
     }
 
-    fn read_string<S>(&mut self) -> Result<S>
+    pub(crate) fn read_string<S>(&mut self) -> Result<S>
     where
-        S: FromModifiedUtf8,
+        S: FromModifiedUtf8<'de>,
     {
-        let len = self.read_short()?;
-        let mut buf = vec![0u8; len as usize];
-        self.reader.read_exact(&mut buf)?;
-
-        S::from_modified_utf8(&buf)
+        self.account_bytes(2)?;
+        let len = self.reader.read_u16::<BigEndian>()?;
+        self.account_bytes(len as u64)?;
+        match self.reader.read_slice(len as usize, &mut self.scratch)? {
+            Reference::Borrowed(bytes) => S::from_borrowed_modified_utf8(bytes),
+            Reference::Copied(bytes) => S::from_modified_utf8(bytes),
+        }
     }
 
     fn read_any_list<S>(&mut self) -> Result<List<S>>
     where
-        S: FromModifiedUtf8 + Hash + Ord,
+        S: FromModifiedUtf8<'de> + Hash + Ord,
     {
-        match self.read_tag()? {
-            Tag::Byte => Ok(self.read_list(|s| s.read_byte())?.into()),
-            Tag::Short => Ok(self.read_list(|s| s.read_short())?.into()),
-            Tag::Int => Ok(self.read_list(|s| s.read_int())?.into()),
-            Tag::Long => Ok(self.read_list(|s| s.read_long())?.into()),
-            Tag::Float => Ok(self.read_list(|s| s.read_float())?.into()),
-            Tag::Double => Ok(self.read_list(|s| s.read_double())?.into()),
-            Tag::ByteArray => Ok(self.read_list(|s| s.read_byte_array())?.into()),
-            Tag::String => Ok(List::String(self.read_list(|s| s.read_string::<S>())?)),
-            Tag::List => self.check_depth(|s| Ok(s.read_list(|s| s.read_any_list::<S>())?.into())),
+        let tag = self.read_tag()?;
+        let min_size = min_payload_size(tag);
+        match tag {
+            Tag::Byte => Ok(self.read_list(min_size, |s| s.read_byte())?.into()),
+            Tag::Short => Ok(self.read_list(min_size, |s| s.read_short())?.into()),
+            Tag::Int => Ok(self.read_list(min_size, |s| s.read_int())?.into()),
+            Tag::Long => Ok(self.read_list(min_size, |s| s.read_long())?.into()),
+            Tag::Float => Ok(self.read_list(min_size, |s| s.read_float())?.into()),
+            Tag::Double => Ok(self.read_list(min_size, |s| s.read_double())?.into()),
+            Tag::ByteArray => Ok(self.read_list(min_size, |s| s.read_byte_array())?.into()),
+            Tag::String => Ok(List::String(
+                self.read_list(min_size, |s| s.read_string::<S>())?,
+            )),
+            Tag::List => {
+                self.check_depth(|s| Ok(s.read_list(min_size, |s| s.read_any_list::<S>())?.into()))
+            }
             Tag::Compound => {
-                self.check_depth(|s| Ok(s.read_list(|s| s.read_compound::<S>())?.into()))
+                self.check_depth(|s| Ok(s.read_list(min_size, |s| s.read_compound::<S>())?.into()))
             }
-            Tag::IntArray => Ok(self.read_list(|s| s.read_int_array())?.into()),
-            Tag::LongArray => Ok(self.read_list(|s| s.read_long_array())?.into()),
+            Tag::IntArray => Ok(self.read_list(min_size, |s| s.read_int_array())?.into()),
+            Tag::LongArray => Ok(self.read_list(min_size, |s| s.read_long_array())?.into()),
             Tag::End => match self.read_int()? {
                 0 => Ok(List::End),
                 len => Err(Error::TagEndListWithNonZeroLength(len)),
@@ -141,15 +326,12 @@ impl<R: Read> DecodeState<R> {
         }
     }
 
-    fn read_list<T, F>(&mut self, mut read_elem: F) -> Result<Vec<T>>
+    fn read_list<T, F>(&mut self, min_size: u64, mut read_elem: F) -> Result<Vec<T>>
     where
         F: FnMut(&mut Self) -> Result<T>,
     {
-        let len = self.read_int()?;
-        if len.is_negative() {
-            return Err(Error::InvalidArrayLength(len));
-        }
-        let mut buf = Vec::with_capacity(len as usize);
+        let len = self.read_len(min_size)?;
+        let mut buf = Vec::with_capacity(len);
         for _ in 0..len {
             buf.push(read_elem(self)?);
         }
@@ -158,7 +340,7 @@ impl<R: Read> DecodeState<R> {
 
     fn read_compound<S>(&mut self) -> Result<Compound<S>>
     where
-        S: FromModifiedUtf8 + Hash + Ord,
+        S: FromModifiedUtf8<'de> + Hash + Ord,
     {
         let mut compound = Compound::new();
         loop {
@@ -166,38 +348,57 @@ impl<R: Read> DecodeState<R> {
             if tag == Tag::End {
                 return Ok(compound);
             }
+            self.account_elements(1)?;
             let name = self.read_string::<S>()?;
             let value = self.read_value::<S>(tag)?;
             compound.insert(name, value);
         }
     }
 
-    fn read_int_array(&mut self) -> Result<Vec<i32>> {
-        let len = self.read_int()?;
-        if len.is_negative() {
-            return Err(Error::InvalidArrayLength(len));
-        }
-        let mut buf = vec![0i32; len as usize];
+    pub(crate) fn read_int_array(&mut self) -> Result<Vec<i32>> {
+        let len = self.read_len(4)?;
+        self.account_bytes(len as u64 * 4)?;
+        let mut buf = vec![0i32; len];
         self.reader.read_i32_into::<BigEndian>(&mut buf)?;
         Ok(buf)
     }
 
-    fn read_long_array(&mut self) -> Result<Vec<i64>> {
-        let len = self.read_int()?;
-        if len.is_negative() {
-            return Err(Error::InvalidArrayLength(len));
-        }
-        let mut buf = vec![0i64; len as usize];
+    pub(crate) fn read_long_array(&mut self) -> Result<Vec<i64>> {
+        let len = self.read_len(8)?;
+        self.account_bytes(len as u64 * 8)?;
+        let mut buf = vec![0i64; len];
         self.reader.read_i64_into::<BigEndian>(&mut buf)?;
         Ok(buf)
     }
 }
 
-pub trait FromModifiedUtf8: Sized {
+/// Smallest number of bytes the payload of a `tag` can take up.
+pub(crate) fn min_payload_size(tag: Tag) -> u64 {
+    match tag {
+        Tag::End => 0,
+        Tag::Byte | Tag::Compound => 1,
+        Tag::Short | Tag::String => 2,
+        Tag::Int | Tag::Float | Tag::ByteArray | Tag::IntArray | Tag::LongArray => 4,
+        Tag::Long | Tag::Double => 8,
+        Tag::List => 5,
+    }
+}
+
+/// String types that binary NBT strings can be decoded into.
+///
+/// The lifetime is that of the input being decoded, which allows types such
+/// as `Cow<'de, str>` to borrow from it.
+pub trait FromModifiedUtf8<'de>: Sized {
     fn from_modified_utf8(bytes: &[u8]) -> Result<Self>;
+
+    /// Called instead of [`from_modified_utf8`](Self::from_modified_utf8)
+    /// when `bytes` is borrowed from the input itself.
+    fn from_borrowed_modified_utf8(bytes: &'de [u8]) -> Result<Self> {
+        Self::from_modified_utf8(bytes)
+    }
 }
 
-impl FromModifiedUtf8 for String {
+impl FromModifiedUtf8<'_> for String {
     fn from_modified_utf8(bytes: &[u8]) -> Result<Self> {
         match cesu8::from_java_cesu8(bytes) {
             Ok(str) => Ok(str.into_owned()),
@@ -206,8 +407,176 @@ impl FromModifiedUtf8 for String {
     }
 }
 
-// impl<'de> FromModifiedUtf8 for Cow<'de, str> {
-//     fn from_modified_utf8(bytes: &[u8]) -> Result<Self> {
-//         cesu8::from_java_cesu8(bytes).map_err(move |e| Error::InvalidUtf8(e))
-//     }
-// }
+impl<'de> FromModifiedUtf8<'de> for Cow<'de, str> {
+    fn from_modified_utf8(bytes: &[u8]) -> Result<Self> {
+        String::from_modified_utf8(bytes).map(Cow::Owned)
+    }
+
+    fn from_borrowed_modified_utf8(bytes: &'de [u8]) -> Result<Self> {
+        Ok(cesu8::from_java_cesu8(bytes)?)
+    }
+}
+
+#[cfg(feature = "java_string")]
+impl FromModifiedUtf8<'_> for java_string::JavaString {
+    fn from_modified_utf8(bytes: &[u8]) -> Result<Self> {
+        Ok(java_string::JavaStr::from_modified_utf8(bytes)?.into_owned())
+    }
+}
+
+#[cfg(feature = "java_string")]
+impl<'de> FromModifiedUtf8<'de> for Cow<'de, java_string::JavaStr> {
+    fn from_modified_utf8(bytes: &[u8]) -> Result<Self> {
+        let string = java_string::JavaString::from_modified_utf8(bytes.to_vec())?;
+        Ok(Cow::Owned(string))
+    }
+
+    fn from_borrowed_modified_utf8(bytes: &'de [u8]) -> Result<Self> {
+        Ok(java_string::JavaStr::from_modified_utf8(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::binary::decode::{
+        from_binary, from_binary_slice, value_from_network_binary_with_limits, NbtReadLimits,
+    };
+    use crate::binary::encode::to_binary;
+    use crate::error::Error;
+    use crate::{Compound, List, Value};
+
+    fn encode(compound: &Compound) -> Vec<u8> {
+        let mut buf = Vec::new();
+        to_binary(compound, "root", &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn borrows_valid_utf8() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("plain", "hello");
+        compound.insert("list", List::String(vec!["a".to_owned(), "b".to_owned()]));
+        let buf = encode(&compound);
+
+        let (decoded, root_name) = from_binary_slice::<Cow<str>>(&buf).unwrap();
+
+        assert!(matches!(root_name, Cow::Borrowed("root")));
+        assert!(decoded.keys().all(|k| matches!(k, Cow::Borrowed(_))));
+        assert!(matches!(
+            decoded.get("plain"),
+            Some(Value::String(Cow::Borrowed("hello")))
+        ));
+        match decoded.get("list") {
+            Some(Value::List(List::String(v))) => {
+                assert!(v.iter().all(|s| matches!(s, Cow::Borrowed(_))))
+            }
+            v => panic!("unexpected value {v:?}"),
+        }
+    }
+
+    #[test]
+    fn copies_modified_utf8() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("nul", "\0");
+        compound.insert("emoji", "😀");
+        let buf = encode(&compound);
+
+        let (decoded, _) = from_binary_slice::<Cow<str>>(&buf).unwrap();
+
+        assert!(matches!(
+            decoded.get("nul"),
+            Some(Value::String(Cow::Owned(s))) if s == "\0"
+        ));
+        assert!(matches!(
+            decoded.get("emoji"),
+            Some(Value::String(Cow::Owned(s))) if s == "😀"
+        ));
+    }
+
+    #[cfg(feature = "java_string")]
+    #[test]
+    fn round_trips_unpaired_surrogates() {
+        use java_string::{JavaCodePoint, JavaStr, JavaString};
+
+        let mut surrogate = JavaString::from("a");
+        surrogate.push_java(JavaCodePoint::from_u32(0xD800).unwrap());
+        let mut compound: Compound<JavaString> = Compound::new();
+        compound.insert(JavaString::from("key"), Value::String(surrogate.clone()));
+        let mut buf = Vec::new();
+        to_binary(&compound, "root", &mut buf).unwrap();
+
+        let (decoded, _) = from_binary::<_, JavaString>(buf.as_slice()).unwrap();
+        assert_eq!(decoded, compound);
+
+        let (borrowed, _) = from_binary_slice::<Cow<JavaStr>>(&buf).unwrap();
+        assert!(matches!(
+            borrowed.get(JavaStr::from_str("key")),
+            Some(Value::String(Cow::Owned(s))) if *s == surrogate
+        ));
+        let mut reencoded = Vec::new();
+        to_binary(&borrowed, "root", &mut reencoded).unwrap();
+        assert_eq!(reencoded, buf);
+    }
+
+    #[test]
+    fn reader_input_is_owned() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("plain", "hello");
+        let buf = encode(&compound);
+
+        let (decoded, root_name) = from_binary::<_, Cow<str>>(buf.as_slice()).unwrap();
+
+        assert!(matches!(root_name, Cow::Owned(_)));
+        assert!(matches!(
+            decoded.get("plain"),
+            Some(Value::String(Cow::Owned(s))) if s == "hello"
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_array_before_allocating() {
+        // A nameless TAG_Int_Array claiming i32::MAX elements.
+        let mut buf = vec![11];
+        buf.extend_from_slice(&i32::MAX.to_be_bytes());
+
+        let res = value_from_network_binary_with_limits::<_, String>(
+            buf.as_slice(),
+            NbtReadLimits::network(),
+        );
+        assert!(matches!(res, Err(Error::ElementLimitExceeded(_))));
+
+        let limits = NbtReadLimits {
+            max_elements: u64::MAX,
+            ..NbtReadLimits::network()
+        };
+        let res = value_from_network_binary_with_limits::<_, String>(buf.as_slice(), limits);
+        assert!(matches!(res, Err(Error::SizeLimitExceeded(_))));
+    }
+
+    #[test]
+    fn enforces_depth_limit() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("nested", Compound::new());
+        let buf = encode(&compound);
+        let mut network = buf.clone();
+        // Drop the root name ("root") to get the network format.
+        network.drain(1..7);
+
+        let limits = NbtReadLimits {
+            max_depth: 1,
+            ..NbtReadLimits::default()
+        };
+        let res = value_from_network_binary_with_limits::<_, String>(network.as_slice(), limits);
+        assert!(matches!(res, Err(Error::RecursionLimitExceeded)));
+
+        let limits = NbtReadLimits {
+            max_depth: 2,
+            ..NbtReadLimits::default()
+        };
+        assert!(
+            value_from_network_binary_with_limits::<_, String>(network.as_slice(), limits).is_ok()
+        );
+    }
+}
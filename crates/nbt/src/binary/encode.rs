@@ -1 +1,436 @@
+use std::borrow::Cow;
+use std::hash::Hash;
+use std::io::Write;
 
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::conv::i8_slice_as_u8_slice;
+use crate::error::{Error, Result};
+use crate::{Compound, List, Tag, Value};
+
+pub fn to_binary<W, S, R>(compound: &Compound<S>, root_name: &R, writer: W) -> Result<()>
+where
+    W: Write,
+    S: ToModifiedUtf8 + Hash + Ord,
+    R: ToModifiedUtf8 + ?Sized,
+{
+    let mut state = EncodeState::new(writer);
+
+    state.write_tag(Tag::Compound)?;
+    state.write_string(root_name)?;
+    state.write_compound(compound)?;
+
+    Ok(())
+}
+
+/// Writes a compound in the network format used by the protocol since 1.20.2,
+/// where the root tag is not followed by a name.
+pub fn to_network_binary<W, S>(compound: &Compound<S>, writer: W) -> Result<()>
+where
+    W: Write,
+    S: ToModifiedUtf8 + Hash + Ord,
+{
+    let mut state = EncodeState::new(writer);
+
+    state.write_tag(Tag::Compound)?;
+    state.write_compound(compound)?;
+
+    Ok(())
+}
+
+/// Writes a nameless root value of any type in the network format. `None` is
+/// written as a lone `TAG_End`.
+pub fn value_to_network_binary<W, S>(value: Option<&Value<S>>, writer: W) -> Result<()>
+where
+    W: Write,
+    S: ToModifiedUtf8 + Hash + Ord,
+{
+    let mut state = EncodeState::new(writer);
+
+    match value {
+        Some(value) => {
+            state.write_tag(value.tag())?;
+            state.write_value(value)
+        }
+        None => state.write_tag(Tag::End),
+    }
+}
+
+pub(crate) struct EncodeState<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> EncodeState<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        EncodeState { writer }
+    }
+
+    pub(crate) fn write_tag(&mut self, tag: Tag) -> Result<()> {
+        Ok(self.writer.write_u8(tag.into())?)
+    }
+
+    fn write_value<S>(&mut self, value: &Value<S>) -> Result<()>
+    where
+        S: ToModifiedUtf8 + Hash + Ord,
+    {
+        match value {
+            Value::Byte(v) => self.write_byte(*v),
+            Value::Short(v) => self.write_short(*v),
+            Value::Int(v) => self.write_int(*v),
+            Value::Long(v) => self.write_long(*v),
+            Value::Float(v) => self.write_float(*v),
+            Value::Double(v) => self.write_double(*v),
+            Value::ByteArray(v) => self.write_byte_array(v),
+            Value::String(v) => self.write_string(v),
+            Value::List(v) => self.write_any_list(v),
+            Value::Compound(v) => self.write_compound(v),
+            Value::IntArray(v) => self.write_int_array(v),
+            Value::LongArray(v) => self.write_long_array(v),
+        }
+    }
+
+    pub(crate) fn write_byte(&mut self, v: i8) -> Result<()> {
+        Ok(self.writer.write_i8(v)?)
+    }
+
+    pub(crate) fn write_short(&mut self, v: i16) -> Result<()> {
+        Ok(self.writer.write_i16::<BigEndian>(v)?)
+    }
+
+    pub(crate) fn write_int(&mut self, v: i32) -> Result<()> {
+        Ok(self.writer.write_i32::<BigEndian>(v)?)
+    }
+
+    pub(crate) fn write_long(&mut self, v: i64) -> Result<()> {
+        Ok(self.writer.write_i64::<BigEndian>(v)?)
+    }
+
+    pub(crate) fn write_float(&mut self, v: f32) -> Result<()> {
+        Ok(self.writer.write_f32::<BigEndian>(v)?)
+    }
+
+    pub(crate) fn write_double(&mut self, v: f64) -> Result<()> {
+        Ok(self.writer.write_f64::<BigEndian>(v)?)
+    }
+
+    pub(crate) fn write_len(&mut self, len: usize) -> Result<()> {
+        match i32::try_from(len) {
+            Ok(len) => self.write_int(len),
+            Err(_) => Err(Error::LengthTooLarge(len)),
+        }
+    }
+
+    pub(crate) fn write_byte_array(&mut self, v: &[i8]) -> Result<()> {
+        self.write_len(v.len())?;
+        Ok(self.writer.write_all(i8_slice_as_u8_slice(v))?)
+    }
+
+    pub(crate) fn write_string<S>(&mut self, v: &S) -> Result<()>
+    where
+        S: ToModifiedUtf8 + ?Sized,
+    {
+        let bytes = v.to_modified_utf8();
+        match u16::try_from(bytes.len()) {
+            Ok(len) => self.writer.write_u16::<BigEndian>(len)?,
+            Err(_) => return Err(Error::StringTooLong(bytes.len())),
+        }
+        Ok(self.writer.write_all(&bytes)?)
+    }
+
+    fn write_any_list<S>(&mut self, list: &List<S>) -> Result<()>
+    where
+        S: ToModifiedUtf8 + Hash + Ord,
+    {
+        match list {
+            List::End => {
+                self.write_tag(Tag::End)?;
+                self.write_int(0)
+            }
+            List::Byte(v) => {
+                self.write_tag(Tag::Byte)?;
+                self.write_len(v.len())?;
+                Ok(self.writer.write_all(i8_slice_as_u8_slice(v))?)
+            }
+            List::Short(v) => self.write_list(Tag::Short, v, |s, v| s.write_short(*v)),
+            List::Int(v) => self.write_list(Tag::Int, v, |s, v| s.write_int(*v)),
+            List::Long(v) => self.write_list(Tag::Long, v, |s, v| s.write_long(*v)),
+            List::Float(v) => self.write_list(Tag::Float, v, |s, v| s.write_float(*v)),
+            List::Double(v) => self.write_list(Tag::Double, v, |s, v| s.write_double(*v)),
+            List::ByteArray(v) => self.write_list(Tag::ByteArray, v, |s, v| s.write_byte_array(v)),
+            List::String(v) => self.write_list(Tag::String, v, |s, v| s.write_string(v)),
+            List::List(v) => self.write_list(Tag::List, v, |s, v| s.write_any_list(v)),
+            List::Compound(v) => self.write_list(Tag::Compound, v, |s, v| s.write_compound(v)),
+            List::IntArray(v) => self.write_list(Tag::IntArray, v, |s, v| s.write_int_array(v)),
+            List::LongArray(v) => self.write_list(Tag::LongArray, v, |s, v| s.write_long_array(v)),
+        }
+    }
+
+    fn write_list<T, F>(&mut self, tag: Tag, list: &[T], mut write_elem: F) -> Result<()>
+    where
+        F: FnMut(&mut Self, &T) -> Result<()>,
+    {
+        self.write_tag(tag)?;
+        self.write_len(list.len())?;
+        for elem in list {
+            write_elem(self, elem)?;
+        }
+        Ok(())
+    }
+
+    fn write_compound<S>(&mut self, compound: &Compound<S>) -> Result<()>
+    where
+        S: ToModifiedUtf8 + Hash + Ord,
+    {
+        for (name, value) in compound {
+            self.write_tag(value.tag())?;
+            self.write_string(name)?;
+            self.write_value(value)?;
+        }
+        self.write_tag(Tag::End)
+    }
+
+    fn write_int_array(&mut self, v: &[i32]) -> Result<()> {
+        self.write_len(v.len())?;
+        for &i in v {
+            self.write_int(i)?;
+        }
+        Ok(())
+    }
+
+    fn write_long_array(&mut self, v: &[i64]) -> Result<()> {
+        self.write_len(v.len())?;
+        for &l in v {
+            self.write_long(l)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait ToModifiedUtf8 {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]>;
+}
+
+impl ToModifiedUtf8 for str {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]> {
+        cesu8::to_java_cesu8(self)
+    }
+}
+
+impl ToModifiedUtf8 for String {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]> {
+        cesu8::to_java_cesu8(self)
+    }
+}
+
+impl ToModifiedUtf8 for Cow<'_, str> {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]> {
+        cesu8::to_java_cesu8(self)
+    }
+}
+
+#[cfg(feature = "java_string")]
+impl ToModifiedUtf8 for java_string::JavaStr {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]> {
+        java_string::JavaStr::to_modified_utf8(self)
+    }
+}
+
+#[cfg(feature = "java_string")]
+impl ToModifiedUtf8 for java_string::JavaString {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]> {
+        java_string::JavaStr::to_modified_utf8(self)
+    }
+}
+
+#[cfg(feature = "java_string")]
+impl ToModifiedUtf8 for Cow<'_, java_string::JavaStr> {
+    fn to_modified_utf8(&self) -> Cow<'_, [u8]> {
+        java_string::JavaStr::to_modified_utf8(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::binary::decode::{from_binary, from_network_binary, value_from_network_binary};
+    use crate::binary::encode::{to_binary, to_network_binary, value_to_network_binary};
+    use crate::{Compound, List, Value};
+
+    fn example_compound() -> Compound {
+        let mut nested = Compound::new();
+        nested.insert("name", "Bananrama");
+        nested.insert("value", 0.5f32);
+
+        let mut compound = Compound::new();
+        compound.insert("byte", 127i8);
+        compound.insert("short", -32768i16);
+        compound.insert("int", 2147483647i32);
+        compound.insert("long", -9223372036854775808i64);
+        compound.insert("float", 0.49823147f32);
+        compound.insert("double", 0.4931287132182315f64);
+        compound.insert("byte_array", vec![0i8, 1, -1, 42]);
+        compound.insert("string", "HELLO WORLD THIS IS A TEST STRING ÅÄÖ! 😀");
+        compound.insert("int_array", vec![1i32, -2, 3]);
+        compound.insert("long_array", vec![1i64, -2, i64::MAX]);
+        compound.insert("compound", nested.clone());
+        compound.insert("empty_list", List::End);
+        compound.insert("byte_list", List::Byte(vec![1, 2, 3]));
+        compound.insert("short_list", List::Short(vec![-1, 2]));
+        compound.insert("int_list", List::Int(vec![7]));
+        compound.insert("long_list", List::Long(vec![11, 12]));
+        compound.insert("float_list", List::Float(vec![0.5, -0.25]));
+        compound.insert("double_list", List::Double(vec![1.5]));
+        compound.insert("byte_array_list", List::ByteArray(vec![vec![1], vec![]]));
+        compound.insert(
+            "string_list",
+            List::String(vec!["a".to_owned(), "\0".to_owned()]),
+        );
+        compound.insert(
+            "list_list",
+            List::List(vec![List::End, List::Int(vec![1, 2])]),
+        );
+        compound.insert(
+            "compound_list",
+            List::Compound(vec![nested.clone(), nested]),
+        );
+        compound.insert("int_array_list", List::IntArray(vec![vec![1, 2], vec![3]]));
+        compound.insert("long_array_list", List::LongArray(vec![vec![4], vec![]]));
+        compound
+    }
+
+    #[test]
+    fn round_trip() {
+        let compound = example_compound();
+
+        let mut buf = Vec::new();
+        to_binary(&compound, "root", &mut buf).unwrap();
+
+        let (decoded, root_name) = from_binary::<_, String>(buf.as_slice()).unwrap();
+
+        assert_eq!(root_name, "root");
+        assert_eq!(decoded, compound);
+    }
+
+    #[test]
+    fn round_trip_bytes() {
+        let compound = example_compound();
+
+        let mut first = Vec::new();
+        to_binary(&compound, "", &mut first).unwrap();
+
+        let (decoded, root_name) = from_binary::<_, String>(first.as_slice()).unwrap();
+
+        let mut second = Vec::new();
+        to_binary(&decoded, &root_name, &mut second).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn exact_encoding() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("a", 1i8);
+        compound.insert("b", List::End);
+
+        let mut buf = Vec::new();
+        to_binary(&compound, "hi", &mut buf).unwrap();
+
+        #[rustfmt::skip]
+        let expected = [
+            10, 0, 2, b'h', b'i',
+            1, 0, 1, b'a', 1,
+            9, 0, 1, b'b', 0, 0, 0, 0, 0,
+            0,
+        ];
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn modified_utf8() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("nul", "\0");
+        compound.insert("emoji", "😀");
+
+        let mut buf = Vec::new();
+        to_binary(&compound, "", &mut buf).unwrap();
+
+        // NUL is written as the two-byte sequence 0xC0 0x80.
+        assert!(buf.windows(4).any(|w| w == [0, 2, 0xC0, 0x80]));
+        // Supplementary characters are written as a surrogate pair of 3 bytes each.
+        assert!(buf
+            .windows(8)
+            .any(|w| w == [0, 6, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]));
+
+        let (decoded, _) = from_binary::<_, String>(buf.as_slice()).unwrap();
+        assert_eq!(decoded.get("nul"), Some(&Value::String("\0".to_owned())));
+        assert_eq!(decoded.get("emoji"), Some(&Value::String("😀".to_owned())));
+    }
+
+    #[test]
+    fn string_too_long() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("s", "a".repeat(u16::MAX as usize + 1));
+
+        let mut buf = Vec::new();
+        assert!(to_binary(&compound, "", &mut buf).is_err());
+    }
+
+    #[test]
+    fn max_length_string() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("s", "a".repeat(u16::MAX as usize));
+
+        let mut buf = Vec::new();
+        to_binary(&compound, "", &mut buf).unwrap();
+
+        let (decoded, _) = from_binary::<_, String>(buf.as_slice()).unwrap();
+        assert_eq!(decoded, compound);
+    }
+
+    #[test]
+    fn network_round_trip() {
+        let compound = example_compound();
+
+        let mut buf = Vec::new();
+        to_network_binary(&compound, &mut buf).unwrap();
+
+        let decoded = from_network_binary::<_, String>(buf.as_slice()).unwrap();
+        assert_eq!(decoded, compound);
+    }
+
+    #[test]
+    fn network_has_no_root_name() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("a", 1i8);
+
+        let mut named = Vec::new();
+        to_binary(&compound, "", &mut named).unwrap();
+
+        let mut network = Vec::new();
+        to_network_binary(&compound, &mut network).unwrap();
+
+        assert_eq!(network, [10, 1, 0, 1, b'a', 1, 0]);
+        // The named format only differs by the empty root name's length prefix.
+        assert_eq!(&named[..1], &network[..1]);
+        assert_eq!(&named[3..], &network[1..]);
+    }
+
+    #[test]
+    fn network_values() {
+        let mut buf = Vec::new();
+        value_to_network_binary(Some(&Value::<String>::from("hi")), &mut buf).unwrap();
+        assert_eq!(buf, [8, 0, 2, b'h', b'i']);
+        assert_eq!(
+            value_from_network_binary::<_, String>(buf.as_slice()).unwrap(),
+            Some(Value::String("hi".to_owned()))
+        );
+
+        let mut buf = Vec::new();
+        value_to_network_binary::<_, String>(None, &mut buf).unwrap();
+        assert_eq!(buf, [0]);
+        assert_eq!(
+            value_from_network_binary::<_, String>(buf.as_slice()).unwrap(),
+            None
+        );
+        assert!(from_network_binary::<_, String>(buf.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::io;
+
+use crate::error::Result;
+
+/// Source of binary NBT that can optionally lend out parts of itself.
+pub(crate) trait Input<'de>: io::Read {
+    /// Reads exactly `len` bytes, borrowing them from the input if possible
+    /// and copying them into `scratch` otherwise.
+    fn read_slice<'s>(
+        &'s mut self,
+        len: usize,
+        scratch: &'s mut Vec<u8>,
+    ) -> Result<Reference<'de, 's>>;
+
+    /// Discards exactly `len` bytes.
+    fn skip(&mut self, len: u64) -> Result<()>;
+}
+
+pub(crate) enum Reference<'b, 'c> {
+    Borrowed(&'b [u8]),
+    Copied(&'c [u8]),
+}
+
+pub(crate) struct IoInput<R>(pub(crate) R);
+
+impl<R: io::Read> io::Read for IoInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<'de, R: io::Read> Input<'de> for IoInput<R> {
+    fn read_slice<'s>(
+        &'s mut self,
+        len: usize,
+        scratch: &'s mut Vec<u8>,
+    ) -> Result<Reference<'de, 's>> {
+        scratch.clear();
+        scratch.resize(len, 0);
+        self.0.read_exact(scratch)?;
+        Ok(Reference::Copied(scratch))
+    }
+
+    fn skip(&mut self, len: u64) -> Result<()> {
+        let skipped = io::copy(&mut io::Read::take(&mut self.0, len), &mut io::sink())?;
+        if skipped != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct SliceInput<'de>(pub(crate) &'de [u8]);
+
+impl io::Read for SliceInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<'de> Input<'de> for SliceInput<'de> {
+    fn read_slice<'s>(
+        &'s mut self,
+        len: usize,
+        _scratch: &'s mut Vec<u8>,
+    ) -> Result<Reference<'de, 's>> {
+        if len > self.0.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(Reference::Borrowed(bytes))
+    }
+
+    fn skip(&mut self, len: u64) -> Result<()> {
+        match usize::try_from(len) {
+            Ok(len) if len <= self.0.len() => {
+                self.0 = &self.0[len..];
+                Ok(())
+            }
+            _ => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        }
+    }
+}
@@ -0,0 +1,814 @@
+use std::io::Write;
+
+use serde::ser::{
+    Impossible, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple, SerializeTupleStruct,
+};
+use serde::{Serialize, Serializer};
+
+use crate::binary::encode::EncodeState;
+use crate::conv::u8_slice_as_i8_slice;
+use crate::error::{Error, Result};
+use crate::serde::{BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN};
+use crate::Tag;
+
+/// Serializes `value` as a binary NBT compound named `root_name` without
+/// building an intermediate [`Compound`](crate::Compound).
+///
+/// `None` fields are omitted from the output. Sequences become `TAG_List`s
+/// unless marked with [`byte_array`](crate::serde::byte_array),
+/// [`int_array`](crate::serde::int_array) or
+/// [`long_array`](crate::serde::long_array).
+pub fn to_writer<W, T>(writer: W, root_name: &str, value: &T) -> Result<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+{
+    let mut state = EncodeState::new(writer);
+    value.serialize(PayloadSerializer {
+        state: &mut state,
+        header: Header::Root(root_name),
+    })
+}
+
+macro_rules! unsupported {
+    ($lit:literal) => {
+        Err(Error::Serde(
+            concat!("unsupported type: ", $lit).to_string(),
+        ))
+    };
+}
+
+/// What needs to be written before the payload, once its tag is known.
+enum Header<'a> {
+    Root(&'a str),
+    Field(&'a str),
+    Element(&'a mut ListState),
+}
+
+struct ListState {
+    len: usize,
+    tag: Option<Tag>,
+}
+
+struct PayloadSerializer<'a, W: Write> {
+    state: &'a mut EncodeState<W>,
+    header: Header<'a>,
+}
+
+impl<'a, W: Write> PayloadSerializer<'a, W> {
+    fn write_header(&mut self, tag: Tag) -> Result<()> {
+        match &mut self.header {
+            Header::Root(name) => {
+                if tag != Tag::Compound {
+                    return Err(Error::RootTagNotCompound(tag));
+                }
+                self.state.write_tag(tag)?;
+                self.state.write_string(*name)
+            }
+            Header::Field(name) => {
+                self.state.write_tag(tag)?;
+                self.state.write_string(*name)
+            }
+            Header::Element(list) => match list.tag {
+                None => {
+                    self.state.write_tag(tag)?;
+                    self.state.write_len(list.len)?;
+                    list.tag = Some(tag);
+                    Ok(())
+                }
+                Some(list_tag) if list_tag == tag => Ok(()),
+                Some(list_tag) => Err(Error::Serde(format!(
+                    "heterogeneous NBT list (expected `{list_tag}` element, got `{tag}`)"
+                ))),
+            },
+        }
+    }
+
+    fn begin_list(mut self, len: Option<usize>) -> Result<SerializeList<'a, W>> {
+        let Some(len) = len else {
+            return Err(Error::Serde("NBT list length must be known".to_string()));
+        };
+        self.write_header(Tag::List)?;
+        Ok(SerializeList {
+            state: self.state,
+            list: ListState { len, tag: None },
+            written: 0,
+        })
+    }
+}
+
+impl<'a, W: Write> Serializer for PayloadSerializer<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    type SerializeSeq = SerializeList<'a, W>;
+
+    type SerializeTuple = SerializeList<'a, W>;
+
+    type SerializeTupleStruct = SerializeList<'a, W>;
+
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeMap = SerializeCompound<'a, W>;
+
+    type SerializeStruct = SerializeCompound<'a, W>;
+
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
+        self.serialize_i8(v as i8)
+    }
+
+    fn serialize_i8(mut self, v: i8) -> Result<Self::Ok> {
+        self.write_header(Tag::Byte)?;
+        self.state.write_byte(v)
+    }
+
+    fn serialize_i16(mut self, v: i16) -> Result<Self::Ok> {
+        self.write_header(Tag::Short)?;
+        self.state.write_short(v)
+    }
+
+    fn serialize_i32(mut self, v: i32) -> Result<Self::Ok> {
+        self.write_header(Tag::Int)?;
+        self.state.write_int(v)
+    }
+
+    fn serialize_i64(mut self, v: i64) -> Result<Self::Ok> {
+        self.write_header(Tag::Long)?;
+        self.state.write_long(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
+        self.serialize_i8(v as i8)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
+        self.serialize_i16(v as i16)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
+        self.serialize_i32(v as i32)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_f32(mut self, v: f32) -> Result<Self::Ok> {
+        self.write_header(Tag::Float)?;
+        self.state.write_float(v)
+    }
+
+    fn serialize_f64(mut self, v: f64) -> Result<Self::Ok> {
+        self.write_header(Tag::Double)?;
+        self.state.write_double(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(mut self, v: &str) -> Result<Self::Ok> {
+        self.write_header(Tag::String)?;
+        self.state.write_string(v)
+    }
+
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Self::Ok> {
+        self.write_header(Tag::ByteArray)?;
+        self.state.write_byte_array(u8_slice_as_i8_slice(v))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        match self.header {
+            // Absent fields are simply not written.
+            Header::Field(_) => Ok(()),
+            _ => unsupported!("none"),
+        }
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        unsupported!("unit")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        let tag = match name {
+            BYTE_ARRAY_TOKEN => Tag::ByteArray,
+            INT_ARRAY_TOKEN => Tag::IntArray,
+            LONG_ARRAY_TOKEN => Tag::LongArray,
+            _ => return value.serialize(self),
+        };
+        value.serialize(ArraySerializer { inner: self, tag })
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        unsupported!("newtype variant")
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.begin_list(len)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.begin_list(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.begin_list(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        unsupported!("tuple variant")
+    }
+
+    fn serialize_map(mut self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.write_header(Tag::Compound)?;
+        Ok(SerializeCompound {
+            state: self.state,
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        unsupported!("struct variant")
+    }
+}
+
+struct SerializeList<'a, W: Write> {
+    state: &'a mut EncodeState<W>,
+    list: ListState,
+    written: usize,
+}
+
+impl<'a, W: Write> SerializeList<'a, W> {
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        if self.written == self.list.len {
+            return Err(Error::Serde("NBT list is longer than declared".to_string()));
+        }
+        value.serialize(PayloadSerializer {
+            state: self.state,
+            header: Header::Element(&mut self.list),
+        })?;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.written != self.list.len {
+            return Err(Error::Serde(
+                "NBT list is shorter than declared".to_string(),
+            ));
+        }
+        if self.list.tag.is_none() {
+            self.state.write_tag(Tag::End)?;
+            self.state.write_int(0)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> SerializeSeq for SerializeList<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl<'a, W: Write> SerializeTuple for SerializeList<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl<'a, W: Write> SerializeTupleStruct for SerializeList<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+struct SerializeCompound<'a, W: Write> {
+    state: &'a mut EncodeState<W>,
+    /// Temp storage for `serialize_key`.
+    key: Option<String>,
+}
+
+impl<'a, W: Write> SerializeMap for SerializeCompound<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        debug_assert!(
+            self.key.is_none(),
+            "call to `serialize_key` must be followed by `serialize_value`"
+        );
+
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .key
+            .take()
+            .expect("missing previous call to `serialize_key`");
+        value.serialize(PayloadSerializer {
+            state: self.state,
+            header: Header::Field(&key),
+        })
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.state.write_tag(Tag::End)
+    }
+}
+
+impl<'a, W: Write> SerializeStruct for SerializeCompound<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(PayloadSerializer {
+            state: self.state,
+            header: Header::Field(key),
+        })
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.state.write_tag(Tag::End)
+    }
+}
+
+/// [`Serializer`] for compound keys, which must be strings.
+struct KeySerializer;
+
+macro_rules! key_must_be_a_string {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Self::Ok> {
+                Err(Error::KeyMustBeAString)
+            }
+        )*
+    };
+}
+
+impl Serializer for KeySerializer {
+    type Ok = String;
+
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    key_must_be_a_string! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(Error::KeyMustBeAString)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::KeyMustBeAString)
+    }
+}
+
+/// [`Serializer`] for sequences marked as `TAG_Byte_Array`, `TAG_Int_Array`
+/// or `TAG_Long_Array`.
+struct ArraySerializer<'a, W: Write> {
+    inner: PayloadSerializer<'a, W>,
+    tag: Tag,
+}
+
+impl<'a, W: Write> ArraySerializer<'a, W> {
+    fn not_an_array<T>(&self) -> Result<T> {
+        Err(Error::Serde(format!(
+            "expected a sequence for `{}`",
+            self.tag
+        )))
+    }
+}
+
+macro_rules! not_an_array {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Self::Ok> {
+                self.not_an_array()
+            }
+        )*
+    };
+}
+
+impl<'a, W: Write> Serializer for ArraySerializer<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray<'a, W>;
+
+    type SerializeTuple = SerializeArray<'a, W>;
+
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    not_an_array! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
+        if self.tag != Tag::ByteArray {
+            return self.not_an_array();
+        }
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok> {
+        self.not_an_array()
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.not_an_array()
+    }
+
+    fn serialize_seq(mut self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        let Some(len) = len else {
+            return Err(Error::Serde("NBT array length must be known".to_string()));
+        };
+        self.inner.write_header(self.tag)?;
+        self.inner.state.write_len(len)?;
+        Ok(SerializeArray {
+            state: self.inner.state,
+            tag: self.tag,
+            remaining: len,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.not_an_array()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.not_an_array()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.not_an_array()
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.not_an_array()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.not_an_array()
+    }
+}
+
+struct SerializeArray<'a, W: Write> {
+    state: &'a mut EncodeState<W>,
+    tag: Tag,
+    remaining: usize,
+}
+
+impl<'a, W: Write> SerializeArray<'a, W> {
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        if self.remaining == 0 {
+            return Err(Error::Serde(
+                "NBT array is longer than declared".to_string(),
+            ));
+        }
+        self.remaining -= 1;
+
+        // Arrays have no per-element header, so elements are written like
+        // list elements after the list header has already been emitted.
+        let element_tag = match self.tag {
+            Tag::ByteArray => Tag::Byte,
+            Tag::IntArray => Tag::Int,
+            _ => Tag::Long,
+        };
+        let mut list = ListState {
+            len: 0,
+            tag: Some(element_tag),
+        };
+        value.serialize(PayloadSerializer {
+            state: self.state,
+            header: Header::Element(&mut list),
+        })
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.remaining != 0 {
+            return Err(Error::Serde(
+                "NBT array is shorter than declared".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> SerializeSeq for SerializeArray<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl<'a, W: Write> SerializeTuple for SerializeArray<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        self.finish()
+    }
+}
@@ -0,0 +1,387 @@
+//! Streaming reads of binary NBT.
+//!
+//! Instead of building a [`Value`](crate::Value) tree, a [`Visitor`] is handed
+//! each value as it is read and decides which subtrees to descend into. Skipped
+//! subtrees are never allocated, which makes this the cheapest way to pick a
+//! few fields out of a large or untrusted input. This mirrors vanilla's
+//! `StreamTagVisitor`.
+
+use std::io::Read;
+
+use crate::binary::decode::{min_payload_size, DecodeState, NbtReadLimits};
+use crate::binary::input::{Input, IoInput};
+use crate::error::{Error, Result};
+use crate::Tag;
+
+/// What to do after a value has been visited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueResult {
+    /// Keep reading.
+    Continue,
+    /// Skip the rest of the enclosing list or compound.
+    Break,
+    /// Stop reading altogether.
+    Halt,
+}
+
+/// What to do with a list element or compound entry that is about to be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryResult {
+    /// Read the value and pass it to the visitor.
+    Enter,
+    /// Skip the value without visiting it.
+    Skip,
+    /// Skip the value and the rest of the enclosing list or compound.
+    Break,
+    /// Stop reading altogether.
+    Halt,
+}
+
+/// Receives NBT values as they are read by [`visit_binary`] or
+/// [`visit_network_binary`].
+///
+/// Every method has a default that accepts everything, so implementations
+/// only need to override the callbacks they are interested in.
+#[allow(unused_variables)]
+pub trait Visitor {
+    /// Called with the type of the root value. Returning anything other than
+    /// [`ValueResult::Continue`] skips the rest of the input.
+    fn visit_root(&mut self, tag: Tag) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_byte(&mut self, v: i8) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_short(&mut self, v: i16) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_int(&mut self, v: i32) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_long(&mut self, v: i64) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_float(&mut self, v: f32) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_double(&mut self, v: f64) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_byte_array(&mut self, v: &[i8]) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_string(&mut self, v: &str) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_int_array(&mut self, v: &[i32]) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    fn visit_long_array(&mut self, v: &[i64]) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    /// Called when a list of `len` elements of type `element` starts.
+    /// [`ValueResult::Break`] skips all of its elements.
+    fn visit_list(&mut self, element: Tag, len: usize) -> ValueResult {
+        ValueResult::Continue
+    }
+
+    /// Called before the list element at `index` is read.
+    fn visit_element(&mut self, tag: Tag, index: usize) -> EntryResult {
+        EntryResult::Enter
+    }
+
+    /// Called before the value of the compound entry `name` is read.
+    fn visit_entry(&mut self, tag: Tag, name: &str) -> EntryResult {
+        EntryResult::Enter
+    }
+
+    /// Called when a list or compound ends, including when the rest of it
+    /// was skipped.
+    fn visit_container_end(&mut self) -> ValueResult {
+        ValueResult::Continue
+    }
+}
+
+/// Streams a named root compound from `reader` into `visitor`. The root name
+/// is skipped.
+pub fn visit_binary<R, V>(reader: R, visitor: &mut V, limits: NbtReadLimits) -> Result<()>
+where
+    R: Read,
+    V: Visitor + ?Sized,
+{
+    let mut state = DecodeState::with_limits(IoInput(reader), limits);
+    let tag = state.read_tag()?;
+    if tag != Tag::Compound {
+        return Err(Error::RootTagNotCompound(tag));
+    }
+    if visitor.visit_root(tag) == ValueResult::Continue {
+        state.skip_string()?;
+        state.visit_value(tag, visitor)?;
+    }
+    Ok(())
+}
+
+/// Streams a nameless root value in the network format from `reader` into
+/// `visitor`. Absent NBT is visited as a root of type [`Tag::End`].
+pub fn visit_network_binary<R, V>(reader: R, visitor: &mut V, limits: NbtReadLimits) -> Result<()>
+where
+    R: Read,
+    V: Visitor + ?Sized,
+{
+    let mut state = DecodeState::with_limits(IoInput(reader), limits);
+    let tag = state.read_tag()?;
+    if visitor.visit_root(tag) == ValueResult::Continue && tag != Tag::End {
+        state.visit_value(tag, visitor)?;
+    }
+    Ok(())
+}
+
+impl<'de, I: Input<'de>> DecodeState<I> {
+    fn visit_value<V>(&mut self, tag: Tag, visitor: &mut V) -> Result<ValueResult>
+    where
+        V: Visitor + ?Sized,
+    {
+        match tag {
+            Tag::Byte => Ok(visitor.visit_byte(self.read_byte()?)),
+            Tag::Short => Ok(visitor.visit_short(self.read_short()?)),
+            Tag::Int => Ok(visitor.visit_int(self.read_int()?)),
+            Tag::Long => Ok(visitor.visit_long(self.read_long()?)),
+            Tag::Float => Ok(visitor.visit_float(self.read_float()?)),
+            Tag::Double => Ok(visitor.visit_double(self.read_double()?)),
+            Tag::ByteArray => Ok(visitor.visit_byte_array(&self.read_byte_array()?)),
+            Tag::String => Ok(visitor.visit_string(&self.read_string::<String>()?)),
+            Tag::List => self.check_depth(|st| st.visit_list(visitor)),
+            Tag::Compound => self.check_depth(|st| st.visit_compound(visitor)),
+            Tag::IntArray => Ok(visitor.visit_int_array(&self.read_int_array()?)),
+            Tag::LongArray => Ok(visitor.visit_long_array(&self.read_long_array()?)),
+            Tag::End => Err(Error::EndTagInValue),
+        }
+    }
+
+    fn visit_list<V>(&mut self, visitor: &mut V) -> Result<ValueResult>
+    where
+        V: Visitor + ?Sized,
+    {
+        let tag = self.read_tag()?;
+        let len = self.read_list_len(tag)?;
+
+        let mut index = 0;
+        match visitor.visit_list(tag, len) {
+            ValueResult::Halt => return Ok(ValueResult::Halt),
+            ValueResult::Break => {}
+            ValueResult::Continue => {
+                while index < len {
+                    index += 1;
+                    match visitor.visit_element(tag, index - 1) {
+                        EntryResult::Halt => return Ok(ValueResult::Halt),
+                        EntryResult::Break => {
+                            self.skip_value(tag)?;
+                            break;
+                        }
+                        EntryResult::Skip => self.skip_value(tag)?,
+                        EntryResult::Enter => match self.visit_value(tag, visitor)? {
+                            ValueResult::Halt => return Ok(ValueResult::Halt),
+                            ValueResult::Break => break,
+                            ValueResult::Continue => {}
+                        },
+                    }
+                }
+            }
+        }
+
+        for _ in index..len {
+            self.skip_value(tag)?;
+        }
+        Ok(visitor.visit_container_end())
+    }
+
+    fn visit_compound<V>(&mut self, visitor: &mut V) -> Result<ValueResult>
+    where
+        V: Visitor + ?Sized,
+    {
+        loop {
+            let tag = self.read_tag()?;
+            if tag == Tag::End {
+                return Ok(visitor.visit_container_end());
+            }
+            self.account_elements(1)?;
+            let name = self.read_string::<String>()?;
+            match visitor.visit_entry(tag, &name) {
+                EntryResult::Halt => return Ok(ValueResult::Halt),
+                EntryResult::Break => {
+                    self.skip_value(tag)?;
+                    break;
+                }
+                EntryResult::Skip => self.skip_value(tag)?,
+                EntryResult::Enter => match self.visit_value(tag, visitor)? {
+                    ValueResult::Halt => return Ok(ValueResult::Halt),
+                    ValueResult::Break => break,
+                    ValueResult::Continue => {}
+                },
+            }
+        }
+
+        self.skip_compound()?;
+        Ok(visitor.visit_container_end())
+    }
+
+    /// Reads the length of a list of `tag` elements.
+    fn read_list_len(&mut self, tag: Tag) -> Result<usize> {
+        let len = self.read_len(min_payload_size(tag))?;
+        if tag == Tag::End && len != 0 {
+            return Err(Error::TagEndListWithNonZeroLength(len as i32));
+        }
+        Ok(len)
+    }
+
+    /// Discards the payload of a `tag` while still charging it against the
+    /// limits.
+    fn skip_value(&mut self, tag: Tag) -> Result<()> {
+        match tag {
+            Tag::Byte | Tag::Short | Tag::Int | Tag::Long | Tag::Float | Tag::Double => {
+                self.skip_bytes(min_payload_size(tag))
+            }
+            Tag::ByteArray => self.skip_array(1),
+            Tag::String => self.skip_string(),
+            Tag::List => self.check_depth(|st| {
+                let tag = st.read_tag()?;
+                let len = st.read_list_len(tag)?;
+                match tag {
+                    Tag::Byte | Tag::Short | Tag::Int | Tag::Long | Tag::Float | Tag::Double => {
+                        st.skip_bytes(len as u64 * min_payload_size(tag))
+                    }
+                    _ => (0..len).try_for_each(|_| st.skip_value(tag)),
+                }
+            }),
+            Tag::Compound => self.check_depth(|st| st.skip_compound()),
+            Tag::IntArray => self.skip_array(4),
+            Tag::LongArray => self.skip_array(8),
+            Tag::End => Err(Error::EndTagInValue),
+        }
+    }
+
+    fn skip_compound(&mut self) -> Result<()> {
+        loop {
+            let tag = self.read_tag()?;
+            if tag == Tag::End {
+                return Ok(());
+            }
+            self.account_elements(1)?;
+            self.skip_string()?;
+            self.skip_value(tag)?;
+        }
+    }
+
+    fn skip_array(&mut self, element_size: u64) -> Result<()> {
+        let len = self.read_len(element_size)?;
+        self.skip_bytes(len as u64 * element_size)
+    }
+
+    fn skip_string(&mut self) -> Result<()> {
+        let len = self.read_short()? as u16;
+        self.skip_bytes(len as u64)
+    }
+
+    fn skip_bytes(&mut self, len: u64) -> Result<()> {
+        self.account_bytes(len)?;
+        self.reader.skip(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::binary::decode::NbtReadLimits;
+    use crate::binary::encode::to_binary;
+    use crate::binary::visit::{visit_binary, EntryResult, ValueResult, Visitor};
+    use crate::error::Error;
+    use crate::{Compound, List, Tag};
+
+    #[derive(Default)]
+    struct Names {
+        seen: Vec<String>,
+        ints: Vec<i32>,
+    }
+
+    impl Visitor for Names {
+        fn visit_entry(&mut self, _tag: Tag, name: &str) -> EntryResult {
+            self.seen.push(name.to_owned());
+            match name {
+                "skipped" => EntryResult::Skip,
+                "stop" => EntryResult::Break,
+                _ => EntryResult::Enter,
+            }
+        }
+
+        fn visit_int(&mut self, v: i32) -> ValueResult {
+            self.ints.push(v);
+            ValueResult::Continue
+        }
+    }
+
+    #[test]
+    fn skips_and_breaks() {
+        let mut inner: Compound = Compound::new();
+        inner.insert("deep", 2);
+        let mut compound: Compound = Compound::new();
+        compound.insert("a", 1);
+        compound.insert("list", List::Int(vec![3, 4]));
+        compound.insert("skipped", inner);
+        compound.insert("stop", 5);
+        compound.insert("z", 6);
+        let mut buf = Vec::new();
+        to_binary(&compound, "", &mut buf).unwrap();
+
+        let mut visitor = Names::default();
+        visit_binary(buf.as_slice(), &mut visitor, NbtReadLimits::default()).unwrap();
+
+        assert_eq!(visitor.seen, ["a", "list", "skipped", "stop"]);
+        assert_eq!(visitor.ints, [1, 3, 4]);
+    }
+
+    #[test]
+    fn limits_apply_to_skipped_values() {
+        let mut compound: Compound = Compound::new();
+        compound.insert("skipped", vec![0i64; 1024]);
+        let mut buf = Vec::new();
+        to_binary(&compound, "", &mut buf).unwrap();
+
+        let limits = NbtReadLimits {
+            max_bytes: 1024,
+            ..NbtReadLimits::default()
+        };
+        let res = visit_binary(buf.as_slice(), &mut Names::default(), limits);
+        assert!(matches!(res, Err(Error::SizeLimitExceeded(1024))));
+    }
+
+    #[test]
+    fn limits_compound_entries() {
+        let wide: Compound = (0..10).map(|i| (i.to_string(), i.into())).collect();
+        let limits = NbtReadLimits {
+            max_elements: 5,
+            ..NbtReadLimits::default()
+        };
+
+        let mut buf = Vec::new();
+        to_binary(&wide, "", &mut buf).unwrap();
+        let res = visit_binary(buf.as_slice(), &mut Names::default(), limits);
+        assert!(matches!(res, Err(Error::ElementLimitExceeded(5))));
+
+        let mut compound: Compound = Compound::new();
+        compound.insert("skipped", wide);
+        let mut buf = Vec::new();
+        to_binary(&compound, "", &mut buf).unwrap();
+        let res = visit_binary(buf.as_slice(), &mut Names::default(), limits);
+        assert!(matches!(res, Err(Error::ElementLimitExceeded(5))));
+    }
+}
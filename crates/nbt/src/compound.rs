@@ -41,6 +41,14 @@ where
         self.map.get(key)
     }
 
+    pub fn get_mut<K>(&mut self, key: &K) -> Option<&mut Value<S>>
+    where
+        K: ?Sized + Ord,
+        S: Borrow<K>,
+    {
+        self.map.get_mut(key)
+    }
+
     pub fn insert<K, V>(&mut self, key: K, value: V) -> Option<Value<S>>
     where
         K: Into<S>,
@@ -57,6 +65,50 @@ where
         self.map.remove(key)
     }
 
+    pub fn entry<K>(&mut self, key: K) -> Entry<'_, S>
+    where
+        K: Into<S>,
+    {
+        use std::collections::btree_map::Entry as MapEntry;
+
+        match self.map.entry(key.into()) {
+            MapEntry::Vacant(ve) => Entry::Vacant(VacantEntry { ve }),
+            MapEntry::Occupied(oe) => Entry::Occupied(OccupiedEntry { oe }),
+        }
+    }
+
+    /// Deep-merges `other` into this compound the way `/data merge` does.
+    ///
+    /// Nested compounds are merged recursively, while every other value in
+    /// `other`, lists included, replaces the value under the same key. Returns
+    /// whether anything changed.
+    pub fn merge(&mut self, other: &Compound<S>) -> bool
+    where
+        S: Clone,
+    {
+        let mut changed = false;
+        for (key, value) in other {
+            match self.entry(key.clone()) {
+                Entry::Occupied(mut entry) => match (entry.get_mut(), value) {
+                    (Value::Compound(target), Value::Compound(source)) => {
+                        changed |= target.merge(source);
+                    }
+                    (target, value) => {
+                        if target != value {
+                            target.clone_from(value);
+                            changed = true;
+                        }
+                    }
+                },
+                Entry::Vacant(entry) => {
+                    entry.insert(value.clone());
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
     pub fn iter(&self) -> Iter<S> {
         Iter {
             iter: self.map.iter(),
@@ -0,0 +1,234 @@
+//! Structural comparison of compounds.
+//!
+//! [`diff`] walks two compounds side by side and reports every path whose
+//! value was added, removed or changed. The [`Display`](fmt::Display) output
+//! lists one change per line, which makes a [`Diff`] readable in test
+//! assertions:
+//!
+//! ```text
+//! + Data.LevelName: "world"
+//! - Data.Version
+//! ~ Data.DataVersion: 3465 -> 3700
+//! ```
+
+use std::fmt;
+
+use crate::path::NbtPath;
+use crate::{Compound, List, Value, ValueRef};
+
+/// A single difference between two compounds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// `path` only exists in the new compound.
+    Added { path: NbtPath, value: Value },
+    /// `path` only exists in the old compound.
+    Removed { path: NbtPath, value: Value },
+    /// `path` exists in both compounds, but with different values.
+    Changed {
+        path: NbtPath,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Change {
+    pub fn path(&self) -> &NbtPath {
+        match self {
+            Change::Added { path, .. } | Change::Removed { path, .. } => path,
+            Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {path}: {value}"),
+            Change::Removed { path, .. } => write!(f, "- {path}"),
+            Change::Changed { path, old, new } => write!(f, "~ {path}: {old} -> {new}"),
+        }
+    }
+}
+
+/// The differences between two compounds, as returned by [`diff`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Change> {
+        self.changes.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diff {
+    type Item = &'a Change;
+    type IntoIter = std::slice::Iter<'a, Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.iter()
+    }
+}
+
+impl IntoIterator for Diff {
+    type Item = Change;
+    type IntoIter = std::vec::IntoIter<Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares `old` with `new`.
+///
+/// Compounds are compared key by key. Lists with the same element type are
+/// compared element by element, with elements past the end of the shorter
+/// list reported as added or removed. Any other difference, including a
+/// change of type, is reported as a change of the whole value.
+pub fn diff(old: &Compound, new: &Compound) -> Diff {
+    let mut diff = Diff::default();
+    diff_compounds(None, old, new, &mut diff.changes);
+    diff
+}
+
+fn diff_compounds(
+    parent: Option<&NbtPath>,
+    old: &Compound,
+    new: &Compound,
+    changes: &mut Vec<Change>,
+) {
+    let path = |key: &str| match parent {
+        Some(parent) => parent.join_key(key),
+        None => NbtPath::from_key(key),
+    };
+
+    for (key, old_value) in old {
+        match new.get(key.as_str()) {
+            Some(new_value) => diff_values(
+                path(key),
+                old_value.as_value_ref(),
+                new_value.as_value_ref(),
+                changes,
+            ),
+            None => changes.push(Change::Removed {
+                path: path(key),
+                value: old_value.clone(),
+            }),
+        }
+    }
+
+    for (key, new_value) in new {
+        if old.get(key.as_str()).is_none() {
+            changes.push(Change::Added {
+                path: path(key),
+                value: new_value.clone(),
+            });
+        }
+    }
+}
+
+fn diff_lists(path: &NbtPath, old: &List, new: &List, changes: &mut Vec<Change>) {
+    for index in 0..old.len().max(new.len()) {
+        let path = path.join_index(index);
+        match (old.get(index), new.get(index)) {
+            (Some(old), Some(new)) => diff_values(path, old, new, changes),
+            (Some(old), None) => changes.push(Change::Removed {
+                path,
+                value: old.to_value(),
+            }),
+            (None, Some(new)) => changes.push(Change::Added {
+                path,
+                value: new.to_value(),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn diff_values(path: NbtPath, old: ValueRef, new: ValueRef, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (ValueRef::Compound(old), ValueRef::Compound(new)) => {
+            diff_compounds(Some(&path), old, new, changes)
+        }
+        (ValueRef::List(old), ValueRef::List(new))
+            if old.element_tag() == new.element_tag() || old.is_empty() || new.is_empty() =>
+        {
+            diff_lists(&path, old, new, changes)
+        }
+        (old, new) if old != new => changes.push(Change::Changed {
+            path,
+            old: old.to_value(),
+            new: new.to_value(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::diff;
+    use crate::snbt::from_snbt_str;
+    use crate::{Compound, Value};
+
+    fn compound(snbt: &str) -> Compound {
+        match from_snbt_str(snbt).unwrap() {
+            Value::Compound(compound) => compound,
+            value => panic!("not a compound: {value}"),
+        }
+    }
+
+    #[test]
+    fn reports_paths() {
+        let old = compound(r#"{a: 1, b: {c: "x", d: [1, 2, 3]}, gone: 1b}"#);
+        let new = compound(r#"{a: 2, b: {c: "x", d: [1, 5]}, e: {f: 1b}}"#);
+
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "~ a: 1 -> 2\n~ b.d[1]: 2 -> 5\n- b.d[2]\n- gone\n+ e: {f:1b}\n"
+        );
+        assert!(super::diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn type_changes_replace_whole_value() {
+        let old = compound("{a: [1, 2], b: 1}");
+        let new = compound("{a: [1L, 2L], b: 1b}");
+        assert_eq!(
+            diff(&old, &new).to_string(),
+            "~ a: [1,2] -> [1L,2L]\n~ b: 1 -> 1b\n"
+        );
+    }
+
+    #[test]
+    fn merge_is_deep() {
+        let mut target = compound("{a: {b: 1, c: [1, 2]}, d: 1}");
+        let source = compound("{a: {c: [3], e: 1b}}");
+
+        assert!(target.merge(&source));
+        assert_eq!(target, compound("{a: {b: 1, c: [3], e: 1b}, d: 1}"));
+        assert!(!target.merge(&source));
+    }
+}
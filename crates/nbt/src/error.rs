@@ -17,10 +17,17 @@ pub enum Error {
     #[cfg(feature = "binary")]
     #[error("Invalid UTF-8 string: {0}")]
     InvalidUtf8(#[from] cesu8::Cesu8DecodingError),
+    #[cfg(all(feature = "binary", feature = "java_string"))]
+    #[error("Invalid modified UTF-8 string: {0}")]
+    InvalidJavaUtf8(#[from] java_string::Utf8Error),
     #[error("Encountered type '{0}', which has no corresponding NBT tag")]
     UnrepresentableType(&'static str),
     #[error("Invalid array length: {0}")]
     InvalidArrayLength(i32),
+    #[error("Length {0} does not fit in a TAG_Int")]
+    LengthTooLarge(usize),
+    #[error("String of {0} bytes exceeds the maximum length of 65535 bytes")]
+    StringTooLong(usize),
     #[error("Invalid TAG_End list length: {0}")]
     TagEndListWithNonZeroLength(i32),
     #[error("Key must be a string")]
@@ -33,6 +40,10 @@ pub enum Error {
     RootTagNotCompound(Tag),
     #[error("Reached maximum recursion depth")]
     RecursionLimitExceeded,
+    #[error("NBT data exceeds the limit of {0} bytes")]
+    SizeLimitExceeded(u64),
+    #[error("NBT data exceeds the limit of {0} elements")]
+    ElementLimitExceeded(u64),
 }
 
 #[cfg(feature = "serde")]
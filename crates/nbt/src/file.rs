@@ -0,0 +1,192 @@
+//! Standalone NBT files such as `level.dat`, `playerdata/<uuid>.dat` and
+//! `.schem` files.
+//!
+//! Vanilla writes these gzip-compressed, but older tools and some formats use
+//! zlib or no compression at all, so reading detects the format from the
+//! first bytes of the input.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+use crate::binary::decode::{from_binary, FromModifiedUtf8};
+use crate::binary::encode::{to_binary, ToModifiedUtf8};
+use crate::error::Result;
+use crate::Compound;
+
+/// Compression applied to a whole NBT file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed binary NBT.
+    None,
+    /// Gzip, which is what vanilla reads and writes.
+    #[default]
+    Gzip,
+    /// Zlib.
+    Zlib,
+}
+
+impl Compression {
+    /// Guesses the compression of an NBT file from its first two bytes.
+    ///
+    /// Anything that doesn't carry a gzip or zlib header is assumed to be
+    /// uncompressed.
+    pub fn detect(header: &[u8]) -> Self {
+        match *header {
+            [0x1f, 0x8b, ..] => Self::Gzip,
+            [cmf, flg, ..] if cmf & 0x0f == 8 && u16::from_be_bytes([cmf, flg]) % 31 == 0 => {
+                Self::Zlib
+            }
+            _ => Self::None,
+        }
+    }
+}
+
+/// Reads a named root compound from `reader`, detecting its compression.
+pub fn from_compressed_reader<R, S>(reader: R) -> Result<(Compound<S>, S)>
+where
+    R: Read,
+    S: for<'de> FromModifiedUtf8<'de> + Hash + Ord,
+{
+    let mut reader = BufReader::new(reader);
+    match Compression::detect(reader.fill_buf()?) {
+        Compression::None => from_binary(reader),
+        Compression::Gzip => from_binary(GzDecoder::new(reader)),
+        Compression::Zlib => from_binary(ZlibDecoder::new(reader)),
+    }
+}
+
+/// Writes `compound` as a named root compound to `writer`.
+pub fn to_compressed_writer<W, S, R>(
+    compound: &Compound<S>,
+    root_name: &R,
+    writer: W,
+    compression: Compression,
+) -> Result<()>
+where
+    W: Write,
+    S: ToModifiedUtf8 + Hash + Ord,
+    R: ToModifiedUtf8 + ?Sized,
+{
+    match compression {
+        Compression::None => to_binary(compound, root_name, writer),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            to_binary(compound, root_name, &mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        Compression::Zlib => {
+            let mut encoder = ZlibEncoder::new(writer, flate2::Compression::default());
+            to_binary(compound, root_name, &mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        }
+    }
+}
+
+/// Reads the NBT file at `path`, detecting its compression.
+pub fn read_file<S>(path: impl AsRef<Path>) -> Result<(Compound<S>, S)>
+where
+    S: for<'de> FromModifiedUtf8<'de> + Hash + Ord,
+{
+    from_compressed_reader(File::open(path)?)
+}
+
+/// Saves `compound` to `path` as a gzip-compressed NBT file.
+///
+/// Like vanilla, the data is first written to a temporary file next to
+/// `path`. Only once that succeeded is the previous file kept as
+/// `<name>_old` and replaced, so a crash mid-write never loses both copies.
+pub fn write_file<P, S, R>(path: P, compound: &Compound<S>, root_name: &R) -> Result<()>
+where
+    P: AsRef<Path>,
+    S: ToModifiedUtf8 + Hash + Ord,
+    R: ToModifiedUtf8 + ?Sized,
+{
+    let path = path.as_ref();
+    let temp = with_suffix(path, ".tmp")?;
+
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    to_compressed_writer(compound, root_name, &mut writer, Compression::Gzip)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+
+    replace_file(path, &temp, &with_suffix(path, "_old")?)?;
+    Ok(())
+}
+
+/// Moves `new` to `path`, keeping the file previously at `path` as `backup`.
+fn replace_file(path: &Path, new: &Path, backup: &Path) -> io::Result<()> {
+    match fs::remove_file(backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    match fs::rename(path, backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::rename(new, path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let mut name =
+        OsString::from(path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path does not name a file")
+        })?);
+    name.push(suffix);
+    Ok(path.with_file_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file::{
+        from_compressed_reader, read_file, to_compressed_writer, write_file, Compression,
+    };
+    use crate::Compound;
+
+    fn level() -> Compound {
+        let mut data = Compound::new();
+        data.insert("LevelName", "world");
+        data.insert("DataVersion", 3700);
+        let mut level = Compound::new();
+        level.insert("Data", data);
+        level
+    }
+
+    #[test]
+    fn detects_compression() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Zlib] {
+            let mut buf = Vec::new();
+            to_compressed_writer(&level(), "", &mut buf, compression).unwrap();
+            assert_eq!(Compression::detect(&buf), compression);
+
+            let (read, name) = from_compressed_reader::<_, String>(buf.as_slice()).unwrap();
+            assert_eq!(read, level());
+            assert_eq!(name, "");
+        }
+    }
+
+    #[test]
+    fn keeps_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("level.dat");
+
+        let mut old = level();
+        old.insert("old", 1i8);
+        write_file(&path, &old, "").unwrap();
+        write_file(&path, &level(), "").unwrap();
+
+        assert_eq!(read_file::<String>(&path).unwrap().0, level());
+        let backup = dir.path().join("level.dat_old");
+        assert_eq!(read_file::<String>(backup).unwrap().0, old);
+        assert!(!dir.path().join("level.dat.tmp").exists());
+    }
+}
@@ -0,0 +1,554 @@
+//! Conversion between NBT and JSON, e.g. for turning datapack files into
+//! registry NBT or dumping world data for inspection.
+//!
+//! Plain JSON has no notion of NBT's numeric and array types, so
+//! [`to_json`] is lossy and [`from_json`] has to guess them, see
+//! [`NumberInference`]. [`to_typed_json`] and [`from_typed_json`] instead
+//! wrap every value as `{"type": "int", "value": 1}` and round-trip exactly.
+
+use std::hash::Hash;
+
+use serde_json::{Map, Number};
+use thiserror::Error;
+
+use crate::snbt::push_to_list;
+use crate::{Compound, List, Tag, Value, ValueRef};
+
+/// Decides which NBT number type an untyped JSON number becomes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumberInference {
+    /// Whole numbers become `TAG_Int`, or `TAG_Long` if they don't fit, and
+    /// any other number becomes `TAG_Double`.
+    #[default]
+    IntOrDouble,
+    /// Whole numbers become the smallest of `TAG_Byte`, `TAG_Short`,
+    /// `TAG_Int` and `TAG_Long` that fits, and any other number becomes a
+    /// `TAG_Float` if that is exact or a `TAG_Double` otherwise.
+    Smallest,
+    /// Every number becomes a `TAG_Double`, like vanilla's conversion from
+    /// `JsonOps` to `NbtOps`.
+    Double,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum JsonError {
+    #[error("JSON null has no NBT equivalent")]
+    Null,
+    #[error("Can't insert {found} into list of {expected}")]
+    MixedList { expected: Tag, found: Tag },
+    #[error("Invalid typed value: {0}")]
+    InvalidTypedValue(String),
+}
+
+/// Converts `value` to plain JSON. Bytes, shorts, ints and longs all become
+/// JSON integers, arrays become JSON arrays and non-finite floats become
+/// `null`.
+pub fn to_json<S: AsRef<str> + Ord + Hash>(value: &Value<S>) -> serde_json::Value {
+    value_to_json(value.as_value_ref(), false)
+}
+
+/// Converts `value` to JSON in which every value is tagged with its type, so
+/// that [`from_typed_json`] can restore it exactly.
+pub fn to_typed_json<S: AsRef<str> + Ord + Hash>(value: &Value<S>) -> serde_json::Value {
+    value_to_json(value.as_value_ref(), true)
+}
+
+fn value_to_json<S: AsRef<str> + Ord + Hash>(value: ValueRef<S>, typed: bool) -> serde_json::Value {
+    let json = match value {
+        ValueRef::Byte(v) => (*v).into(),
+        ValueRef::Short(v) => (*v).into(),
+        ValueRef::Int(v) => (*v).into(),
+        ValueRef::Long(v) => (*v).into(),
+        // Non-finite floats are only representable as strings in typed JSON.
+        ValueRef::Float(v) => match float_to_json(*v) {
+            Some(json) => json,
+            None if typed => v.to_string().into(),
+            None => serde_json::Value::Null,
+        },
+        ValueRef::Double(v) => match double_to_json(*v) {
+            Some(json) => json,
+            None if typed => v.to_string().into(),
+            None => serde_json::Value::Null,
+        },
+        ValueRef::ByteArray(v) => v.into(),
+        ValueRef::String(v) => v.as_ref().into(),
+        ValueRef::List(v) => (0..v.len())
+            .filter_map(|i| v.get(i))
+            .map(|v| value_to_json(v, typed))
+            .collect(),
+        ValueRef::Compound(v) => v
+            .iter()
+            .map(|(key, value)| {
+                let value = value_to_json(value.as_value_ref(), typed);
+                (key.as_ref().to_owned(), value)
+            })
+            .collect::<Map<_, _>>()
+            .into(),
+        ValueRef::IntArray(v) => v.into(),
+        ValueRef::LongArray(v) => v.into(),
+    };
+
+    if !typed {
+        return json;
+    }
+
+    let mut object = Map::new();
+    object.insert("type".into(), type_name(value.tag()).into());
+    if let ValueRef::List(list) = value {
+        object.insert("element".into(), type_name(list.element_tag()).into());
+    }
+    object.insert("value".into(), json);
+    object.into()
+}
+
+/// Converts plain JSON to NBT, choosing number types with `numbers`.
+///
+/// Booleans become `TAG_Byte`s and arrays become lists. Numbers in the same
+/// array are widened to a common type, but arrays mixing other types are an
+/// error.
+pub fn from_json(json: &serde_json::Value, numbers: NumberInference) -> Result<Value, JsonError> {
+    match json {
+        serde_json::Value::Null => Err(JsonError::Null),
+        serde_json::Value::Bool(v) => Ok(Value::Byte(*v as i8)),
+        serde_json::Value::Number(v) => Ok(number_from_json(v, numbers)),
+        serde_json::Value::String(v) => Ok(Value::String(v.clone())),
+        serde_json::Value::Array(v) => {
+            let elements = v
+                .iter()
+                .map(|v| from_json(v, numbers))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::List(list_from_values(widen_numbers(elements))?))
+        }
+        serde_json::Value::Object(v) => {
+            let mut compound = Compound::new();
+            for (key, value) in v {
+                compound.insert(key.clone(), from_json(value, numbers)?);
+            }
+            Ok(Value::Compound(compound))
+        }
+    }
+}
+
+/// Converts JSON produced by [`to_typed_json`] back to NBT.
+pub fn from_typed_json(json: &serde_json::Value) -> Result<Value, JsonError> {
+    let invalid = |reason: &str| JsonError::InvalidTypedValue(reason.to_owned());
+
+    let object = json
+        .as_object()
+        .ok_or_else(|| invalid("expected an object with \"type\" and \"value\""))?;
+    let tag = object
+        .get("type")
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| invalid("missing \"type\""))?;
+    let tag = tag_from_name(tag).ok_or_else(|| invalid(&format!("unknown type \"{tag}\"")))?;
+    let value = object
+        .get("value")
+        .ok_or_else(|| invalid("missing \"value\""))?;
+
+    let out_of_range = || invalid(&format!("{value} is not a valid {}", type_name(tag)));
+    let integer = |value: &serde_json::Value| value.as_i64().ok_or_else(out_of_range);
+    let array = || {
+        value
+            .as_array()
+            .ok_or_else(|| invalid(&format!("{} must be an array", type_name(tag))))
+    };
+
+    Ok(match tag {
+        Tag::Byte => Value::Byte(integer(value)?.try_into().map_err(|_| out_of_range())?),
+        Tag::Short => Value::Short(integer(value)?.try_into().map_err(|_| out_of_range())?),
+        Tag::Int => Value::Int(integer(value)?.try_into().map_err(|_| out_of_range())?),
+        Tag::Long => Value::Long(integer(value)?),
+        Tag::Float => Value::Float(float_from_json(value).ok_or_else(out_of_range)? as f32),
+        Tag::Double => Value::Double(float_from_json(value).ok_or_else(out_of_range)?),
+        Tag::ByteArray => Value::ByteArray(
+            array()?
+                .iter()
+                .map(|v| v.as_i64().and_then(|v| v.try_into().ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(out_of_range)?,
+        ),
+        Tag::String => Value::String(
+            value
+                .as_str()
+                .ok_or_else(|| invalid("string must be a JSON string"))?
+                .to_owned(),
+        ),
+        Tag::List => {
+            let element = object
+                .get("element")
+                .and_then(serde_json::Value::as_str)
+                .and_then(tag_from_name)
+                .ok_or_else(|| invalid("list is missing a valid \"element\" type"))?;
+
+            let elements = array()?
+                .iter()
+                .map(from_typed_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some(found) = elements.iter().map(Value::tag).find(|&t| t != element) {
+                return Err(JsonError::MixedList {
+                    expected: element,
+                    found,
+                });
+            }
+
+            if elements.is_empty() {
+                Value::List(empty_list(element))
+            } else {
+                Value::List(list_from_values(elements)?)
+            }
+        }
+        Tag::Compound => {
+            let object = value
+                .as_object()
+                .ok_or_else(|| invalid("compound must be a JSON object"))?;
+
+            let mut compound = Compound::new();
+            for (key, value) in object {
+                compound.insert(key.clone(), from_typed_json(value)?);
+            }
+            Value::Compound(compound)
+        }
+        Tag::IntArray => Value::IntArray(
+            array()?
+                .iter()
+                .map(|v| v.as_i64().and_then(|v| v.try_into().ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(out_of_range)?,
+        ),
+        Tag::LongArray => Value::LongArray(
+            array()?
+                .iter()
+                .map(serde_json::Value::as_i64)
+                .collect::<Option<_>>()
+                .ok_or_else(out_of_range)?,
+        ),
+        Tag::End => return Err(invalid("\"end\" is only valid as a list element type")),
+    })
+}
+
+/// Formats a float through its shortest `f32` representation, so that e.g.
+/// `0.1f32` becomes `0.1` rather than `0.10000000149011612`.
+fn float_to_json(v: f32) -> Option<serde_json::Value> {
+    let v = v.to_string().parse().ok()?;
+    double_to_json(v)
+}
+
+fn double_to_json(v: f64) -> Option<serde_json::Value> {
+    Number::from_f64(v).map(serde_json::Value::Number)
+}
+
+fn float_from_json(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(v) => v.as_f64(),
+        serde_json::Value::String(v) => match v.as_str() {
+            "NaN" => Some(f64::NAN),
+            "inf" => Some(f64::INFINITY),
+            "-inf" => Some(f64::NEG_INFINITY),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn number_from_json(number: &Number, numbers: NumberInference) -> Value {
+    let double = number.as_f64().unwrap_or_default();
+    match (numbers, number.as_i64()) {
+        (NumberInference::Double, _) | (_, None) => match numbers {
+            NumberInference::Smallest if double as f32 as f64 == double => {
+                Value::Float(double as f32)
+            }
+            _ => Value::Double(double),
+        },
+        (NumberInference::IntOrDouble, Some(v)) => match i32::try_from(v) {
+            Ok(v) => Value::Int(v),
+            Err(_) => Value::Long(v),
+        },
+        (NumberInference::Smallest, Some(v)) => {
+            if let Ok(v) = i8::try_from(v) {
+                Value::Byte(v)
+            } else if let Ok(v) = i16::try_from(v) {
+                Value::Short(v)
+            } else if let Ok(v) = i32::try_from(v) {
+                Value::Int(v)
+            } else {
+                Value::Long(v)
+            }
+        }
+    }
+}
+
+/// Converts the numbers in `values` to a type that can hold all of them, if
+/// every value is a number.
+fn widen_numbers(mut values: Vec<Value>) -> Vec<Value> {
+    let Some(first) = values.first() else {
+        return values;
+    };
+
+    let mut widest = first.tag();
+    for value in &values {
+        if !value.is_number() {
+            return values;
+        }
+        widest = match (widest, value.tag()) {
+            (a, b) if a == b => a,
+            (Tag::Long, Tag::Float) | (Tag::Float, Tag::Long) => Tag::Double,
+            (Tag::Int, Tag::Float) | (Tag::Float, Tag::Int) => Tag::Double,
+            (a, b) if rank(b) > rank(a) => b,
+            (a, _) => a,
+        };
+    }
+
+    for value in &mut values {
+        *value = match widest {
+            Tag::Byte => value.as_i8().map(Value::Byte),
+            Tag::Short => value.as_i16().map(Value::Short),
+            Tag::Int => value.as_i32().map(Value::Int),
+            Tag::Long => value.as_i64().map(Value::Long),
+            Tag::Float => value.as_f32().map(Value::Float),
+            _ => value.as_f64().map(Value::Double),
+        }
+        .expect("value is a number");
+    }
+    values
+}
+
+fn rank(tag: Tag) -> u8 {
+    match tag {
+        Tag::Byte => 0,
+        Tag::Short => 1,
+        Tag::Int => 2,
+        Tag::Long => 3,
+        Tag::Float => 4,
+        _ => 5,
+    }
+}
+
+fn list_from_values(values: Vec<Value>) -> Result<List, JsonError> {
+    let mut list = List::End;
+    for value in values {
+        let found = value.tag();
+        if let Some(expected) = push_to_list(&mut list, value) {
+            return Err(JsonError::MixedList { expected, found });
+        }
+    }
+    Ok(list)
+}
+
+fn empty_list(element: Tag) -> List {
+    match element {
+        Tag::End => List::End,
+        Tag::Byte => List::Byte(Vec::new()),
+        Tag::Short => List::Short(Vec::new()),
+        Tag::Int => List::Int(Vec::new()),
+        Tag::Long => List::Long(Vec::new()),
+        Tag::Float => List::Float(Vec::new()),
+        Tag::Double => List::Double(Vec::new()),
+        Tag::ByteArray => List::ByteArray(Vec::new()),
+        Tag::String => List::String(Vec::new()),
+        Tag::List => List::List(Vec::new()),
+        Tag::Compound => List::Compound(Vec::new()),
+        Tag::IntArray => List::IntArray(Vec::new()),
+        Tag::LongArray => List::LongArray(Vec::new()),
+    }
+}
+
+fn type_name(tag: Tag) -> &'static str {
+    match tag {
+        Tag::End => "end",
+        Tag::Byte => "byte",
+        Tag::Short => "short",
+        Tag::Int => "int",
+        Tag::Long => "long",
+        Tag::Float => "float",
+        Tag::Double => "double",
+        Tag::ByteArray => "byte_array",
+        Tag::String => "string",
+        Tag::List => "list",
+        Tag::Compound => "compound",
+        Tag::IntArray => "int_array",
+        Tag::LongArray => "long_array",
+    }
+}
+
+fn tag_from_name(name: &str) -> Option<Tag> {
+    Some(match name {
+        "end" => Tag::End,
+        "byte" => Tag::Byte,
+        "short" => Tag::Short,
+        "int" => Tag::Int,
+        "long" => Tag::Long,
+        "float" => Tag::Float,
+        "double" => Tag::Double,
+        "byte_array" => Tag::ByteArray,
+        "string" => Tag::String,
+        "list" => Tag::List,
+        "compound" => Tag::Compound,
+        "int_array" => Tag::IntArray,
+        "long_array" => Tag::LongArray,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::json::{
+        from_json, from_typed_json, to_json, to_typed_json, JsonError, NumberInference,
+    };
+    use crate::{Compound, List, Tag, Value};
+
+    fn example() -> Value {
+        let mut nested = Compound::new();
+        nested.insert("empty_ints", List::Int(vec![]));
+        nested.insert("end", List::End);
+        nested.insert("lists", List::List(vec![List::Byte(vec![1]), List::End]));
+
+        let mut compound = Compound::new();
+        compound.insert("byte", 1i8);
+        compound.insert("short", -2i16);
+        compound.insert("int", 3i32);
+        compound.insert("long", i64::MIN);
+        compound.insert("float", 0.1f32);
+        compound.insert("infinity", f64::INFINITY);
+        compound.insert("string", "hello");
+        compound.insert("bytes", vec![1i8, -1]);
+        compound.insert("ints", vec![1i32, 2]);
+        compound.insert("longs", vec![i64::MAX]);
+        compound.insert("nested", nested.clone());
+        compound.insert("compounds", List::Compound(vec![nested]));
+        compound.into()
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(
+            to_json(&example()),
+            json!({
+                "byte": 1,
+                "short": -2,
+                "int": 3,
+                "long": i64::MIN,
+                "float": 0.1,
+                "infinity": null,
+                "string": "hello",
+                "bytes": [1, -1],
+                "ints": [1, 2],
+                "longs": [i64::MAX],
+                "nested": {"empty_ints": [], "end": [], "lists": [[1], []]},
+                "compounds": [{"empty_ints": [], "end": [], "lists": [[1], []]}],
+            })
+        );
+    }
+
+    #[test]
+    fn typed_round_trip() {
+        let value = example();
+        let json = to_typed_json(&value);
+
+        assert_eq!(json["value"]["byte"], json!({"type": "byte", "value": 1}));
+        assert_eq!(
+            json["value"]["nested"]["value"]["empty_ints"],
+            json!({"type": "list", "element": "int", "value": []})
+        );
+        assert_eq!(from_typed_json(&json), Ok(value));
+    }
+
+    #[test]
+    fn typed_errors() {
+        assert!(matches!(
+            from_typed_json(&json!({"type": "byte", "value": 128})),
+            Err(JsonError::InvalidTypedValue(_))
+        ));
+        assert!(matches!(
+            from_typed_json(&json!({"type": "bool", "value": 1})),
+            Err(JsonError::InvalidTypedValue(_))
+        ));
+        assert!(matches!(
+            from_typed_json(&json!(1)),
+            Err(JsonError::InvalidTypedValue(_))
+        ));
+        assert_eq!(
+            from_typed_json(&json!({
+                "type": "list",
+                "element": "int",
+                "value": [{"type": "byte", "value": 1}],
+            })),
+            Err(JsonError::MixedList {
+                expected: Tag::Int,
+                found: Tag::Byte
+            })
+        );
+    }
+
+    #[test]
+    fn number_inference() {
+        let json = json!([1, 300, 100000, 10000000000i64, 0.5, 0.1]);
+        let convert = |numbers| {
+            json.as_array()
+                .unwrap()
+                .iter()
+                .map(|v| from_json(v, numbers).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            convert(NumberInference::IntOrDouble),
+            [
+                Value::Int(1),
+                Value::Int(300),
+                Value::Int(100000),
+                Value::Long(10000000000),
+                Value::Double(0.5),
+                Value::Double(0.1),
+            ]
+        );
+        assert_eq!(
+            convert(NumberInference::Smallest),
+            [
+                Value::Byte(1),
+                Value::Short(300),
+                Value::Int(100000),
+                Value::Long(10000000000),
+                Value::Float(0.5),
+                Value::Double(0.1),
+            ]
+        );
+        assert!(convert(NumberInference::Double)
+            .iter()
+            .all(|v| matches!(v, Value::Double(_))));
+    }
+
+    #[test]
+    fn plain_to_nbt() {
+        let value = from_json(
+            &json!({
+                "has_skylight": true,
+                "name": "overworld",
+                "widened": [1, 300, 70000],
+                "mixed_numbers": [1, 0.5],
+            }),
+            NumberInference::Smallest,
+        )
+        .unwrap();
+
+        let mut expected = Compound::new();
+        expected.insert("has_skylight", 1i8);
+        expected.insert("name", "overworld");
+        expected.insert("widened", List::Int(vec![1, 300, 70000]));
+        expected.insert("mixed_numbers", List::Float(vec![1.0, 0.5]));
+        assert_eq!(value, Value::Compound(expected));
+
+        assert_eq!(
+            from_json(&json!(["a", 1]), NumberInference::default()),
+            Err(JsonError::MixedList {
+                expected: Tag::String,
+                found: Tag::Int
+            })
+        );
+        assert_eq!(
+            from_json(&json!({"a": null}), NumberInference::default()),
+            Err(JsonError::Null)
+        );
+    }
+}
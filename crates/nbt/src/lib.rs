@@ -1,7 +1,12 @@
 pub use compound::Compound;
 pub use list::List;
 pub use tag::Tag;
-pub use value::Value;
+pub use value::{Value, ValueMut, ValueRef};
+
+#[cfg(all(feature = "binary", feature = "serde"))]
+pub use binary::de::{from_reader, from_slice};
+#[cfg(all(feature = "binary", feature = "serde"))]
+pub use binary::ser::to_writer;
 
 pub mod error;
 
@@ -9,8 +14,15 @@ pub mod error;
 pub mod binary;
 pub mod compound;
 mod conv;
+pub mod diff;
+#[cfg(feature = "file")]
+pub mod file;
+#[cfg(feature = "serde_json")]
+pub mod json;
 pub mod list;
+pub mod path;
 #[cfg(feature = "serde")]
 pub mod serde;
+pub mod snbt;
 pub mod tag;
 pub mod value;
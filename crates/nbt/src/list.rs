@@ -2,7 +2,8 @@ use std::borrow::Cow;
 use std::hash::Hash;
 
 use crate::compound::Compound;
-use crate::value::Value;
+use crate::value::{Value, ValueMut, ValueRef};
+use crate::Tag;
 
 #[derive(Debug, Clone, Default)]
 pub enum List<S = String> {
@@ -48,6 +49,66 @@ impl<S> List<S> {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Returns the type of the elements of this list, or [`Tag::End`] if the
+    /// list has no element type.
+    pub fn element_tag(&self) -> Tag {
+        match self {
+            Self::End => Tag::End,
+            Self::Byte(_) => Tag::Byte,
+            Self::Short(_) => Tag::Short,
+            Self::Int(_) => Tag::Int,
+            Self::Long(_) => Tag::Long,
+            Self::Float(_) => Tag::Float,
+            Self::Double(_) => Tag::Double,
+            Self::ByteArray(_) => Tag::ByteArray,
+            Self::String(_) => Tag::String,
+            Self::List(_) => Tag::List,
+            Self::Compound(_) => Tag::Compound,
+            Self::IntArray(_) => Tag::IntArray,
+            Self::LongArray(_) => Tag::LongArray,
+        }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if it is out
+    /// of bounds.
+    pub fn get(&self, index: usize) -> Option<ValueRef<'_, S>> {
+        match self {
+            Self::End => None,
+            Self::Byte(v) => v.get(index).map(ValueRef::Byte),
+            Self::Short(v) => v.get(index).map(ValueRef::Short),
+            Self::Int(v) => v.get(index).map(ValueRef::Int),
+            Self::Long(v) => v.get(index).map(ValueRef::Long),
+            Self::Float(v) => v.get(index).map(ValueRef::Float),
+            Self::Double(v) => v.get(index).map(ValueRef::Double),
+            Self::ByteArray(v) => v.get(index).map(|v| ValueRef::ByteArray(v)),
+            Self::String(v) => v.get(index).map(ValueRef::String),
+            Self::List(v) => v.get(index).map(ValueRef::List),
+            Self::Compound(v) => v.get(index).map(ValueRef::Compound),
+            Self::IntArray(v) => v.get(index).map(|v| ValueRef::IntArray(v)),
+            Self::LongArray(v) => v.get(index).map(|v| ValueRef::LongArray(v)),
+        }
+    }
+
+    /// Returns a mutable reference to the element at `index`, or `None` if it
+    /// is out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<ValueMut<'_, S>> {
+        match self {
+            Self::End => None,
+            Self::Byte(v) => v.get_mut(index).map(ValueMut::Byte),
+            Self::Short(v) => v.get_mut(index).map(ValueMut::Short),
+            Self::Int(v) => v.get_mut(index).map(ValueMut::Int),
+            Self::Long(v) => v.get_mut(index).map(ValueMut::Long),
+            Self::Float(v) => v.get_mut(index).map(ValueMut::Float),
+            Self::Double(v) => v.get_mut(index).map(ValueMut::Double),
+            Self::ByteArray(v) => v.get_mut(index).map(ValueMut::ByteArray),
+            Self::String(v) => v.get_mut(index).map(ValueMut::String),
+            Self::List(v) => v.get_mut(index).map(ValueMut::List),
+            Self::Compound(v) => v.get_mut(index).map(ValueMut::Compound),
+            Self::IntArray(v) => v.get_mut(index).map(ValueMut::IntArray),
+            Self::LongArray(v) => v.get_mut(index).map(ValueMut::LongArray),
+        }
+    }
 }
 
 impl<S> PartialEq for List<S>
@@ -0,0 +1,748 @@
+//! NBT paths, as used by the `/data` command, e.g.
+//! `Inventory[{Slot:0b}].tag.display.Name`.
+
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::snbt::{write_quoted, Parser, SnbtError, SnbtErrorKind};
+use crate::{Compound, List, Tag, Value, ValueMut, ValueRef};
+
+/// A parsed NBT path that can select, replace, remove or merge into values
+/// nested inside a root compound.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NbtPath {
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// `{filter}`, only allowed as the first node.
+    MatchRoot(Compound),
+    /// `name`
+    Key(String),
+    /// `name{filter}`
+    MatchKey(String, Compound),
+    /// `[index]`, counting from the end if negative.
+    Index(i32),
+    /// `[]`
+    AllElements,
+    /// `[{filter}]`
+    MatchElement(Compound),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PathError {
+    #[error("Can't insert {found} where {expected} is expected")]
+    MixedTypes { expected: Tag, found: Tag },
+    #[error("Expected compound, found {0}")]
+    ExpectedCompound(Tag),
+    #[error("The root of a path can't be replaced or removed")]
+    RootNotModifiable,
+}
+
+impl NbtPath {
+    /// Returns a path selecting the key `key` of the root compound.
+    pub(crate) fn from_key(key: &str) -> Self {
+        Self {
+            nodes: vec![Node::Key(key.to_owned())],
+        }
+    }
+
+    /// Returns this path extended by the compound key `key`.
+    pub(crate) fn join_key(&self, key: &str) -> Self {
+        let mut path = self.clone();
+        path.nodes.push(Node::Key(key.to_owned()));
+        path
+    }
+
+    /// Returns this path extended by the list index `index`.
+    pub(crate) fn join_index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.nodes.push(Node::Index(index as i32));
+        path
+    }
+
+    /// Returns the first value selected by this path.
+    pub fn get<'a>(&self, root: &'a Compound) -> Option<ValueRef<'a>> {
+        self.get_all(root).into_iter().next()
+    }
+
+    /// Returns every value selected by this path, in order.
+    pub fn get_all<'a>(&self, root: &'a Compound) -> Vec<ValueRef<'a>> {
+        let mut current = vec![ValueRef::Compound(root)];
+        for node in &self.nodes {
+            let mut next = Vec::new();
+            for value in current {
+                node.get(value, &mut next);
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// Replaces every value selected by this path with `value`, creating
+    /// missing compounds and lists along the way. Returns the number of
+    /// values that actually changed.
+    pub fn set(&self, root: &mut Compound, value: Value) -> Result<usize, PathError> {
+        let (last, parents) = self.nodes.split_last().expect("paths are never empty");
+
+        let mut changed = 0;
+        for parent in resolve_mut(parents, Some(last), root, true) {
+            changed += last.set(parent, &value)?;
+        }
+        Ok(changed)
+    }
+
+    /// Removes every value selected by this path, returning how many were
+    /// removed.
+    pub fn remove(&self, root: &mut Compound) -> Result<usize, PathError> {
+        let (last, parents) = self.nodes.split_last().expect("paths are never empty");
+
+        let mut removed = 0;
+        for parent in resolve_mut(parents, Some(last), root, false) {
+            removed += last.remove(parent)?;
+        }
+        Ok(removed)
+    }
+
+    /// Merges `other` into every compound selected by this path, creating it
+    /// if missing. Nested compounds are merged recursively, other values are
+    /// replaced. Returns the number of compounds that actually changed.
+    pub fn merge(&self, root: &mut Compound, other: &Compound) -> Result<usize, PathError> {
+        let mut changed = 0;
+        for target in resolve_mut(&self.nodes, None, root, true) {
+            match target {
+                ValueMut::Compound(target) => changed += target.merge(other) as usize,
+                target => return Err(PathError::ExpectedCompound(target.tag())),
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Follows `nodes` from `root`, creating missing intermediate values if
+/// `create` is set. `following` is the node that will be applied to the
+/// result, which decides whether a compound or a list is created.
+fn resolve_mut<'a>(
+    nodes: &[Node],
+    following: Option<&Node>,
+    root: &'a mut Compound,
+    create: bool,
+) -> Vec<ValueMut<'a>> {
+    let mut current = vec![ValueMut::Compound(root)];
+    for (i, node) in nodes.iter().enumerate() {
+        let default = create.then(|| preferred_parent(nodes.get(i + 1).or(following)));
+        let mut next = Vec::new();
+        for value in current {
+            node.get_mut(value, default.as_ref(), &mut next);
+        }
+        current = next;
+    }
+    current
+}
+
+fn preferred_parent(next: Option<&Node>) -> Value {
+    match next {
+        Some(Node::Index(_) | Node::AllElements | Node::MatchElement(_)) => List::End.into(),
+        _ => Compound::new().into(),
+    }
+}
+
+impl Node {
+    fn get<'a>(&self, value: ValueRef<'a>, out: &mut Vec<ValueRef<'a>>) {
+        match (self, value) {
+            (Node::MatchRoot(filter), ValueRef::Compound(c)) if compound_matches(filter, c) => {
+                out.push(value);
+            }
+            (Node::Key(key), ValueRef::Compound(c)) => {
+                out.extend(c.get(key.as_str()).map(Value::as_value_ref));
+            }
+            (Node::MatchKey(key, filter), ValueRef::Compound(c)) => {
+                if let Some(Value::Compound(child)) = c.get(key.as_str()) {
+                    if compound_matches(filter, child) {
+                        out.push(ValueRef::Compound(child));
+                    }
+                }
+            }
+            (Node::Index(index), value) => {
+                let index = element_count(value).and_then(|len| resolve_index(*index, len));
+                out.extend(index.and_then(|index| element(value, index)));
+            }
+            (Node::AllElements, value) => {
+                let len = element_count(value).unwrap_or(0);
+                out.extend((0..len).filter_map(|index| element(value, index)));
+            }
+            (Node::MatchElement(filter), ValueRef::List(List::Compound(list))) => {
+                out.extend(
+                    list.iter()
+                        .filter(|c| compound_matches(filter, c))
+                        .map(ValueRef::Compound),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn get_mut<'a>(
+        &self,
+        value: ValueMut<'a>,
+        default: Option<&Value>,
+        out: &mut Vec<ValueMut<'a>>,
+    ) {
+        match (self, value) {
+            (Node::MatchRoot(filter), ValueMut::Compound(c)) if compound_matches(filter, c) => {
+                out.push(ValueMut::Compound(c));
+            }
+            (Node::Key(key), ValueMut::Compound(c)) => {
+                if let Some(default) = default {
+                    if c.get(key.as_str()).is_none() {
+                        c.insert(key.clone(), default.clone());
+                    }
+                }
+                out.extend(c.get_mut(key.as_str()).map(Value::as_value_mut));
+            }
+            (Node::MatchKey(key, filter), ValueMut::Compound(c)) => {
+                if default.is_some() && c.get(key.as_str()).is_none() {
+                    c.insert(key.clone(), filter.clone());
+                }
+                if let Some(Value::Compound(child)) = c.get_mut(key.as_str()) {
+                    if compound_matches(filter, child) {
+                        out.push(ValueMut::Compound(child));
+                    }
+                }
+            }
+            (Node::Index(index), value) => out.extend(element_mut(value, *index)),
+            (Node::AllElements, value) => elements_mut(value, out),
+            (Node::MatchElement(filter), ValueMut::List(list)) => {
+                if default.is_some() && list.is_empty() {
+                    *list = List::Compound(Vec::new());
+                }
+                if let List::Compound(list) = list {
+                    if default.is_some() && !list.iter().any(|c| compound_matches(filter, c)) {
+                        list.push(filter.clone());
+                    }
+                    out.extend(
+                        list.iter_mut()
+                            .filter(|c| compound_matches(filter, c))
+                            .map(ValueMut::Compound),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set(&self, parent: ValueMut, value: &Value) -> Result<usize, PathError> {
+        match (self, parent) {
+            (Node::MatchRoot(_), _) => Err(PathError::RootNotModifiable),
+            (Node::Key(key), ValueMut::Compound(c)) => {
+                let old = c.insert(key.clone(), value.clone());
+                Ok((old.as_ref() != Some(value)) as usize)
+            }
+            (Node::MatchKey(key, filter), ValueMut::Compound(c)) => match c.get_mut(key.as_str()) {
+                Some(Value::Compound(child)) if compound_matches(filter, child) => match value {
+                    Value::Compound(value) => Ok(replace(child, value)),
+                    value => Err(PathError::ExpectedCompound(value.tag())),
+                },
+                _ => Ok(0),
+            },
+            (Node::Index(index), parent) => match element_mut(parent, *index) {
+                Some(element) => assign(element, value),
+                None => Ok(0),
+            },
+            (Node::AllElements, ValueMut::List(list)) if list.is_empty() => {
+                *list = value.clone().into();
+                Ok(1)
+            }
+            (Node::AllElements, parent) => {
+                let mut elements = Vec::new();
+                elements_mut(parent, &mut elements);
+
+                let mut changed = 0;
+                for element in elements {
+                    changed += assign(element, value)?;
+                }
+                Ok(changed)
+            }
+            (Node::MatchElement(filter), ValueMut::List(List::Compound(list))) => {
+                let Value::Compound(value) = value else {
+                    return Err(PathError::MixedTypes {
+                        expected: Tag::Compound,
+                        found: value.tag(),
+                    });
+                };
+
+                let mut changed = 0;
+                for element in list.iter_mut().filter(|c| compound_matches(filter, c)) {
+                    changed += replace(element, value);
+                }
+                Ok(changed)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn remove(&self, parent: ValueMut) -> Result<usize, PathError> {
+        match (self, parent) {
+            (Node::MatchRoot(_), _) => Err(PathError::RootNotModifiable),
+            (Node::Key(key), ValueMut::Compound(c)) => {
+                Ok(c.remove(key.as_str()).is_some() as usize)
+            }
+            (Node::MatchKey(key, filter), ValueMut::Compound(c)) => match c.get(key.as_str()) {
+                Some(Value::Compound(child)) if compound_matches(filter, child) => {
+                    c.remove(key.as_str());
+                    Ok(1)
+                }
+                _ => Ok(0),
+            },
+            (Node::Index(index), parent) => {
+                let index =
+                    element_count(parent.as_value_ref()).and_then(|len| resolve_index(*index, len));
+                Ok(match index {
+                    Some(index) => retain_elements(parent, |i| i != index),
+                    None => 0,
+                })
+            }
+            (Node::AllElements, parent) => Ok(retain_elements(parent, |_| false)),
+            (Node::MatchElement(filter), ValueMut::List(List::Compound(list))) => {
+                let len = list.len();
+                list.retain(|c| !compound_matches(filter, c));
+                Ok(len - list.len())
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+fn resolve_index(index: i32, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        len.checked_sub(index.unsigned_abs() as usize)?
+    } else {
+        index as usize
+    };
+    (index < len).then_some(index)
+}
+
+/// Returns the number of elements in a list or array.
+fn element_count(value: ValueRef) -> Option<usize> {
+    match value {
+        ValueRef::List(list) => Some(list.len()),
+        ValueRef::ByteArray(v) => Some(v.len()),
+        ValueRef::IntArray(v) => Some(v.len()),
+        ValueRef::LongArray(v) => Some(v.len()),
+        _ => None,
+    }
+}
+
+fn element(value: ValueRef, index: usize) -> Option<ValueRef> {
+    match value {
+        ValueRef::List(list) => list.get(index),
+        ValueRef::ByteArray(v) => v.get(index).map(ValueRef::Byte),
+        ValueRef::IntArray(v) => v.get(index).map(ValueRef::Int),
+        ValueRef::LongArray(v) => v.get(index).map(ValueRef::Long),
+        _ => None,
+    }
+}
+
+fn element_mut(value: ValueMut, index: i32) -> Option<ValueMut> {
+    let index = resolve_index(index, element_count(value.as_value_ref())?)?;
+    match value {
+        ValueMut::List(list) => list.get_mut(index),
+        ValueMut::ByteArray(v) => v.get_mut(index).map(ValueMut::Byte),
+        ValueMut::IntArray(v) => v.get_mut(index).map(ValueMut::Int),
+        ValueMut::LongArray(v) => v.get_mut(index).map(ValueMut::Long),
+        _ => None,
+    }
+}
+
+fn elements_mut<'a>(value: ValueMut<'a>, out: &mut Vec<ValueMut<'a>>) {
+    match value {
+        ValueMut::List(list) => match list {
+            List::End => {}
+            List::Byte(v) => out.extend(v.iter_mut().map(ValueMut::Byte)),
+            List::Short(v) => out.extend(v.iter_mut().map(ValueMut::Short)),
+            List::Int(v) => out.extend(v.iter_mut().map(ValueMut::Int)),
+            List::Long(v) => out.extend(v.iter_mut().map(ValueMut::Long)),
+            List::Float(v) => out.extend(v.iter_mut().map(ValueMut::Float)),
+            List::Double(v) => out.extend(v.iter_mut().map(ValueMut::Double)),
+            List::ByteArray(v) => out.extend(v.iter_mut().map(ValueMut::ByteArray)),
+            List::String(v) => out.extend(v.iter_mut().map(ValueMut::String)),
+            List::List(v) => out.extend(v.iter_mut().map(ValueMut::List)),
+            List::Compound(v) => out.extend(v.iter_mut().map(ValueMut::Compound)),
+            List::IntArray(v) => out.extend(v.iter_mut().map(ValueMut::IntArray)),
+            List::LongArray(v) => out.extend(v.iter_mut().map(ValueMut::LongArray)),
+        },
+        ValueMut::ByteArray(v) => out.extend(v.iter_mut().map(ValueMut::Byte)),
+        ValueMut::IntArray(v) => out.extend(v.iter_mut().map(ValueMut::Int)),
+        ValueMut::LongArray(v) => out.extend(v.iter_mut().map(ValueMut::Long)),
+        _ => {}
+    }
+}
+
+/// Keeps the elements of a list or array whose index satisfies `keep`,
+/// returning how many were removed.
+fn retain_elements(value: ValueMut, mut keep: impl FnMut(usize) -> bool) -> usize {
+    macro_rules! retain {
+        ($v:expr) => {{
+            let len = $v.len();
+            let mut index = 0;
+            $v.retain(|_| {
+                index += 1;
+                keep(index - 1)
+            });
+            len - $v.len()
+        }};
+    }
+
+    match value {
+        ValueMut::List(list) => match list {
+            List::End => 0,
+            List::Byte(v) => retain!(v),
+            List::Short(v) => retain!(v),
+            List::Int(v) => retain!(v),
+            List::Long(v) => retain!(v),
+            List::Float(v) => retain!(v),
+            List::Double(v) => retain!(v),
+            List::ByteArray(v) => retain!(v),
+            List::String(v) => retain!(v),
+            List::List(v) => retain!(v),
+            List::Compound(v) => retain!(v),
+            List::IntArray(v) => retain!(v),
+            List::LongArray(v) => retain!(v),
+        },
+        ValueMut::ByteArray(v) => retain!(v),
+        ValueMut::IntArray(v) => retain!(v),
+        ValueMut::LongArray(v) => retain!(v),
+        _ => 0,
+    }
+}
+
+/// Overwrites a list or array element with `value`, which must be of the same
+/// type.
+fn assign(element: ValueMut, value: &Value) -> Result<usize, PathError> {
+    Ok(match (element, value) {
+        (ValueMut::Byte(e), Value::Byte(v)) => replace(e, v),
+        (ValueMut::Short(e), Value::Short(v)) => replace(e, v),
+        (ValueMut::Int(e), Value::Int(v)) => replace(e, v),
+        (ValueMut::Long(e), Value::Long(v)) => replace(e, v),
+        (ValueMut::Float(e), Value::Float(v)) => replace(e, v),
+        (ValueMut::Double(e), Value::Double(v)) => replace(e, v),
+        (ValueMut::ByteArray(e), Value::ByteArray(v)) => replace(e, v),
+        (ValueMut::String(e), Value::String(v)) => replace(e, v),
+        (ValueMut::List(e), Value::List(v)) => replace(e, v),
+        (ValueMut::Compound(e), Value::Compound(v)) => replace(e, v),
+        (ValueMut::IntArray(e), Value::IntArray(v)) => replace(e, v),
+        (ValueMut::LongArray(e), Value::LongArray(v)) => replace(e, v),
+        (element, value) => {
+            return Err(PathError::MixedTypes {
+                expected: element.tag(),
+                found: value.tag(),
+            })
+        }
+    })
+}
+
+fn replace<T: PartialEq + Clone>(slot: &mut T, value: &T) -> usize {
+    if slot == value {
+        0
+    } else {
+        slot.clone_from(value);
+        1
+    }
+}
+
+/// Checks whether `compound` contains everything in `filter`, the same way
+/// vanilla matches `{...}` in paths.
+fn compound_matches(filter: &Compound, compound: &Compound) -> bool {
+    filter.iter().all(|(key, filter)| {
+        compound
+            .get(key.as_str())
+            .is_some_and(|value| matches(filter.as_value_ref(), value.as_value_ref()))
+    })
+}
+
+fn matches(filter: ValueRef, value: ValueRef) -> bool {
+    match (filter, value) {
+        (ValueRef::Compound(filter), ValueRef::Compound(compound)) => {
+            compound_matches(filter, compound)
+        }
+        // Every element of a non-empty filter list must match some element of
+        // the list, in any order.
+        (ValueRef::List(filter), ValueRef::List(list)) => {
+            if filter.is_empty() {
+                return list.is_empty();
+            }
+            (0..filter.len())
+                .filter_map(|i| filter.get(i))
+                .all(|filter| {
+                    (0..list.len())
+                        .filter_map(|i| list.get(i))
+                        .any(|element| matches(filter, element))
+                })
+        }
+        (filter, value) => filter == value,
+    }
+}
+
+impl FromStr for NbtPath {
+    type Err = SnbtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s);
+        let mut nodes = Vec::new();
+        loop {
+            nodes.push(parse_node(&mut parser, nodes.is_empty())?);
+            match parser.peek() {
+                None => break,
+                Some('[' | '{') => {}
+                Some('.') => {
+                    parser.next();
+                }
+                Some(_) => return Err(parser.error(SnbtErrorKind::ExpectedChar('.'))),
+            }
+        }
+        Ok(NbtPath { nodes })
+    }
+}
+
+fn parse_node(parser: &mut Parser, first: bool) -> Result<Node, SnbtError> {
+    match parser.peek() {
+        Some('{') if first => Ok(Node::MatchRoot(parser.parse_compound()?)),
+        Some('[') => {
+            parser.next();
+            let node = match parser.peek() {
+                Some('{') => Node::MatchElement(parser.parse_compound()?),
+                Some(']') => Node::AllElements,
+                _ => Node::Index(parse_index(parser)?),
+            };
+            parser.expect(']')?;
+            Ok(node)
+        }
+        Some('"' | '\'') => {
+            let key = parser.parse_quoted_string()?;
+            parse_key_filter(parser, key)
+        }
+        _ => {
+            let mut key = String::new();
+            while let Some(c) = parser.peek().filter(|&c| is_allowed_in_unquoted_key(c)) {
+                key.push(c);
+                parser.next();
+            }
+            if key.is_empty() {
+                return Err(parser.error(SnbtErrorKind::InvalidPathNode));
+            }
+            parse_key_filter(parser, key)
+        }
+    }
+}
+
+fn parse_key_filter(parser: &mut Parser, key: String) -> Result<Node, SnbtError> {
+    if parser.peek() == Some('{') {
+        Ok(Node::MatchKey(key, parser.parse_compound()?))
+    } else {
+        Ok(Node::Key(key))
+    }
+}
+
+fn parse_index(parser: &mut Parser) -> Result<i32, SnbtError> {
+    let start = parser.pos;
+    let mut index = String::new();
+    while let Some(c) = parser.peek().filter(|&c| c == '-' || c.is_ascii_digit()) {
+        index.push(c);
+        parser.next();
+    }
+    index
+        .parse()
+        .map_err(|_| parser.error_at(SnbtErrorKind::InvalidPathNode, start))
+}
+
+fn is_allowed_in_unquoted_key(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '"' | '\'' | '[' | ']' | '.' | '{' | '}')
+}
+
+impl fmt::Display for NbtPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, node) in self.nodes.iter().enumerate() {
+            match node {
+                Node::MatchRoot(filter) => write!(f, "{filter}")?,
+                Node::Key(key) | Node::MatchKey(key, _) => {
+                    if i > 0 {
+                        f.write_char('.')?;
+                    }
+                    if !key.is_empty() && key.chars().all(is_allowed_in_unquoted_key) {
+                        f.write_str(key)?;
+                    } else {
+                        write_quoted(f, key)?;
+                    }
+                    if let Node::MatchKey(_, filter) = node {
+                        write!(f, "{filter}")?;
+                    }
+                }
+                Node::Index(index) => write!(f, "[{index}]")?,
+                Node::AllElements => f.write_str("[]")?,
+                Node::MatchElement(filter) => write!(f, "[{filter}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path::{NbtPath, PathError};
+    use crate::{Compound, List, Tag, Value, ValueRef};
+
+    fn path(s: &str) -> NbtPath {
+        s.parse().unwrap()
+    }
+
+    fn player() -> Compound {
+        r#"{
+            Health: 20.0f,
+            Inventory: [
+                {Slot: 0b, id: "minecraft:stone", Count: 64b},
+                {Slot: 1b, id: "minecraft:diamond_sword", Count: 1b,
+                    tag: {display: {Name: '{"text":"Sting"}'}, Damage: 3}},
+            ],
+            Pos: [1.0d, 64.0d, -3.5d],
+            UUID: [I; 1, 2, 3, 4],
+        }"#
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for s in [
+            "Inventory[0].tag.display",
+            "Inventory[{Slot:1b}].tag",
+            "Inventory[].id",
+            "{Health:20.0f}.Pos[-1]",
+            "Inventory[1].tag{Damage:3}",
+            "\"odd key\".\"a.b\"",
+            "minecraft:custom",
+        ] {
+            assert_eq!(path(s).to_string(), s);
+        }
+
+        assert!("".parse::<NbtPath>().is_err());
+        assert!("a..b".parse::<NbtPath>().is_err());
+        assert!("a[x]".parse::<NbtPath>().is_err());
+        assert!("a[0".parse::<NbtPath>().is_err());
+        assert!("a.{b:1}".parse::<NbtPath>().is_err());
+    }
+
+    #[test]
+    fn get() {
+        let player = player();
+
+        let name = path("Inventory[1].tag.display.Name").get(&player);
+        assert!(matches!(name, Some(ValueRef::String(s)) if s == r#"{"text":"Sting"}"#));
+
+        let ids = path("Inventory[].id").get_all(&player);
+        assert_eq!(ids.len(), 2);
+        assert!(matches!(ids[0], ValueRef::String(s) if s == "minecraft:stone"));
+
+        let sword = path("Inventory[{Slot:1b}].tag.Damage").get(&player);
+        assert!(matches!(sword, Some(ValueRef::Int(3))));
+
+        assert!(matches!(path("Pos[-1]").get(&player), Some(ValueRef::Double(v)) if *v == -3.5));
+        assert!(matches!(
+            path("UUID[2]").get(&player),
+            Some(ValueRef::Int(3))
+        ));
+        assert!(path("Pos[3]").get(&player).is_none());
+        assert!(path("Inventory[{Slot:2b}]").get(&player).is_none());
+        assert!(path("{Health:20.0f}.Health").get(&player).is_some());
+        assert!(path("{Health:1.0f}.Health").get(&player).is_none());
+    }
+
+    #[test]
+    fn set() {
+        let mut player = player();
+
+        assert_eq!(
+            path("Inventory[].Count").set(&mut player, Value::Byte(1)),
+            Ok(1)
+        );
+        assert!(matches!(
+            path("Inventory[0].Count").get(&player),
+            Some(ValueRef::Byte(1))
+        ));
+
+        // Missing compounds are created along the way.
+        path("Inventory[0].tag.display.Name")
+            .set(&mut player, "Rock".into())
+            .unwrap();
+        assert!(matches!(
+            path("Inventory[0].tag.display.Name").get(&player),
+            Some(ValueRef::String(s)) if s == "Rock"
+        ));
+
+        // So are list elements matching a filter.
+        path("Attributes[{Name:\"generic.luck\"}].Base")
+            .set(&mut player, 1.0f64.into())
+            .unwrap();
+        assert_eq!(
+            path("Attributes").get(&player).unwrap().to_value(),
+            Value::from(List::Compound(vec!["{Name:\"generic.luck\",Base:1.0d}"
+                .parse()
+                .unwrap()]))
+        );
+
+        assert_eq!(
+            path("Pos[0]").set(&mut player, Value::Int(1)),
+            Err(PathError::MixedTypes {
+                expected: Tag::Double,
+                found: Tag::Int
+            })
+        );
+        assert_eq!(
+            path("{}").set(&mut player, Value::Int(1)),
+            Err(PathError::RootNotModifiable)
+        );
+    }
+
+    #[test]
+    fn remove() {
+        let mut player = player();
+
+        assert_eq!(path("Inventory[{Slot:0b}]").remove(&mut player), Ok(1));
+        assert_eq!(path("Inventory[].tag.Damage").remove(&mut player), Ok(1));
+        assert_eq!(path("Pos[-1]").remove(&mut player), Ok(1));
+        assert_eq!(path("UUID[]").remove(&mut player), Ok(4));
+        assert_eq!(path("Missing.Key").remove(&mut player), Ok(0));
+
+        let inventory = path("Inventory").get(&player).unwrap();
+        assert!(matches!(inventory, ValueRef::List(l) if l.len() == 1));
+        assert!(path("Inventory[0].tag.Damage").get(&player).is_none());
+        assert!(matches!(path("Pos").get(&player), Some(ValueRef::List(l)) if l.len() == 2));
+        assert!(matches!(
+            path("UUID").get(&player),
+            Some(ValueRef::IntArray([]))
+        ));
+    }
+
+    #[test]
+    fn merge() {
+        let mut player = player();
+
+        let other: Compound = "{display:{Lore:['\"Sharp\"']},Damage:0}".parse().unwrap();
+        assert_eq!(path("Inventory[1].tag").merge(&mut player, &other), Ok(1));
+        assert_eq!(path("Inventory[1].tag").merge(&mut player, &other), Ok(0));
+
+        let tag = path("Inventory[1].tag").get(&player).unwrap().to_value();
+        let expected: Value = "{display:{Name:'{\"text\":\"Sting\"}',Lore:['\"Sharp\"']},Damage:0}"
+            .parse()
+            .unwrap();
+        assert_eq!(tag, expected);
+
+        assert_eq!(
+            path("Health").merge(&mut player, &other),
+            Err(PathError::ExpectedCompound(Tag::Float))
+        );
+    }
+}
@@ -5,6 +5,15 @@ use crate::error::Error;
 pub mod de;
 pub mod ser;
 
+/// Newtype struct names used to mark a sequence as a `TAG_Byte_Array`,
+/// `TAG_Int_Array` or `TAG_Long_Array` instead of a `TAG_List`.
+pub(crate) const BYTE_ARRAY_TOKEN: &str = "__cellophanemc_nbt_byte_array";
+pub(crate) const INT_ARRAY_TOKEN: &str = "__cellophanemc_nbt_int_array";
+pub(crate) const LONG_ARRAY_TOKEN: &str = "__cellophanemc_nbt_long_array";
+/// Newtype struct name through which [`Value`](crate::Value) asks to be
+/// told arrays apart from lists.
+pub(crate) const VALUE_TOKEN: &str = "__cellophanemc_nbt_value";
+
 impl serde::de::Error for Error {
     fn custom<T>(msg: T) -> Self
     where
@@ -13,3 +22,35 @@ impl serde::de::Error for Error {
         Self::Serde(format!("{msg}"))
     }
 }
+
+macro_rules! array_module {
+    ($name:ident, $token:ident, $tag:literal) => {
+        #[doc = concat!("Serializes a sequence as a `", $tag, "` instead of a `TAG_List`.")]
+        ///
+        /// Meant to be used with `#[serde(with = "...")]`. Deserialization is
+        /// passed through as-is, since arrays are always readable as sequences.
+        pub mod $name {
+            use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+            pub fn serialize<T, S>(array: &T, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                T: Serialize + ?Sized,
+                S: Serializer,
+            {
+                serializer.serialize_newtype_struct(super::$token, array)
+            }
+
+            pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+            where
+                T: Deserialize<'de>,
+                D: Deserializer<'de>,
+            {
+                T::deserialize(deserializer)
+            }
+        }
+    };
+}
+
+array_module!(byte_array, BYTE_ARRAY_TOKEN, "TAG_Byte_Array");
+array_module!(int_array, INT_ARRAY_TOKEN, "TAG_Int_Array");
+array_module!(long_array, LONG_ARRAY_TOKEN, "TAG_Long_Array");
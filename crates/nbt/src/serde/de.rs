@@ -1,16 +1,18 @@
 use std::fmt;
 use std::hash::Hash;
+use std::iter;
 use std::marker::PhantomData;
 
 use serde::de::value::{
     MapAccessDeserializer, MapDeserializer, SeqAccessDeserializer, StrDeserializer,
     StringDeserializer,
 };
-use serde::de::{IntoDeserializer, SeqAccess, Visitor};
+use serde::de::{IntoDeserializer, SeqAccess, VariantAccess, Visitor};
 use serde::{de, forward_to_deserialize_any, Deserialize, Deserializer};
 
 use crate::conv::{i8_vec_into_u8_vec, u8_slice_as_i8_slice, u8_vec_into_i8_vec};
 use crate::error::Error;
+use crate::serde::{BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN, VALUE_TOKEN};
 use crate::{Compound, List, Value};
 
 impl<'de, S> Deserialize<'de> for Value<S>
@@ -151,9 +153,34 @@ where
             {
                 Ok(Compound::deserialize(MapAccessDeserializer::new(map))?.into())
             }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_any(self)
+            }
+
+            /// Arrays are passed as a newtype variant named after their token
+            /// by [`Value`]'s deserializer, see [`array_enum`].
+            fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::EnumAccess<'de>,
+            {
+                let (token, variant) = data.variant::<String>()?;
+                match token.as_str() {
+                    BYTE_ARRAY_TOKEN => variant.newtype_variant().map(Value::ByteArray),
+                    INT_ARRAY_TOKEN => variant.newtype_variant().map(Value::IntArray),
+                    LONG_ARRAY_TOKEN => variant.newtype_variant().map(Value::LongArray),
+                    _ => Err(de::Error::unknown_variant(
+                        &token,
+                        &[BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN],
+                    )),
+                }
+            }
         }
 
-        deserializer.deserialize_any(ValueVisitor::<S>(PhantomData))
+        deserializer.deserialize_newtype_struct(VALUE_TOKEN, ValueVisitor::<S>(PhantomData))
     }
 }
 
@@ -326,9 +353,25 @@ impl<'de> Deserializer<'de> for Value {
         }
     }
 
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match (name, self) {
+            (VALUE_TOKEN, Value::ByteArray(v)) => array_enum(BYTE_ARRAY_TOKEN, v, visitor),
+            (VALUE_TOKEN, Value::IntArray(v)) => array_enum(INT_ARRAY_TOKEN, v, visitor),
+            (VALUE_TOKEN, Value::LongArray(v)) => array_enum(LONG_ARRAY_TOKEN, v, visitor),
+            (_, other) => other.deserialize_any(visitor),
+        }
+    }
+
     forward_to_deserialize_any! {
         i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        bytes byte_buf unit unit_struct seq tuple
         tuple_struct map struct identifier ignored_any
     }
 }
@@ -341,6 +384,17 @@ impl<'de> IntoDeserializer<'de, Error> for Value {
     }
 }
 
+/// Passes an array to the visitor of [`Value`] as a newtype variant named
+/// after the array's token, so it isn't read back as a [`List`].
+pub(crate) fn array_enum<'de, T, V>(token: &'static str, array: Vec<T>, visitor: V) -> Result<V::Value, Error>
+where
+    T: IntoDeserializer<'de, Error>,
+    V: Visitor<'de>,
+{
+    let entry = MapDeserializer::new(iter::once((token, array)));
+    visitor.visit_enum(MapAccessDeserializer::new(entry))
+}
+
 impl<'de> Deserializer<'de> for List {
     type Error = Error;
 
@@ -369,12 +423,12 @@ impl<'de> Deserializer<'de> for List {
             List::Long(v) => v.into_deserializer().deserialize_any(visitor),
             List::Float(v) => v.into_deserializer().deserialize_any(visitor),
             List::Double(v) => v.into_deserializer().deserialize_any(visitor),
-            List::ByteArray(v) => v.into_deserializer().deserialize_any(visitor),
+            List::ByteArray(v) => array_list(v, Value::ByteArray, visitor),
             List::String(v) => v.into_deserializer().deserialize_any(visitor),
             List::List(v) => v.into_deserializer().deserialize_any(visitor),
             List::Compound(v) => v.into_deserializer().deserialize_any(visitor),
-            List::IntArray(v) => v.into_deserializer().deserialize_any(visitor),
-            List::LongArray(v) => v.into_deserializer().deserialize_any(visitor),
+            List::IntArray(v) => array_list(v, Value::IntArray, visitor),
+            List::LongArray(v) => array_list(v, Value::LongArray, visitor),
         }
     }
 
@@ -385,6 +439,20 @@ impl<'de> Deserializer<'de> for List {
     }
 }
 
+/// Deserializes a list of arrays with each element as a [`Value`], which
+/// keeps the array types when read into a [`List`].
+fn array_list<'de, T, V>(
+    arrays: Vec<T>,
+    conv: fn(T) -> Value,
+    visitor: V,
+) -> Result<V::Value, Error>
+where
+    V: Visitor<'de>,
+{
+    let values = arrays.into_iter().map(conv).collect::<Vec<_>>();
+    values.into_deserializer().deserialize_any(visitor)
+}
+
 impl<'de> IntoDeserializer<'de, Error> for List {
     type Deserializer = Self;
 
@@ -392,3 +460,27 @@ impl<'de> IntoDeserializer<'de, Error> for List {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::{Compound, List, Value};
+
+    #[test]
+    fn keeps_array_types() {
+        let mut compound = Compound::new();
+        compound.insert("bytes", vec![1i8, 2]);
+        compound.insert("ints", vec![1, 2]);
+        compound.insert("longs", vec![1i64, 2]);
+        compound.insert("list", List::IntArray(vec![vec![1, 2], vec![]]));
+        compound.insert("ints_as_list", List::Int(vec![1, 2]));
+
+        let value = Value::Compound(compound.clone());
+        assert_eq!(Compound::deserialize(value).unwrap(), compound);
+        assert_eq!(
+            Vec::<i32>::deserialize(Value::IntArray(vec![3, 4])).unwrap(),
+            [3, 4]
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use std::hash::Hash;
 use std::marker::PhantomData;
 
-use serde::ser::{Impossible, SerializeMap, SerializeSeq, SerializeStruct};
+use serde::ser::{Impossible, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple};
 use serde::{Serialize, Serializer};
 
 use crate::conv::{i8_slice_as_u8_slice, u8_vec_into_i8_vec};
 use crate::error::Error;
+use crate::serde::{BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN};
 use crate::{Compound, List, Value};
 
 impl<Str> Serialize for Value<Str>
@@ -27,8 +28,8 @@ where
             Value::String(v) => v.serialize(serializer),
             Value::List(v) => v.serialize(serializer),
             Value::Compound(v) => v.serialize(serializer),
-            Value::IntArray(v) => v.serialize(serializer),
-            Value::LongArray(v) => v.serialize(serializer),
+            Value::IntArray(v) => serializer.serialize_newtype_struct(INT_ARRAY_TOKEN, v),
+            Value::LongArray(v) => serializer.serialize_newtype_struct(LONG_ARRAY_TOKEN, v),
         }
     }
 }
@@ -246,6 +247,11 @@ impl Serializer for CompoundSerializer {
     }
 }
 
+/// Serializes `value` into an in-memory [`Value`].
+pub(crate) fn serialize_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
 /// [`Serializer`] whose output is [`Value`].
 struct ValueSerializer;
 
@@ -256,7 +262,7 @@ impl Serializer for ValueSerializer {
 
     type SerializeSeq = ValueSerializeSeq;
 
-    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = ValueSerializeSeq;
 
     type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
 
@@ -354,13 +360,24 @@ impl Serializer for ValueSerializer {
 
     fn serialize_newtype_struct<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        value.serialize(self)
+        // Sequences of bytes and ints already become arrays, longs need the hint.
+        let value = value.serialize(self)?;
+        match (name, value) {
+            (BYTE_ARRAY_TOKEN, Value::List(List::End)) => Ok(Value::ByteArray(Vec::new())),
+            (INT_ARRAY_TOKEN, Value::List(List::End)) => Ok(Value::IntArray(Vec::new())),
+            (LONG_ARRAY_TOKEN, Value::List(List::End)) => Ok(Value::LongArray(Vec::new())),
+            (LONG_ARRAY_TOKEN, Value::List(List::Long(v))) => Ok(Value::LongArray(v)),
+            (BYTE_ARRAY_TOKEN | INT_ARRAY_TOKEN | LONG_ARRAY_TOKEN, Value::List(_)) => Err(
+                Error::Serde("NBT array elements have the wrong type".to_string()),
+            ),
+            (_, value) => Ok(value),
+        }
     }
 
     fn serialize_newtype_variant<T: ?Sized>(
@@ -382,8 +399,8 @@ impl Serializer for ValueSerializer {
         })
     }
 
-    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        unsupported!("tuple")
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
     }
 
     fn serialize_tuple_struct(
@@ -530,6 +547,23 @@ impl SerializeSeq for ValueSerializeSeq {
     }
 }
 
+impl SerializeTuple for ValueSerializeSeq {
+    type Ok = Value;
+
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize,
+    {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
 #[doc(hidden)]
 pub struct GenericSerializeMap<Ok> {
     /// Temp storage for `serialize_key`.
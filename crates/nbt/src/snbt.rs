@@ -0,0 +1,822 @@
+//! Stringified NBT, as used by commands and data packs, e.g.
+//! `{id:"minecraft:stone",Count:1b}`.
+
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::{Compound, List, Tag, Value};
+
+const MAX_DEPTH: usize = 512;
+
+/// Parses a single SNBT value. Surrounding whitespace is ignored.
+pub fn from_snbt_str(snbt: &str) -> Result<Value, SnbtError> {
+    let mut parser = Parser::new(snbt);
+    parser.skip_whitespace();
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error(SnbtErrorKind::TrailingData));
+    }
+    Ok(value)
+}
+
+/// Formats `value` as compact SNBT, e.g. `{a:1b,b:[I;1,2]}`.
+pub fn to_snbt_string<S: AsRef<str>>(value: &Value<S>) -> String {
+    SnbtFormatter::compact().format_value(value)
+}
+
+/// Formats `value` as SNBT spread over multiple lines, indenting nested
+/// compounds and lists with `indent`.
+pub fn to_snbt_string_pretty<S: AsRef<str>>(value: &Value<S>, indent: &str) -> String {
+    SnbtFormatter::pretty(indent).format_value(value)
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind} at line {line}, column {column}")]
+pub struct SnbtError {
+    pub kind: SnbtErrorKind,
+    /// Byte offset of the offending character.
+    pub offset: usize,
+    /// 1-based line of the offending character.
+    pub line: usize,
+    /// 1-based column (in characters) of the offending character.
+    pub column: usize,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SnbtErrorKind {
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("Expected '{0}'")]
+    ExpectedChar(char),
+    #[error("Expected key")]
+    ExpectedKey,
+    #[error("Expected value")]
+    ExpectedValue,
+    #[error("Expected compound")]
+    ExpectedCompound,
+    #[error("Invalid escape sequence '\\{0}' in quoted string")]
+    InvalidEscape(char),
+    #[error("Unterminated quoted string")]
+    UnterminatedString,
+    #[error("Can't insert {found} into list of {expected}")]
+    MixedList { expected: Tag, found: Tag },
+    #[error("Invalid array type '{0}'")]
+    InvalidArrayType(char),
+    #[error("Can't insert {found} into {array}")]
+    MixedArray { array: Tag, found: Tag },
+    #[error("Unexpected trailing data")]
+    TrailingData,
+    #[error("Reached maximum recursion depth")]
+    RecursionLimitExceeded,
+    #[error("Invalid NBT path element")]
+    InvalidPathNode,
+}
+
+pub(crate) struct Parser<'a> {
+    input: &'a str,
+    pub(crate) pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        Parser {
+            input,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn error(&self, kind: SnbtErrorKind) -> SnbtError {
+        self.error_at(kind, self.pos)
+    }
+
+    pub(crate) fn error_at(&self, kind: SnbtErrorKind, offset: usize) -> SnbtError {
+        let before = &self.input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+        SnbtError {
+            kind,
+            offset,
+            line,
+            column,
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    pub(crate) fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    pub(crate) fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    pub(crate) fn expect(&mut self, expected: char) -> Result<(), SnbtError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(_) => Err(self.error(SnbtErrorKind::ExpectedChar(expected))),
+            None => Err(self.error(SnbtErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    /// Consumes a `,` if there is one, returning whether it did.
+    fn element_separator(&mut self) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(',') {
+            self.next();
+            self.skip_whitespace();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn check_depth<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, SnbtError>,
+    ) -> Result<T, SnbtError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(SnbtErrorKind::RecursionLimitExceeded));
+        }
+
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
+    fn parse_value(&mut self) -> Result<Value, SnbtError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.check_depth(|p| Ok(p.parse_compound()?.into())),
+            Some('[') => self.check_depth(|p| p.parse_list_or_array()),
+            Some('"' | '\'') => Ok(Value::String(self.parse_quoted_string()?)),
+            Some(_) => {
+                let start = self.pos;
+                let token = self.parse_unquoted_string();
+                if token.is_empty() {
+                    return Err(self.error_at(SnbtErrorKind::ExpectedValue, start));
+                }
+                Ok(parse_scalar(token))
+            }
+            None => Err(self.error(SnbtErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    pub(crate) fn parse_compound(&mut self) -> Result<Compound, SnbtError> {
+        self.expect('{')?;
+        self.skip_whitespace();
+
+        let mut compound = Compound::new();
+        while self.peek() != Some('}') {
+            let start = self.pos;
+            let key = match self.peek() {
+                Some('"' | '\'') => self.parse_quoted_string()?,
+                Some(_) => self.parse_unquoted_string().to_owned(),
+                None => return Err(self.error(SnbtErrorKind::UnexpectedEnd)),
+            };
+            if key.is_empty() {
+                return Err(self.error_at(SnbtErrorKind::ExpectedKey, start));
+            }
+
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            compound.insert(key, value);
+
+            if !self.element_separator() {
+                break;
+            }
+        }
+
+        self.expect('}')?;
+        Ok(compound)
+    }
+
+    fn parse_list_or_array(&mut self) -> Result<Value, SnbtError> {
+        let rest = &self.input[self.pos..];
+        let mut chars = rest.chars().skip(1);
+        if let (Some(kind), Some(';')) = (chars.next(), chars.next()) {
+            if kind != '"' && kind != '\'' {
+                return self.parse_array(kind);
+            }
+        }
+        Ok(self.parse_list()?.into())
+    }
+
+    fn parse_array(&mut self, kind: char) -> Result<Value, SnbtError> {
+        self.expect('[')?;
+        let kind_offset = self.pos;
+        self.next();
+        self.next();
+        self.skip_whitespace();
+
+        let array = match kind {
+            'B' => Tag::ByteArray,
+            'I' => Tag::IntArray,
+            'L' => Tag::LongArray,
+            _ => return Err(self.error_at(SnbtErrorKind::InvalidArrayType(kind), kind_offset)),
+        };
+
+        let mut bytes = Vec::new();
+        let mut ints = Vec::new();
+        let mut longs = Vec::new();
+        while self.peek() != Some(']') {
+            let start = self.pos;
+            match (array, self.parse_value()?) {
+                (Tag::ByteArray, Value::Byte(v)) => bytes.push(v),
+                (Tag::IntArray, Value::Int(v)) => ints.push(v),
+                (Tag::LongArray, Value::Long(v)) => longs.push(v),
+                (array, found) => {
+                    let found = found.tag();
+                    return Err(self.error_at(SnbtErrorKind::MixedArray { array, found }, start));
+                }
+            }
+
+            if !self.element_separator() {
+                break;
+            }
+        }
+
+        self.expect(']')?;
+        Ok(match array {
+            Tag::ByteArray => Value::ByteArray(bytes),
+            Tag::IntArray => Value::IntArray(ints),
+            _ => Value::LongArray(longs),
+        })
+    }
+
+    fn parse_list(&mut self) -> Result<List, SnbtError> {
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        let mut list = List::End;
+        while self.peek() != Some(']') {
+            let start = self.pos;
+            let value = self.parse_value()?;
+            let found = value.tag();
+            if let Some(expected) = push_to_list(&mut list, value) {
+                return Err(self.error_at(SnbtErrorKind::MixedList { expected, found }, start));
+            }
+
+            if !self.element_separator() {
+                break;
+            }
+        }
+
+        self.expect(']')?;
+        Ok(list)
+    }
+
+    pub(crate) fn parse_quoted_string(&mut self) -> Result<String, SnbtError> {
+        let start = self.pos;
+        let quote = self.next().expect("quoted string must start with a quote");
+
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('\\') => match self.next() {
+                    Some(c) if c == quote || c == '\\' => string.push(c),
+                    Some(c) => {
+                        let offset = self.pos - c.len_utf8();
+                        return Err(self.error_at(SnbtErrorKind::InvalidEscape(c), offset));
+                    }
+                    None => break,
+                },
+                Some(c) if c == quote => return Ok(string),
+                Some(c) => string.push(c),
+                None => break,
+            }
+        }
+
+        Err(self.error_at(SnbtErrorKind::UnterminatedString, start))
+    }
+
+    fn parse_unquoted_string(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(is_allowed_in_unquoted_string) {
+            self.next();
+        }
+        &self.input[start..self.pos]
+    }
+}
+
+/// Appends `value` to `list`, returning the tag of the other elements if its
+/// type doesn't match them.
+pub(crate) fn push_to_list(list: &mut List, value: Value) -> Option<Tag> {
+    match (list, value) {
+        (list @ List::End, value) => *list = value.into(),
+        (List::Byte(v), Value::Byte(e)) => v.push(e),
+        (List::Short(v), Value::Short(e)) => v.push(e),
+        (List::Int(v), Value::Int(e)) => v.push(e),
+        (List::Long(v), Value::Long(e)) => v.push(e),
+        (List::Float(v), Value::Float(e)) => v.push(e),
+        (List::Double(v), Value::Double(e)) => v.push(e),
+        (List::ByteArray(v), Value::ByteArray(e)) => v.push(e),
+        (List::String(v), Value::String(e)) => v.push(e),
+        (List::List(v), Value::List(e)) => v.push(e),
+        (List::Compound(v), Value::Compound(e)) => v.push(e),
+        (List::IntArray(v), Value::IntArray(e)) => v.push(e),
+        (List::LongArray(v), Value::LongArray(e)) => v.push(e),
+        (list, _) => return Some(list.element_tag()),
+    }
+    None
+}
+
+fn is_allowed_in_unquoted_string(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+/// Infers the type of an unquoted token the same way vanilla does, falling
+/// back to a string if it isn't a valid number.
+fn parse_scalar(token: &str) -> Value {
+    fn parse<T: FromStr>(s: &str, f: impl FnOnce(T) -> Value) -> Option<Value> {
+        s.parse().ok().map(f)
+    }
+
+    let (body, suffix) = match token.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&token[..i], Some(c.to_ascii_lowercase())),
+        _ => (token, None),
+    };
+
+    let value = match suffix {
+        Some('b') if is_integer(body) => parse(body, Value::Byte),
+        Some('s') if is_integer(body) => parse(body, Value::Short),
+        Some('l') if is_integer(body) => parse(body, Value::Long),
+        Some('f') if is_float(body, true) || is_non_finite(body) => parse(body, Value::Float),
+        Some('d') if is_float(body, true) || is_non_finite(body) => parse(body, Value::Double),
+        _ if is_integer(token) => parse(token, Value::Int),
+        _ if is_float(token, false) => parse(token, Value::Double),
+        _ => None,
+    };
+
+    value.unwrap_or_else(|| match token {
+        "true" => Value::Byte(1),
+        "false" => Value::Byte(0),
+        _ => Value::String(token.to_owned()),
+    })
+}
+
+/// `[-+]?(?:0|[1-9][0-9]*)`
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    match digits.as_bytes() {
+        [b'0'] => true,
+        [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+        _ => false,
+    }
+}
+
+/// `[-+]?(?:[0-9]+[.]?|[0-9]*[.][0-9]+)(?:e[-+]?[0-9]+)?` if `suffixed`,
+/// otherwise a `.` is required: `[-+]?(?:[0-9]+[.]|[0-9]*[.][0-9]+)(?:e[-+]?[0-9]+)?`
+fn is_float(s: &str, suffixed: bool) -> bool {
+    let s = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+
+    let mantissa_ok = match mantissa.split_once('.') {
+        Some((int, frac)) => {
+            !(int.is_empty() && frac.is_empty())
+                && int.bytes().all(|b| b.is_ascii_digit())
+                && frac.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => suffixed && !mantissa.is_empty() && mantissa.bytes().all(|b| b.is_ascii_digit()),
+    };
+
+    let exponent_ok = match exponent {
+        Some(e) => {
+            let e = e.strip_prefix(['-', '+']).unwrap_or(e);
+            !e.is_empty() && e.bytes().all(|b| b.is_ascii_digit())
+        }
+        None => true,
+    };
+
+    mantissa_ok && exponent_ok
+}
+
+/// `NaN`, `inf` or `-inf`, as written for non-finite floats. Vanilla has no
+/// syntax for them, so they are only read with a suffix.
+fn is_non_finite(s: &str) -> bool {
+    matches!(s, "NaN" | "inf" | "-inf" | "+inf")
+}
+
+/// Writes NBT values as SNBT, either on a single line or spread over
+/// multiple lines with a configurable indentation.
+#[derive(Debug, Clone, Copy)]
+pub struct SnbtFormatter<'i> {
+    indent: Option<&'i str>,
+}
+
+impl SnbtFormatter<'static> {
+    pub fn compact() -> Self {
+        SnbtFormatter { indent: None }
+    }
+}
+
+impl<'i> SnbtFormatter<'i> {
+    pub fn pretty(indent: &'i str) -> Self {
+        SnbtFormatter {
+            indent: Some(indent),
+        }
+    }
+
+    pub fn format_value<S: AsRef<str>>(&self, value: &Value<S>) -> String {
+        let mut out = String::new();
+        self.write_value(&mut out, value, 0)
+            .expect("writing to a String can't fail");
+        out
+    }
+
+    pub fn write_value<S, W>(&self, out: &mut W, value: &Value<S>, depth: usize) -> fmt::Result
+    where
+        S: AsRef<str>,
+        W: Write,
+    {
+        match value {
+            Value::Byte(v) => write!(out, "{v}b"),
+            Value::Short(v) => write!(out, "{v}s"),
+            Value::Int(v) => write!(out, "{v}"),
+            Value::Long(v) => write!(out, "{v}L"),
+            Value::Float(v) => write!(out, "{}f", ryu::Buffer::new().format(*v)),
+            Value::Double(v) => write!(out, "{}d", ryu::Buffer::new().format(*v)),
+            Value::ByteArray(v) => self.write_array(out, "B", v.iter().map(|v| format!("{v}B"))),
+            Value::String(v) => write_quoted(out, v.as_ref()),
+            Value::List(v) => self.write_list(out, v, depth),
+            Value::Compound(v) => self.write_compound(out, v, depth),
+            Value::IntArray(v) => self.write_array(out, "I", v.iter().map(|v| v.to_string())),
+            Value::LongArray(v) => self.write_array(out, "L", v.iter().map(|v| format!("{v}L"))),
+        }
+    }
+
+    pub fn write_compound<S, W>(
+        &self,
+        out: &mut W,
+        compound: &Compound<S>,
+        depth: usize,
+    ) -> fmt::Result
+    where
+        S: AsRef<str>,
+        W: Write,
+    {
+        if compound.is_empty() {
+            return out.write_str("{}");
+        }
+
+        out.write_char('{')?;
+        for (i, (key, value)) in compound.into_iter().enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            self.write_newline(out, depth + 1)?;
+            write_key(out, key.as_ref())?;
+            out.write_str(if self.indent.is_some() { ": " } else { ":" })?;
+            self.write_value(out, value, depth + 1)?;
+        }
+        self.write_newline(out, depth)?;
+        out.write_char('}')
+    }
+
+    pub fn write_list<S, W>(&self, out: &mut W, list: &List<S>, depth: usize) -> fmt::Result
+    where
+        S: AsRef<str>,
+        W: Write,
+    {
+        macro_rules! elements {
+            ($v:expr, $variant:ident) => {
+                $v.iter()
+                    .map(|e| Value::$variant(e.clone()))
+                    .collect::<Vec<_>>()
+            };
+        }
+
+        // Only lists of compounds and lists are spread over multiple lines.
+        let nested = matches!(list, List::List(_) | List::Compound(_));
+        let scalars: Vec<Value<&str>> = match list {
+            List::End => Vec::new(),
+            List::Byte(v) => elements!(v, Byte),
+            List::Short(v) => elements!(v, Short),
+            List::Int(v) => elements!(v, Int),
+            List::Long(v) => elements!(v, Long),
+            List::Float(v) => elements!(v, Float),
+            List::Double(v) => elements!(v, Double),
+            List::ByteArray(v) => elements!(v, ByteArray),
+            List::String(v) => v.iter().map(|s| Value::String(s.as_ref())).collect(),
+            List::IntArray(v) => elements!(v, IntArray),
+            List::LongArray(v) => elements!(v, LongArray),
+            List::List(_) | List::Compound(_) => Vec::new(),
+        };
+
+        if list.is_empty() {
+            return out.write_str("[]");
+        }
+
+        out.write_char('[')?;
+        for i in 0..list.len() {
+            if i > 0 {
+                out.write_char(',')?;
+                if !nested && self.indent.is_some() {
+                    out.write_char(' ')?;
+                }
+            }
+            match list {
+                List::List(v) => {
+                    self.write_newline(out, depth + 1)?;
+                    self.write_list(out, &v[i], depth + 1)?;
+                }
+                List::Compound(v) => {
+                    self.write_newline(out, depth + 1)?;
+                    self.write_compound(out, &v[i], depth + 1)?;
+                }
+                _ => self.write_value(out, &scalars[i], depth + 1)?,
+            }
+        }
+        if nested {
+            self.write_newline(out, depth)?;
+        }
+        out.write_char(']')
+    }
+
+    fn write_array<W, I>(&self, out: &mut W, kind: &str, elements: I) -> fmt::Result
+    where
+        W: Write,
+        I: Iterator<Item = String>,
+    {
+        write!(out, "[{kind};")?;
+        for (i, element) in elements.enumerate() {
+            if i > 0 {
+                out.write_char(',')?;
+            }
+            if self.indent.is_some() {
+                out.write_char(' ')?;
+            }
+            out.write_str(&element)?;
+        }
+        out.write_char(']')
+    }
+
+    fn write_newline<W: Write>(&self, out: &mut W, depth: usize) -> fmt::Result {
+        if let Some(indent) = self.indent {
+            out.write_char('\n')?;
+            for _ in 0..depth {
+                out.write_str(indent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_key<W: Write>(out: &mut W, key: &str) -> fmt::Result {
+    if !key.is_empty() && key.chars().all(is_allowed_in_unquoted_string) {
+        out.write_str(key)
+    } else {
+        write_quoted(out, key)
+    }
+}
+
+/// Quotes `s` with `"`, or with `'` if it contains a `"` before any `'`.
+pub(crate) fn write_quoted<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    let quote = match s.find(['"', '\'']) {
+        Some(i) if s[i..].starts_with('"') => '\'',
+        _ => '"',
+    };
+
+    out.write_char(quote)?;
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            out.write_char('\\')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char(quote)
+}
+
+impl FromStr for Value {
+    type Err = SnbtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        from_snbt_str(s)
+    }
+}
+
+impl FromStr for Compound {
+    type Err = SnbtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser::new(s);
+        parser.skip_whitespace();
+        if parser.peek() != Some('{') {
+            return Err(parser.error(SnbtErrorKind::ExpectedCompound));
+        }
+        let compound = parser.parse_compound()?;
+        parser.skip_whitespace();
+        if parser.peek().is_some() {
+            return Err(parser.error(SnbtErrorKind::TrailingData));
+        }
+        Ok(compound)
+    }
+}
+
+/// Formats as compact SNBT, or as pretty SNBT indented with four spaces
+/// with the alternate flag (`{:#}`).
+impl<S: AsRef<str>> fmt::Display for Value<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter(f).write_value(f, self, 0)
+    }
+}
+
+impl<S: AsRef<str>> fmt::Display for Compound<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter(f).write_compound(f, self, 0)
+    }
+}
+
+impl<S: AsRef<str>> fmt::Display for List<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter(f).write_list(f, self, 0)
+    }
+}
+
+fn formatter(f: &fmt::Formatter<'_>) -> SnbtFormatter<'static> {
+    if f.alternate() {
+        SnbtFormatter::pretty("    ")
+    } else {
+        SnbtFormatter::compact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snbt::{from_snbt_str, to_snbt_string, to_snbt_string_pretty, SnbtErrorKind};
+    use crate::{Compound, List, Tag, Value};
+
+    #[test]
+    fn typed_numbers() {
+        let compound: Compound = "{a:1b,b:2s,c:3,d:4L,e:5.5f,f:6.5d,g:7.5,h:8f,i:true,j:1e3}"
+            .parse()
+            .unwrap();
+
+        assert_eq!(compound.get("a"), Some(&Value::Byte(1)));
+        assert_eq!(compound.get("b"), Some(&Value::Short(2)));
+        assert_eq!(compound.get("c"), Some(&Value::Int(3)));
+        assert_eq!(compound.get("d"), Some(&Value::Long(4)));
+        assert_eq!(compound.get("e"), Some(&Value::Float(5.5)));
+        assert_eq!(compound.get("f"), Some(&Value::Double(6.5)));
+        assert_eq!(compound.get("g"), Some(&Value::Double(7.5)));
+        assert_eq!(compound.get("h"), Some(&Value::Float(8.0)));
+        assert_eq!(compound.get("i"), Some(&Value::Byte(1)));
+        // No dot, so vanilla treats it as a string.
+        assert_eq!(compound.get("j"), Some(&Value::String("1e3".to_owned())));
+    }
+
+    #[test]
+    fn out_of_range_numbers_are_strings() {
+        assert_eq!(
+            from_snbt_str("300b").unwrap(),
+            Value::String("300b".to_owned())
+        );
+        assert_eq!(from_snbt_str("01").unwrap(), Value::String("01".to_owned()));
+    }
+
+    #[test]
+    fn arrays_and_lists() {
+        let compound: Compound =
+            "{ b: [B; 1b, -2B], i: [I;1,2,3], l: [L;1L], e: [], s: ['a', \"b\"] }"
+                .parse()
+                .unwrap();
+
+        assert_eq!(compound.get("b"), Some(&Value::ByteArray(vec![1, -2])));
+        assert_eq!(compound.get("i"), Some(&Value::IntArray(vec![1, 2, 3])));
+        assert_eq!(compound.get("l"), Some(&Value::LongArray(vec![1])));
+        assert_eq!(compound.get("e"), Some(&Value::List(List::End)));
+        assert_eq!(
+            compound.get("s"),
+            Some(&Value::List(List::String(vec![
+                "a".to_owned(),
+                "b".to_owned()
+            ])))
+        );
+    }
+
+    #[test]
+    fn quoted_strings() {
+        let compound: Compound =
+            r#"{"quoted key":"a \"b\" \\ c",'single':'it\'s'}"#.parse().unwrap();
+
+        assert_eq!(
+            compound.get("quoted key"),
+            Some(&Value::String(r#"a "b" \ c"#.to_owned()))
+        );
+        assert_eq!(
+            compound.get("single"),
+            Some(&Value::String("it's".to_owned()))
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let err = from_snbt_str("{a:1,\n  b:[1,2b]}").unwrap_err();
+        assert_eq!(
+            err.kind,
+            SnbtErrorKind::MixedList {
+                expected: Tag::Int,
+                found: Tag::Byte
+            }
+        );
+        assert_eq!((err.line, err.column), (2, 8));
+
+        let err = from_snbt_str("{a:\"x\\n\"}").unwrap_err();
+        assert_eq!(err.kind, SnbtErrorKind::InvalidEscape('n'));
+        assert_eq!(err.column, 7);
+
+        let err = from_snbt_str("[I;1,2b]").unwrap_err();
+        assert_eq!(
+            err.kind,
+            SnbtErrorKind::MixedArray {
+                array: Tag::IntArray,
+                found: Tag::Byte
+            }
+        );
+        assert_eq!(err.column, 6);
+
+        let err = from_snbt_str("{a:1} x").unwrap_err();
+        assert_eq!(err.kind, SnbtErrorKind::TrailingData);
+        assert_eq!(err.column, 7);
+
+        let err = from_snbt_str("{a:1").unwrap_err();
+        assert_eq!(err.kind, SnbtErrorKind::UnexpectedEnd);
+
+        let err = from_snbt_str("{:1}").unwrap_err();
+        assert_eq!(err.kind, SnbtErrorKind::ExpectedKey);
+        assert_eq!(err.column, 2);
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let snbt =
+            r#"{a:1b,b:[I;1,2],c:"it's",d:'say "hi"',e:[{x:1.5d},{}],"f g":[],h:[L;1L,2L],i:0.1f}"#;
+        let value = from_snbt_str(snbt).unwrap();
+
+        assert_eq!(to_snbt_string(&value), snbt);
+        assert_eq!(value.to_string(), snbt);
+    }
+
+    #[test]
+    fn non_finite_round_trip() {
+        let value = Value::List(List::Compound(vec![Compound::from_iter([
+            ("a".to_owned(), Value::Float(f32::INFINITY)),
+            ("b".to_owned(), Value::Float(f32::NEG_INFINITY)),
+            ("c".to_owned(), Value::Double(f64::INFINITY)),
+            ("d".to_owned(), Value::Double(f64::NEG_INFINITY)),
+            ("e".to_owned(), Value::List(List::Double(vec![f64::NAN]))),
+        ])]));
+        let snbt = to_snbt_string(&value);
+        assert_eq!(snbt, "[{a:inff,b:-inff,c:infd,d:-infd,e:[NaNd]}]");
+
+        let parsed = from_snbt_str(&snbt).unwrap();
+        let Value::List(List::Compound(compounds)) = &parsed else {
+            panic!("expected a list of compounds, got {parsed:?}");
+        };
+        assert_eq!(compounds[0].get("a"), Some(&Value::Float(f32::INFINITY)));
+        assert_eq!(
+            compounds[0].get("d"),
+            Some(&Value::Double(f64::NEG_INFINITY))
+        );
+        let Some(Value::List(List::Double(nan))) = compounds[0].get("e") else {
+            panic!("expected a list of doubles");
+        };
+        assert!(nan[0].is_nan());
+        // NaN isn't equal to itself, so compare the SNBT instead.
+        assert_eq!(to_snbt_string(&parsed), snbt);
+    }
+
+    #[test]
+    fn pretty_round_trip() {
+        let value = from_snbt_str("{a:{b:[1,2,3],c:[[B;1b],[B;]]},d:[{}]}").unwrap();
+        let pretty = to_snbt_string_pretty(&value, "  ");
+
+        assert_eq!(
+            pretty,
+            "{\n  a: {\n    b: [1, 2, 3],\n    c: [[B; 1B], [B;]]\n  },\n  d: [\n    {}\n  ]\n}"
+        );
+        assert_eq!(from_snbt_str(&pretty).unwrap(), value);
+        assert_eq!(from_snbt_str(&format!("{value:#}")).unwrap(), value);
+    }
+}
@@ -20,6 +20,42 @@ pub enum Value<S = String> {
     LongArray(Vec<i64>),
 }
 
+/// A borrowed [`Value`], which may also point into the elements of a
+/// [`List`] or an array.
+#[derive(Debug)]
+pub enum ValueRef<'a, S = String> {
+    Byte(&'a i8),
+    Short(&'a i16),
+    Int(&'a i32),
+    Long(&'a i64),
+    Float(&'a f32),
+    Double(&'a f64),
+    ByteArray(&'a [i8]),
+    String(&'a S),
+    List(&'a List<S>),
+    Compound(&'a Compound<S>),
+    IntArray(&'a [i32]),
+    LongArray(&'a [i64]),
+}
+
+/// A mutably borrowed [`Value`], which may also point into the elements of a
+/// [`List`] or an array.
+#[derive(Debug)]
+pub enum ValueMut<'a, S = String> {
+    Byte(&'a mut i8),
+    Short(&'a mut i16),
+    Int(&'a mut i32),
+    Long(&'a mut i64),
+    Float(&'a mut f32),
+    Double(&'a mut f64),
+    ByteArray(&'a mut Vec<i8>),
+    String(&'a mut S),
+    List(&'a mut List<S>),
+    Compound(&'a mut Compound<S>),
+    IntArray(&'a mut Vec<i32>),
+    LongArray(&'a mut Vec<i64>),
+}
+
 macro_rules! impl_value {
     ($name:ident, $($lifetime:lifetime)?, ($($deref:tt)*), $($reference:tt)*) => {
         macro_rules! as_number {
@@ -168,6 +204,108 @@ macro_rules! impl_value {
 }
 
 impl_value!(Value,,(*),);
+impl_value!(ValueRef, 'a, (**), &'a);
+impl_value!(ValueMut, 'a, (**), &'a mut);
+
+impl<S> Value<S> {
+    pub fn as_value_ref(&self) -> ValueRef<'_, S> {
+        match self {
+            Self::Byte(v) => ValueRef::Byte(v),
+            Self::Short(v) => ValueRef::Short(v),
+            Self::Int(v) => ValueRef::Int(v),
+            Self::Long(v) => ValueRef::Long(v),
+            Self::Float(v) => ValueRef::Float(v),
+            Self::Double(v) => ValueRef::Double(v),
+            Self::ByteArray(v) => ValueRef::ByteArray(v),
+            Self::String(v) => ValueRef::String(v),
+            Self::List(v) => ValueRef::List(v),
+            Self::Compound(v) => ValueRef::Compound(v),
+            Self::IntArray(v) => ValueRef::IntArray(v),
+            Self::LongArray(v) => ValueRef::LongArray(v),
+        }
+    }
+
+    pub fn as_value_mut(&mut self) -> ValueMut<'_, S> {
+        match self {
+            Self::Byte(v) => ValueMut::Byte(v),
+            Self::Short(v) => ValueMut::Short(v),
+            Self::Int(v) => ValueMut::Int(v),
+            Self::Long(v) => ValueMut::Long(v),
+            Self::Float(v) => ValueMut::Float(v),
+            Self::Double(v) => ValueMut::Double(v),
+            Self::ByteArray(v) => ValueMut::ByteArray(v),
+            Self::String(v) => ValueMut::String(v),
+            Self::List(v) => ValueMut::List(v),
+            Self::Compound(v) => ValueMut::Compound(v),
+            Self::IntArray(v) => ValueMut::IntArray(v),
+            Self::LongArray(v) => ValueMut::LongArray(v),
+        }
+    }
+}
+
+impl<S> Clone for ValueRef<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for ValueRef<'_, S> {}
+
+impl<S: Clone> ValueRef<'_, S> {
+    /// Clones the referenced data into an owned [`Value`].
+    pub fn to_value(&self) -> Value<S> {
+        match *self {
+            Self::Byte(v) => Value::Byte(*v),
+            Self::Short(v) => Value::Short(*v),
+            Self::Int(v) => Value::Int(*v),
+            Self::Long(v) => Value::Long(*v),
+            Self::Float(v) => Value::Float(*v),
+            Self::Double(v) => Value::Double(*v),
+            Self::ByteArray(v) => Value::ByteArray(v.to_vec()),
+            Self::String(v) => Value::String(v.clone()),
+            Self::List(v) => Value::List(v.clone()),
+            Self::Compound(v) => Value::Compound(v.clone()),
+            Self::IntArray(v) => Value::IntArray(v.to_vec()),
+            Self::LongArray(v) => Value::LongArray(v.to_vec()),
+        }
+    }
+}
+
+impl<'a, S> ValueMut<'a, S> {
+    pub fn as_value_ref(&self) -> ValueRef<'_, S> {
+        match self {
+            Self::Byte(v) => ValueRef::Byte(v),
+            Self::Short(v) => ValueRef::Short(v),
+            Self::Int(v) => ValueRef::Int(v),
+            Self::Long(v) => ValueRef::Long(v),
+            Self::Float(v) => ValueRef::Float(v),
+            Self::Double(v) => ValueRef::Double(v),
+            Self::ByteArray(v) => ValueRef::ByteArray(v),
+            Self::String(v) => ValueRef::String(v),
+            Self::List(v) => ValueRef::List(v),
+            Self::Compound(v) => ValueRef::Compound(v),
+            Self::IntArray(v) => ValueRef::IntArray(v),
+            Self::LongArray(v) => ValueRef::LongArray(v),
+        }
+    }
+
+    pub fn into_value_ref(self) -> ValueRef<'a, S> {
+        match self {
+            Self::Byte(v) => ValueRef::Byte(v),
+            Self::Short(v) => ValueRef::Short(v),
+            Self::Int(v) => ValueRef::Int(v),
+            Self::Long(v) => ValueRef::Long(v),
+            Self::Float(v) => ValueRef::Float(v),
+            Self::Double(v) => ValueRef::Double(v),
+            Self::ByteArray(v) => ValueRef::ByteArray(v),
+            Self::String(v) => ValueRef::String(v),
+            Self::List(v) => ValueRef::List(v),
+            Self::Compound(v) => ValueRef::Compound(v),
+            Self::IntArray(v) => ValueRef::IntArray(v),
+            Self::LongArray(v) => ValueRef::LongArray(v),
+        }
+    }
+}
 
 impl From<String> for Value<String> {
     fn from(s: String) -> Self {
@@ -254,5 +392,5 @@ pub fn to_value<T>(value: T) -> Result<Value, Error>
 where
     T: serde::Serialize,
 {
-    todo!()
+    crate::serde::ser::serialize_value(&value)
 }
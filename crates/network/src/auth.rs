@@ -0,0 +1,258 @@
+//! Online mode, where the session server confirms that a client is logged in
+//! to the account it claims to be.
+//!
+//! After the encryption handshake, the client tells the session server that
+//! it joins a server, identified by a hash over the handshake. The server
+//! computes the same [`server_hash`] and asks the session server whether the
+//! client did so, getting back the signed profile of the player.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+use uuid::Uuid;
+
+use cellophanemc_profile::{GameProfile, Property};
+
+pub const MOJANG_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("session server request failed")]
+    Http(#[from] Box<ureq::Error>),
+    #[error("failed to read session server response")]
+    Io(#[from] std::io::Error),
+    #[error("invalid session server response")]
+    InvalidResponse(#[from] serde_json::Error),
+    #[error("invalid profile id")]
+    InvalidId(#[from] uuid::Error),
+}
+
+/// Checks whether clients joined the server, like the `hasJoined` endpoint
+/// of the session server.
+pub trait SessionService: Debug + Send + Sync {
+    /// Returns the profile of `username` if its client joined the server
+    /// with `server_hash`, or `None` if it didn't.
+    ///
+    /// This may block, so it is run outside of the ECS.
+    fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<GameProfile>, SessionError>;
+}
+
+/// Hashes the result of an encryption handshake the way clients do before
+/// joining a server. Like Java's `BigInteger`, the SHA-1 digest is printed
+/// as a signed number.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key_der: &[u8]) -> String {
+    let mut digest: [u8; 20] = Sha1::new()
+        .chain_update(server_id)
+        .chain_update(shared_secret)
+        .chain_update(public_key_der)
+        .finalize()
+        .into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            (*byte, carry) = (!*byte).overflowing_add(carry as u8);
+        }
+    }
+
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    let hex = match hex.trim_start_matches('0') {
+        "" => "0",
+        hex => hex,
+    };
+    if negative {
+        format!("-{hex}")
+    } else {
+        hex.to_string()
+    }
+}
+
+/// Asks a session server over HTTP, by default the one of Mojang.
+#[derive(Debug, Clone)]
+pub struct HttpSessionService {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpSessionService {
+    /// Uses the session server at `url`, without the `/session/minecraft`
+    /// path.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build(),
+        }
+    }
+}
+
+impl Default for HttpSessionService {
+    fn default() -> Self {
+        Self::new(MOJANG_SESSION_SERVER)
+    }
+}
+
+impl SessionService for HttpSessionService {
+    fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<GameProfile>, SessionError> {
+        let mut request = self
+            .agent
+            .get(&format!("{}/session/minecraft/hasJoined", self.url))
+            .query("username", username)
+            .query("serverId", server_hash);
+        if let Some(ip) = ip {
+            request = request.query("ip", &ip.to_string());
+        }
+
+        let response = request.call().map_err(Box::new)?;
+        // The session server answers with no content if the client didn't join.
+        if response.status() == 204 {
+            return Ok(None);
+        }
+        parse_profile(response.into_reader()).map(Some)
+    }
+}
+
+#[derive(Deserialize)]
+struct ProfileResponse {
+    id: String,
+    name: String,
+    #[serde(default)]
+    properties: Vec<PropertyResponse>,
+}
+
+#[derive(Deserialize)]
+struct PropertyResponse {
+    name: String,
+    value: String,
+    signature: Option<String>,
+}
+
+fn parse_profile(reader: impl Read) -> Result<GameProfile, SessionError> {
+    let response: ProfileResponse = serde_json::from_reader(reader)?;
+    let properties = response
+        .properties
+        .into_iter()
+        .map(|property| Property::new(property.name, property.value, property.signature))
+        .collect();
+    Ok(GameProfile::new(Uuid::parse_str(&response.id)?, response.name).with_properties(properties))
+}
+
+/// Keeps the sessions of clients in memory, standing in for the session
+/// server in tests.
+#[derive(Debug, Default)]
+pub struct LocalSessionService {
+    sessions: Mutex<HashMap<String, (GameProfile, String)>>,
+}
+
+impl LocalSessionService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the client of `profile` joins the server with
+    /// `server_hash`, as clients do before answering the encryption request.
+    pub fn join(&self, profile: GameProfile, server_hash: String) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(profile.name().to_string(), (profile, server_hash));
+    }
+}
+
+impl SessionService for LocalSessionService {
+    fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+        _ip: Option<IpAddr>,
+    ) -> Result<Option<GameProfile>, SessionError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .get(username)
+            .filter(|(_, hash)| hash == server_hash)
+            .map(|(profile, _)| profile.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use cellophanemc_profile::{GameProfile, Property};
+
+    use crate::auth::{parse_profile, server_hash, LocalSessionService, SessionService};
+
+    #[test]
+    fn hashes_like_java() {
+        assert_eq!(
+            server_hash("Notch", &[], &[]),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+        assert_eq!(
+            server_hash("jeb_", &[], &[]),
+            "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+        );
+        assert_eq!(
+            server_hash("simon", &[], &[]),
+            "88e16a1019277b15d58faf0541e11910eb756f6"
+        );
+        assert_eq!(server_hash("", b"jeb", b"_"), server_hash("jeb_", &[], &[]));
+    }
+
+    #[test]
+    fn parses_profile() {
+        let json = r#"{
+            "id": "069a79f444e94726a5befca90e38aaf5",
+            "name": "Notch",
+            "properties": [{"name": "textures", "value": "e30=", "signature": "c2ln"}]
+        }"#;
+        let profile = parse_profile(json.as_bytes()).unwrap();
+        assert_eq!(
+            profile.id(),
+            Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+        );
+        assert_eq!(profile.name(), "Notch");
+        assert_eq!(
+            profile.property("textures"),
+            Some(&Property::new(
+                "textures".to_string(),
+                "e30=".to_string(),
+                Some("c2ln".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn local_sessions() {
+        let sessions = LocalSessionService::new();
+        let profile = GameProfile::new(Uuid::from_u128(1), "Steve".to_string());
+        sessions.join(profile.clone(), "abc".to_string());
+
+        assert_eq!(
+            sessions.has_joined("Steve", "abc", None).unwrap(),
+            Some(profile)
+        );
+        assert_eq!(sessions.has_joined("Steve", "def", None).unwrap(), None);
+        assert_eq!(sessions.has_joined("Alex", "abc", None).unwrap(), None);
+    }
+}
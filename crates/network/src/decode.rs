@@ -1,19 +1,51 @@
+use anyhow::bail;
+use bytes::{Buf, Bytes, BytesMut};
 #[cfg(feature = "compression")]
 use libdeflater::Decompressor;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
-use cellophanemc_protocol::VarInt;
+use cellophanemc_protocol::error::Error;
+use cellophanemc_protocol::var_int::VarIntDecodeError;
+use cellophanemc_protocol::{Decoder, VarInt};
 
+#[cfg(feature = "encryption")]
+use crate::encryption::Cipher;
+use crate::CodecChange;
+use crate::READ_BUF_SIZE;
+
+/// Largest frame a client may send, the largest length a 3 byte VarInt holds.
+pub const MAX_FRAME_SIZE: usize = 2_097_151;
+
+/// Largest size a compressed frame may declare for its uncompressed data.
+#[cfg(feature = "compression")]
+pub const MAX_UNCOMPRESSED_SIZE: usize = 8_388_608;
+
+/// Splits the bytes received from a connection into packet frames.
+///
+/// Bytes are buffered until a whole frame arrived, so [`read_frame`] can be
+/// cancelled without losing data.
+///
+/// [`read_frame`]: PacketDecoder::read_frame
 pub struct PacketDecoder {
+    buf: BytesMut,
     #[cfg(feature = "compression")]
     threshold: i32,
+    #[cfg(feature = "compression")]
+    decompressor: Decompressor,
+    #[cfg(feature = "encryption")]
+    cipher: Option<Cipher>,
 }
 
 impl Default for PacketDecoder {
     fn default() -> Self {
         Self {
+            buf: BytesMut::new(),
             #[cfg(feature = "compression")]
             threshold: -1,
+            #[cfg(feature = "compression")]
+            decompressor: Decompressor::new(),
+            #[cfg(feature = "encryption")]
+            cipher: None,
         }
     }
 }
@@ -23,42 +55,175 @@ impl PacketDecoder {
         Self::default()
     }
 
-    pub async fn read_frame(&mut self, reader: &mut (impl AsyncRead + Unpin + Send)) -> anyhow::Result<bytes::Bytes> {
-        let len = VarInt::read_async(reader).await?;
+    /// Decrypts everything read from now on, including buffered bytes that
+    /// aren't part of a frame yet.
+    #[cfg(feature = "encryption")]
+    pub fn enable_encryption(&mut self, shared_secret: &[u8; 16]) {
+        let mut cipher = Cipher::decryptor(shared_secret);
+        cipher.apply(&mut self.buf);
+        self.cipher = Some(cipher);
+    }
+
+    /// Expects frames in the compressed format from now on, or the plain one
+    /// if `threshold` is negative.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.threshold = threshold;
+    }
+
+    pub(crate) fn apply(&mut self, change: CodecChange) {
+        match change {
+            #[cfg(feature = "compression")]
+            CodecChange::Compression(threshold) => self.set_compression(threshold),
+            #[cfg(feature = "encryption")]
+            CodecChange::Encryption(shared_secret) => self.enable_encryption(&shared_secret),
+        }
+    }
+
+    pub async fn read_frame(
+        &mut self,
+        reader: &mut (impl AsyncRead + Unpin + Send),
+    ) -> anyhow::Result<Bytes> {
+        loop {
+            if let Some(frame) = self.try_next_frame()? {
+                return Ok(frame);
+            }
+
+            self.buf.reserve(READ_BUF_SIZE);
+            let read = reader.read_buf(&mut self.buf).await?;
+            if read == 0 {
+                bail!("connection closed");
+            }
+
+            #[cfg(feature = "encryption")]
+            if let Some(cipher) = &mut self.cipher {
+                let start = self.buf.len() - read;
+                cipher.apply(&mut self.buf[start..]);
+            }
+        }
+    }
+
+    /// Takes the next frame out of the buffer, if it was received completely.
+    pub fn try_next_frame(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let mut header = &self.buf[..];
+        let len = match VarInt::read(&mut header) {
+            Ok(len) => len.0,
+            Err(Error::VarInt(VarIntDecodeError::Incomplete)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let len = match usize::try_from(len) {
+            Ok(len) if len <= MAX_FRAME_SIZE => len,
+            _ => bail!("invalid frame length {len}"),
+        };
+        if header.len() < len {
+            return Ok(None);
+        }
 
-        let data: Vec<u8>;
+        let header_len = self.buf.len() - header.len();
+        self.buf.advance(header_len);
+        let frame = self.buf.split_to(len).freeze();
 
         #[cfg(feature = "compression")]
-        {
-            data = if self.threshold >= 0 {
-                let data_len = VarInt::read_async(reader).await?;
-                let mut compressed = vec![0; (len.0 - data_len.written_size()) as usize];
-                reader.read_exact(&mut compressed).await?;
-
-                if data_len.0 > 0 {
-                    let mut decompressed = vec![0; data_len.0 as usize];
-
-                    let mut decompressor = Decompressor::new();
-                    decompressor.zlib_decompress(&compressed, &mut decompressed)?;
-
-                    decompressed
-                } else {
-                    compressed
-                }
-            } else {
-                let mut buf = vec![0; len.0 as usize];
-                reader.read_exact(&mut buf).await?;
-                buf
-            };
+        if self.threshold >= 0 {
+            return self.decompress(frame).map(Some);
         }
 
-        #[cfg(not(feature = "compression"))]
-        {
-            let mut buf = vec![0; len.0 as usize];
-            reader.read_exact(&mut buf).await?;
-            data = buf;
+        Ok(Some(frame))
+    }
+
+    #[cfg(feature = "compression")]
+    fn decompress(&mut self, frame: Bytes) -> anyhow::Result<Bytes> {
+        let mut data = &frame[..];
+        let data_len = VarInt::read(&mut data)?.0;
+        if data_len == 0 {
+            return Ok(frame.slice(frame.len() - data.len()..));
+        }
+
+        if data_len < self.threshold {
+            bail!(
+                "compressed frame of {data_len} bytes is below the threshold of {}",
+                self.threshold
+            );
+        }
+        let data_len = match usize::try_from(data_len) {
+            Ok(data_len) if data_len <= MAX_UNCOMPRESSED_SIZE => data_len,
+            _ => bail!("invalid uncompressed frame length {data_len}"),
         };
 
-        Ok(data.into())
+        let mut decompressed = vec![0; data_len];
+        let size = self.decompressor.zlib_decompress(data, &mut decompressed)?;
+        if size != data_len {
+            bail!("frame declared {data_len} bytes but decompressed to {size}");
+        }
+        Ok(decompressed.into())
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use libdeflater::{CompressionLvl, Compressor};
+
+    use cellophanemc_protocol::{Encoder, VarInt};
+
+    use crate::decode::{PacketDecoder, MAX_UNCOMPRESSED_SIZE};
+    use crate::encode::PacketEncoder;
+
+    fn compressed_codec(threshold: i32) -> (PacketEncoder, PacketDecoder) {
+        let mut encoder = PacketEncoder::new();
+        encoder.set_compression(threshold);
+        let mut decoder = PacketDecoder::new();
+        decoder.set_compression(threshold);
+        (encoder, decoder)
+    }
+
+    /// Writes a frame in the compressed format with the given header.
+    fn frame(data_len: i32, payload: &[u8]) -> Vec<u8> {
+        let data_len = VarInt(data_len);
+        let mut frame = Vec::new();
+        VarInt((data_len.written_size() + payload.len()) as i32)
+            .write(&mut frame)
+            .unwrap();
+        data_len.write(&mut frame).unwrap();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[tokio::test]
+    async fn compresses_above_threshold() {
+        let (mut encoder, mut decoder) = compressed_codec(64);
+        let small = vec![1; 10];
+        let large = vec![2; 1000];
+
+        let mut written = Vec::new();
+        encoder.write_frame(&mut written, &small).await.unwrap();
+        assert_eq!(written.len(), 12);
+        encoder.write_frame(&mut written, &large).await.unwrap();
+        assert!(written.len() < 12 + 100);
+
+        let mut reader = &written[..];
+        assert_eq!(decoder.read_frame(&mut reader).await.unwrap(), small);
+        assert_eq!(decoder.read_frame(&mut reader).await.unwrap(), large);
+    }
+
+    #[test]
+    fn rejects_invalid_lengths() {
+        let (_, mut decoder) = compressed_codec(64);
+        decoder.buf.extend_from_slice(&frame(10, &[0; 8]));
+        assert!(decoder.try_next_frame().is_err());
+
+        let (_, mut decoder) = compressed_codec(64);
+        let data_len = MAX_UNCOMPRESSED_SIZE as i32 + 1;
+        decoder.buf.extend_from_slice(&frame(data_len, &[0; 8]));
+        assert!(decoder.try_next_frame().is_err());
+
+        let (_, mut decoder) = compressed_codec(64);
+        let mut compressed = vec![0; 1024];
+        let size = Compressor::new(CompressionLvl::default())
+            .zlib_compress(&[3; 100], &mut compressed)
+            .unwrap();
+        decoder
+            .buf
+            .extend_from_slice(&frame(200, &compressed[..size]));
+        assert!(decoder.try_next_frame().is_err());
     }
 }
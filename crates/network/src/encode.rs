@@ -1,13 +1,24 @@
+#[cfg(feature = "compression")]
+use std::borrow::Cow;
+
 #[cfg(feature = "compression")]
 use libdeflater::{CompressionLvl, Compressor};
 use tokio::io;
 use tokio::io::AsyncWriteExt;
 
-use cellophanemc_protocol::VarInt;
+use cellophanemc_protocol::{Encoder, VarInt};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::Cipher;
+use crate::CodecChange;
 
 pub struct PacketEncoder {
     #[cfg(feature = "compression")]
     threshold: i32,
+    #[cfg(feature = "compression")]
+    compressor: Compressor,
+    #[cfg(feature = "encryption")]
+    cipher: Option<Cipher>,
 }
 
 impl Default for PacketEncoder {
@@ -15,6 +26,10 @@ impl Default for PacketEncoder {
         Self {
             #[cfg(feature = "compression")]
             threshold: -1,
+            #[cfg(feature = "compression")]
+            compressor: Compressor::new(CompressionLvl::default()),
+            #[cfg(feature = "encryption")]
+            cipher: None,
         }
     }
 }
@@ -24,41 +39,69 @@ impl PacketEncoder {
         Self::default()
     }
 
+    /// Encrypts every frame written from now on.
+    #[cfg(feature = "encryption")]
+    pub fn enable_encryption(&mut self, shared_secret: &[u8; 16]) {
+        self.cipher = Some(Cipher::encryptor(shared_secret));
+    }
+
+    /// Compresses frames of at least `threshold` bytes from now on, or turns
+    /// compression off if it is negative.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.threshold = threshold;
+    }
+
+    pub(crate) fn apply(&mut self, change: CodecChange) {
+        match change {
+            #[cfg(feature = "compression")]
+            CodecChange::Compression(threshold) => self.set_compression(threshold),
+            #[cfg(feature = "encryption")]
+            CodecChange::Encryption(shared_secret) => self.enable_encryption(&shared_secret),
+        }
+    }
+
     pub async fn write_frame(
         &mut self,
         dest: &mut (impl io::AsyncWrite + Unpin + Send),
         data: &[u8],
     ) -> anyhow::Result<()> {
+        #[allow(unused_mut)]
+        let mut frame = self.encode_frame(data)?;
+
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &mut self.cipher {
+            cipher.apply(&mut frame);
+        }
+
+        dest.write_all(&frame).await?;
+        Ok(())
+    }
+
+    fn encode_frame(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
         let data_len = VarInt(data.len() as i32);
+        let mut frame = Vec::with_capacity(VarInt::MAX_SIZE + data.len());
 
         #[cfg(feature = "compression")]
         if self.threshold >= 0 {
-            if data_len.0 > self.threshold {
-                let mut compressor = Compressor::new(CompressionLvl(4));
-                let max_size = compressor.gzip_compress_bound(data_len.0 as usize);
-                let mut compressed = vec![0; max_size];
-                let actual_size = VarInt(compressor.gzip_compress(data, &mut compressed)? as i32);
-
-                VarInt((actual_size.written_size() + actual_size.0) as i32).write_async(dest).await?;
-                actual_size.write_async(dest).await?;
-                dest.write_all(&compressed).await?;
+            // The length of the uncompressed data, or 0 if it isn't compressed.
+            let (data_len, payload) = if data.len() >= self.threshold as usize {
+                let mut compressed = vec![0; self.compressor.zlib_compress_bound(data.len())];
+                let size = self.compressor.zlib_compress(data, &mut compressed)?;
+                compressed.truncate(size);
+                (data_len, Cow::Owned(compressed))
             } else {
-                let data_len_size = 1;
-                VarInt((data_len_size + data_len.0)).write_async(dest).await?;
-                dest.write_u8(0).await?;
-                dest.write_all(data).await?
-            }
-        } else {
-            data_len.write_async(dest).await?;
-            dest.write_all(data).await?
-        }
+                (VarInt(0), Cow::Borrowed(data))
+            };
 
-        #[cfg(not(feature = "compression"))]
-        {
-            data_len.write_async(dest).await?;
-            dest.write_all(data).await?
+            VarInt((data_len.written_size() + payload.len()) as i32).write(&mut frame)?;
+            data_len.write(&mut frame)?;
+            frame.extend_from_slice(&payload);
+            return Ok(frame);
         }
 
-        Ok(())
+        data_len.write(&mut frame)?;
+        frame.extend_from_slice(data);
+        Ok(frame)
     }
 }
@@ -0,0 +1,177 @@
+use aes::cipher::inout::InOutBuf;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::Aes128;
+use bevy_ecs::system::Resource;
+use rand::Rng;
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey};
+use thiserror::Error;
+
+use cellophanemc_protocol::packets::client::EncryptionResponse;
+use cellophanemc_protocol::packets::server::EncryptionRequest;
+
+/// Size of the RSA key vanilla servers use.
+const KEY_BITS: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("RSA error")]
+    Rsa(#[from] rsa::Error),
+    #[error("failed to encode public key")]
+    PublicKey(#[from] rsa::pkcs8::spki::Error),
+    #[error("no encryption was requested")]
+    NotRequested,
+    #[error("verify token doesn't match")]
+    VerifyTokenMismatch,
+    #[error("shared secret has {0} bytes instead of 16")]
+    InvalidSharedSecret(usize),
+}
+
+/// The RSA key pair the server uses to receive the shared secret from
+/// clients.
+#[derive(Resource)]
+pub struct ServerKey {
+    private_key: RsaPrivateKey,
+    public_key_der: Vec<u8>,
+}
+
+impl ServerKey {
+    pub fn generate() -> Result<Self, EncryptionError> {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), KEY_BITS)?;
+        let public_key_der = private_key.to_public_key().to_public_key_der()?.into_vec();
+        Ok(Self {
+            private_key,
+            public_key_der,
+        })
+    }
+
+    /// Returns the public key as the DER encoded `SubjectPublicKeyInfo` that
+    /// is sent to clients.
+    pub fn public_key_der(&self) -> &[u8] {
+        &self.public_key_der
+    }
+
+    /// Creates the request that starts the handshake, along with the verify
+    /// token to check the response against.
+    pub fn request(&self) -> (EncryptionRequest, [u8; 4]) {
+        let verify_token = rand::thread_rng().gen::<[u8; 4]>();
+        let request = EncryptionRequest {
+            server_id: String::new(),
+            public_key: self.public_key_der.clone(),
+            verify_token: verify_token.to_vec(),
+        };
+        (request, verify_token)
+    }
+
+    /// Decrypts the response of a client and returns the shared secret.
+    pub fn decrypt_response(
+        &self,
+        response: &EncryptionResponse,
+        verify_token: &[u8; 4],
+    ) -> Result<[u8; 16], EncryptionError> {
+        let token = self
+            .private_key
+            .decrypt(Pkcs1v15Encrypt, &response.verify_token)?;
+        if token != verify_token {
+            return Err(EncryptionError::VerifyTokenMismatch);
+        }
+        let secret = self
+            .private_key
+            .decrypt(Pkcs1v15Encrypt, &response.shared_secret)?;
+        secret
+            .as_slice()
+            .try_into()
+            .map_err(|_| EncryptionError::InvalidSharedSecret(secret.len()))
+    }
+}
+
+/// AES/CFB8 stream cipher of one direction of a connection. The shared
+/// secret is both the key and the initial vector.
+pub(crate) enum Cipher {
+    Encrypt(cfb8::Encryptor<Aes128>),
+    Decrypt(cfb8::Decryptor<Aes128>),
+}
+
+impl Cipher {
+    pub(crate) fn encryptor(shared_secret: &[u8; 16]) -> Self {
+        Self::Encrypt(cfb8::Encryptor::new(
+            shared_secret.into(),
+            shared_secret.into(),
+        ))
+    }
+
+    pub(crate) fn decryptor(shared_secret: &[u8; 16]) -> Self {
+        Self::Decrypt(cfb8::Decryptor::new(
+            shared_secret.into(),
+            shared_secret.into(),
+        ))
+    }
+
+    /// Encrypts or decrypts `data` in place, continuing the stream.
+    pub(crate) fn apply(&mut self, data: &mut [u8]) {
+        // CFB8 blocks are single bytes, so there is never a partial block
+        // left over, and the cipher state carries over between calls however
+        // the stream is split.
+        let (blocks, _) = InOutBuf::from(data).into_chunks();
+        match self {
+            Cipher::Encrypt(cipher) => cipher.encrypt_blocks_inout_mut(blocks),
+            Cipher::Decrypt(cipher) => cipher.decrypt_blocks_inout_mut(blocks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::pkcs8::DecodePublicKey;
+    use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+
+    use cellophanemc_protocol::packets::client::EncryptionResponse;
+
+    use crate::encryption::{Cipher, EncryptionError, ServerKey};
+
+    #[test]
+    fn checks_verify_token() {
+        let key = ServerKey::generate().unwrap();
+        let (request, verify_token) = key.request();
+        assert_eq!(request.verify_token, verify_token);
+
+        let public_key = RsaPublicKey::from_public_key_der(&request.public_key).unwrap();
+        let mut rng = rand::thread_rng();
+        let mut response = EncryptionResponse {
+            shared_secret: public_key
+                .encrypt(&mut rng, Pkcs1v15Encrypt, &[7; 16])
+                .unwrap(),
+            verify_token: public_key
+                .encrypt(&mut rng, Pkcs1v15Encrypt, &verify_token)
+                .unwrap(),
+        };
+        assert_eq!(
+            key.decrypt_response(&response, &verify_token).unwrap(),
+            [7; 16]
+        );
+
+        response.verify_token = public_key
+            .encrypt(&mut rng, Pkcs1v15Encrypt, &[0; 4])
+            .unwrap();
+        assert!(matches!(
+            key.decrypt_response(&response, &verify_token),
+            Err(EncryptionError::VerifyTokenMismatch)
+        ));
+    }
+
+    #[test]
+    fn cipher_continues_stream() {
+        let secret = *b"0123456789abcdef";
+        let plain = (0..=255).collect::<Vec<u8>>();
+
+        let mut data = plain.clone();
+        let mut encryptor = Cipher::encryptor(&secret);
+        encryptor.apply(&mut data[..100]);
+        encryptor.apply(&mut data[100..]);
+        assert_ne!(data, plain);
+
+        let mut decryptor = Cipher::decryptor(&secret);
+        decryptor.apply(&mut data);
+        assert_eq!(data, plain);
+    }
+}
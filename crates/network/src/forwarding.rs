@@ -0,0 +1,258 @@
+//! Players forwarded by a proxy in front of the server.
+//!
+//! Behind a proxy, all connections come from the proxy, which also
+//! authenticates the players. With forwarding, the proxy passes on the
+//! address, id and properties of each player it let in.
+
+use std::net::{AddrParseError, IpAddr};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use thiserror::Error;
+use uuid::Uuid;
+
+use cellophanemc_profile::{GameProfile, Property};
+use cellophanemc_protocol::packets::server::{LoginPluginRequest, LoginSuccessProperty};
+use cellophanemc_protocol::{Decoder, VarInt, VarIntPrefixedVec};
+
+/// Channel of the login plugin messages Velocity forwards players with.
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// Version of Velocity's forwarding data that is requested. Later versions
+/// only add chat signing keys.
+const VELOCITY_VERSION: u8 = 1;
+
+/// Size of the HMAC-SHA256 signature in front of Velocity's forwarding data.
+const SIGNATURE_LEN: usize = 32;
+
+/// How a proxy in front of the server forwards players.
+#[derive(Debug, Clone, Default)]
+pub enum Forwarding {
+    /// Players connect directly, or through a proxy that doesn't forward
+    /// them.
+    #[default]
+    None,
+    /// BungeeCord's legacy forwarding, which appends the player to the
+    /// server address of the handshake. Anyone can do so, so the server must
+    /// only be reachable through the proxy.
+    BungeeCord,
+    /// Velocity's modern forwarding, which sends the player in a login
+    /// plugin message signed with the secret shared with the proxy.
+    Velocity { secret: Vec<u8> },
+}
+
+#[derive(Debug, Error)]
+pub enum ForwardingError {
+    #[error("handshake has no forwarded player")]
+    MissingPlayer,
+    #[error("invalid forwarded address")]
+    InvalidAddress(#[from] AddrParseError),
+    #[error("invalid forwarded id")]
+    InvalidId(#[from] uuid::Error),
+    #[error("invalid forwarded properties")]
+    InvalidProperties(#[from] serde_json::Error),
+    #[error("invalid forwarding data")]
+    Protocol(#[from] cellophanemc_protocol::error::Error),
+    #[error("forwarding data has an invalid signature")]
+    InvalidSignature,
+    #[error("unsupported forwarding version {0}")]
+    UnsupportedVersion(i32),
+    #[error("no forwarding was requested")]
+    NotRequested,
+    #[error("proxy didn't forward the player")]
+    NotForwarded,
+}
+
+/// What a proxy forwarded about the player behind a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardedPlayer {
+    pub addr: IpAddr,
+    pub id: Uuid,
+    /// Name of the player, which BungeeCord doesn't forward.
+    pub name: Option<String>,
+    pub properties: Vec<Property>,
+}
+
+impl ForwardedPlayer {
+    /// Returns the profile of the player, named `username` unless the proxy
+    /// forwarded a name.
+    pub fn profile(&self, username: &str) -> GameProfile {
+        let name = self.name.as_deref().unwrap_or(username);
+        GameProfile::new(self.id, name.to_string()).with_properties(self.properties.clone())
+    }
+}
+
+#[derive(Deserialize)]
+struct BungeeCordProperty {
+    name: String,
+    value: String,
+    signature: Option<String>,
+}
+
+/// Splits the server address of a handshake forwarded by BungeeCord into the
+/// address the player connected to and the player.
+///
+/// BungeeCord separates the parts with null characters, leaving out the
+/// properties if the player has none.
+pub fn parse_bungeecord(
+    server_address: &str,
+) -> Result<(String, ForwardedPlayer), ForwardingError> {
+    let mut parts = server_address.split('\0');
+    let host = parts.next().unwrap_or_default();
+    let (Some(addr), Some(id)) = (parts.next(), parts.next()) else {
+        return Err(ForwardingError::MissingPlayer);
+    };
+    let properties = match parts.next() {
+        Some(json) => serde_json::from_str::<Vec<BungeeCordProperty>>(json)?
+            .into_iter()
+            .map(|property| Property::new(property.name, property.value, property.signature))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let player = ForwardedPlayer {
+        addr: addr.parse()?,
+        id: Uuid::parse_str(id)?,
+        name: None,
+        properties,
+    };
+    Ok((host.to_string(), player))
+}
+
+/// Creates the login plugin request asking Velocity to forward the player.
+pub fn velocity_request(message_id: i32) -> LoginPluginRequest {
+    LoginPluginRequest {
+        message_id,
+        channel: VELOCITY_CHANNEL.to_string(),
+        data: vec![VELOCITY_VERSION],
+    }
+}
+
+/// Checks the signature of the data Velocity answered the request with and
+/// reads the player from it.
+pub fn parse_velocity(secret: &[u8], data: &[u8]) -> Result<ForwardedPlayer, ForwardingError> {
+    if data.len() < SIGNATURE_LEN {
+        return Err(ForwardingError::InvalidSignature);
+    }
+    let (signature, mut data) = data.split_at(SIGNATURE_LEN);
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.verify_slice(signature)
+        .map_err(|_| ForwardingError::InvalidSignature)?;
+
+    let version = VarInt::read(&mut data)?.0;
+    if version < VELOCITY_VERSION as i32 {
+        return Err(ForwardingError::UnsupportedVersion(version));
+    }
+    let addr = String::read(&mut data)?.parse()?;
+    let id = Uuid::read(&mut data)?;
+    let name = String::read(&mut data)?;
+    // Properties are written like those of the login success packet.
+    let properties: Vec<LoginSuccessProperty> = VarIntPrefixedVec::read(&mut data)?.into();
+
+    Ok(ForwardedPlayer {
+        addr,
+        id,
+        name: Some(name),
+        properties: properties
+            .into_iter()
+            .map(|property| Property::new(property.name, property.value, property.signature))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use uuid::Uuid;
+
+    use cellophanemc_profile::Property;
+    use cellophanemc_protocol::packets::server::LoginSuccessProperty;
+    use cellophanemc_protocol::{Encoder, VarInt, VarIntPrefixedVec};
+
+    use crate::forwarding::{parse_bungeecord, parse_velocity, ForwardingError};
+
+    fn textures() -> Property {
+        Property::new(
+            "textures".to_string(),
+            "e30=".to_string(),
+            Some("c2ln".to_string()),
+        )
+    }
+
+    /// Writes the data Velocity answers a forwarding request with.
+    pub(crate) fn velocity_data(secret: &[u8], addr: IpAddr, id: Uuid, name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        VarInt(1).write(&mut data).unwrap();
+        addr.to_string().write(&mut data).unwrap();
+        id.write(&mut data).unwrap();
+        name.to_string().write(&mut data).unwrap();
+        let properties = vec![LoginSuccessProperty {
+            name: "textures".to_string(),
+            value: "e30=".to_string(),
+            signature: Some("c2ln".to_string()),
+        }];
+        VarIntPrefixedVec::from(properties)
+            .write(&mut data)
+            .unwrap();
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(&data);
+        let mut signed = mac.finalize().into_bytes().to_vec();
+        signed.extend_from_slice(&data);
+        signed
+    }
+
+    #[test]
+    fn parses_bungeecord() {
+        let address = "example.com\u{0}10.0.0.1\u{0}069a79f444e94726a5befca90e38aaf5\u{0}\
+            [{\"name\":\"textures\",\"value\":\"e30=\",\"signature\":\"c2ln\"}]";
+        let (host, player) = parse_bungeecord(address).unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(player.addr, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(
+            player.id,
+            Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+        );
+        assert_eq!(player.properties, vec![textures()]);
+        assert_eq!(player.profile("Notch").name(), "Notch");
+
+        let (_, player) =
+            parse_bungeecord("example.com\u{0}::1\u{0}069a79f444e94726a5befca90e38aaf5").unwrap();
+        assert_eq!(player.addr, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert!(player.properties.is_empty());
+
+        assert!(matches!(
+            parse_bungeecord("example.com"),
+            Err(ForwardingError::MissingPlayer)
+        ));
+    }
+
+    #[test]
+    fn verifies_velocity_signature() {
+        let secret = b"secret";
+        let addr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let data = velocity_data(secret, addr, Uuid::from_u128(7), "Steve");
+
+        let player = parse_velocity(secret, &data).unwrap();
+        assert_eq!(player.addr, addr);
+        assert_eq!(player.id, Uuid::from_u128(7));
+        assert_eq!(player.profile("Alex").name(), "Steve");
+        assert_eq!(player.properties, vec![textures()]);
+
+        assert!(matches!(
+            parse_velocity(b"other", &data),
+            Err(ForwardingError::InvalidSignature)
+        ));
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            parse_velocity(secret, &tampered),
+            Err(ForwardingError::InvalidSignature)
+        ));
+    }
+}
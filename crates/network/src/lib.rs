@@ -1,19 +1,17 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 
 use bevy_app::{App, Plugin, PostStartup, PreUpdate, Update};
 use bevy_ecs::entity::Entity;
 use bevy_ecs::event::EventWriter;
 use bevy_ecs::prelude::{Commands, Component, Event, Query, World};
 use bevy_ecs::system::{Res, Resource, SystemState};
-use bevy_reflect::Reflect;
 use bytes::Bytes;
 use flume::{Receiver, RecvError, Sender, TryRecvError};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::runtime::Runtime;
+use tokio::runtime::{Handle, Runtime};
 use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tracing::{error, info, warn};
@@ -23,9 +21,33 @@ use cellophanemc_protocol::packets::client::{ClientConfigurationPacket, ClientHa
 
 use crate::decode::PacketDecoder;
 use crate::encode::PacketEncoder;
+#[cfg(feature = "encryption")]
+use crate::encryption::{EncryptionError, ServerKey};
+#[cfg(feature = "encryption")]
+use cellophanemc_protocol::packets::client::EncryptionResponse;
+#[cfg(feature = "compression")]
+use cellophanemc_protocol::packets::server::SetCompression;
+#[cfg(any(feature = "compression", feature = "encryption", feature = "forwarding"))]
+use cellophanemc_protocol::packets::server::ServerLoginPacket;
+use crate::settings::NetworkSettings;
+#[cfg(feature = "online-mode")]
+use crate::auth::SessionError;
+#[cfg(feature = "online-mode")]
+use cellophanemc_profile::{offline_uuid, GameProfile};
+#[cfg(feature = "forwarding")]
+use crate::forwarding::{ForwardedPlayer, Forwarding, ForwardingError};
+#[cfg(feature = "forwarding")]
+use cellophanemc_protocol::packets::client::LoginPluginResponse;
 
+#[cfg(feature = "online-mode")]
+pub mod auth;
 pub mod decode;
 pub mod encode;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "forwarding")]
+pub mod forwarding;
+pub mod settings;
 
 pub struct NetworkPlugin;
 
@@ -38,19 +60,58 @@ impl Plugin for NetworkPlugin {
 }
 
 fn build_plugin(app: &mut App) -> anyhow::Result<()> {
-    let runtime = Runtime::new()?;
+    let settings = app
+        .world
+        .get_resource_or_insert_with(NetworkSettings::default)
+        .clone();
 
-    let (new_connections_send, new_connections_recv) = flume::bounded::<RemoteConnection>(64);
+    // A runtime started here lives as long as the systems using it.
+    let (runtime, handle) = match &settings.runtime {
+        Some(handle) => (None, handle.clone()),
+        None => {
+            let runtime = Runtime::new()?;
+            let handle = runtime.handle().clone();
+            (Some(runtime), handle)
+        }
+    };
+
+    let (new_connections_send, new_connections_recv) =
+        flume::bounded::<RemoteConnection>(settings.max_pending_connections);
+    #[cfg(feature = "online-mode")]
+    let (authentications_send, authentications_recv) = flume::unbounded();
     let shared = SharedNetworkState(Arc::new(SharedNetworkStateInner {
+        settings,
+        handle,
         new_connections_send,
         new_connections_recv,
+        #[cfg(feature = "online-mode")]
+        authentications_send,
+        #[cfg(feature = "online-mode")]
+        authentications_recv,
+        connections: Arc::default(),
+        local_addrs: Mutex::default(),
     }));
 
     app.insert_resource(shared.clone());
+    #[cfg(feature = "encryption")]
+    if !app.world.contains_resource::<ServerKey>() {
+        app.insert_resource(ServerKey::generate()?);
+    }
 
     let accept_loop_system = move |shared: Res<SharedNetworkState>| {
-        let _guard = runtime.handle().enter();
-        tokio::spawn(accept_loop(shared.clone()));
+        let _runtime = &runtime;
+        let _guard = shared.0.handle.enter();
+        for addr in &shared.0.settings.addresses {
+            match bind(*addr) {
+                Ok(listener) => {
+                    let local_addr = listener.local_addr().unwrap_or(*addr);
+                    info!("Listening at: {local_addr}");
+                    shared.0.local_addrs.lock().unwrap().push(local_addr);
+                    tokio::spawn(accept_loop(shared.clone(), listener));
+                }
+                Err(e) => error!("Failed to start TCP listener at {addr}: {e}"),
+            }
+        }
     };
 
     let spawn_new_connections = move |world: &mut World| {
@@ -65,34 +126,39 @@ fn build_plugin(app: &mut App) -> anyhow::Result<()> {
     app.add_event::<DisconnectEvent>();
     app.add_systems(Update, run_packet_event_loop);
     app.register_type::<HandshakeState>();
+    #[cfg(feature = "online-mode")]
+    {
+        app.add_event::<AuthenticationEvent>();
+        app.add_systems(PreUpdate, send_authentication_events);
+    }
 
     Ok(())
 }
 
-async fn accept_loop(shared: SharedNetworkState) {
-    let addr = "0.0.0.0:25565";
-    let listener = match TcpListener::bind(addr).await {
-        Ok(listener) => {
-            info!("Listening at: {addr}");
-            listener
-        }
-        Err(e) => {
-            error!("Failed to start TCP listener: {e}");
-            return;
-        }
-    };
+/// Binds a listener right away, so errors and the picked port are known once
+/// the server has started.
+fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
 
-    let timeout = Duration::from_secs(5);
+async fn accept_loop(shared: SharedNetworkState, listener: TcpListener) {
+    let timeout = shared.0.settings.connection_timeout;
 
     loop {
         match listener.accept().await {
             Ok((stream, remote_addr)) => {
+                let Some(permit) = shared.acquire(remote_addr.ip()) else {
+                    warn!("Too many connections, closing connection from {remote_addr}");
+                    continue;
+                };
                 let shared = shared.clone();
 
                 tokio::spawn(async move {
                     if let Err(e) = tokio::time::timeout(
                         timeout,
-                        handle_connection(shared, stream, remote_addr),
+                        handle_connection(shared, stream, remote_addr, permit),
                     )
                         .await
                     {
@@ -115,7 +181,12 @@ pub struct PacketFrame {
     pub payload: Bytes,
 }
 
-async fn handle_connection(shared: SharedNetworkState, stream: TcpStream, remote_addr: SocketAddr) {
+async fn handle_connection(
+    shared: SharedNetworkState,
+    stream: TcpStream,
+    remote_addr: SocketAddr,
+    permit: ConnectionPermit,
+) {
     println!("handle new connection: {}", remote_addr);
     if let Err(e) = stream.set_nodelay(true) {
         error!("Failed to set TCP_NODELAY: {e}");
@@ -123,16 +194,26 @@ async fn handle_connection(shared: SharedNetworkState, stream: TcpStream, remote
     let (mut reader, mut writer) = stream.into_split();
 
     let (incoming_sender, incoming_receiver) = flume::unbounded::<PacketFrame>();
+    let (decoder_sender, decoder_receiver) = flume::unbounded::<CodecChange>();
     let recv_task = tokio::spawn(async move {
         let mut decoder = PacketDecoder::new();
 
         loop {
-            let payload = match decoder.read_frame(&mut reader).await {
-                Ok(payload) => payload,
-                Err(e) => {
-                    error!("error decoding packet frame: {e:#}");
-                    break;
+            // Changes are applied before reading on, as the client only sends
+            // frames using them after the server did.
+            let payload = tokio::select! {
+                biased;
+                Ok(change) = decoder_receiver.recv_async() => {
+                    decoder.apply(change);
+                    continue;
                 }
+                payload = decoder.read_frame(&mut reader) => match payload {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        error!("error decoding packet frame: {e:#}");
+                        break;
+                    }
+                },
             };
 
             let timestamp = Instant::now();
@@ -147,13 +228,17 @@ async fn handle_connection(shared: SharedNetworkState, stream: TcpStream, remote
         }
     });
 
-    let (outgoing_sender, outgoing_receiver) = flume::unbounded::<Bytes>();
+    let (outgoing_sender, outgoing_receiver) = flume::unbounded::<Outgoing>();
     let send_task = tokio::spawn(async move {
         let mut encoder = PacketEncoder::new();
 
         loop {
             let bytes = match outgoing_receiver.recv_async().await {
-                Ok(frame) => frame,
+                Ok(Outgoing::Frame(bytes)) => bytes,
+                Ok(Outgoing::Change(change)) => {
+                    encoder.apply(change);
+                    continue;
+                }
                 Err(e) => match e {
                     RecvError::Disconnected => break
                 },
@@ -169,14 +254,30 @@ async fn handle_connection(shared: SharedNetworkState, stream: TcpStream, remote
         remote_addr,
         recv: incoming_receiver,
         send: outgoing_sender,
+        decoder: decoder_sender,
         recv_task,
         send_task,
         state: HandshakeState::Handshaking,
+        #[cfg(feature = "encryption")]
+        verify_token: None,
+        #[cfg(feature = "forwarding")]
+        forwarded: None,
+        #[cfg(feature = "forwarding")]
+        forwarding_message_id: None,
+        _permit: permit,
     };
 
     let _ = shared.0.new_connections_send.send_async(connection).await;
 }
 
+#[cfg(feature = "online-mode")]
+fn send_authentication_events(
+    shared: Res<SharedNetworkState>,
+    mut events: EventWriter<AuthenticationEvent>,
+) {
+    events.send_batch(shared.0.authentications_recv.try_iter());
+}
+
 #[allow(clippy::type_complexity)]
 fn run_packet_event_loop(
     world: &mut World,
@@ -187,6 +288,8 @@ fn run_packet_event_loop(
         Commands,
     )>,
 ) {
+    #[cfg(feature = "forwarding")]
+    let shared = world.resource::<SharedNetworkState>().clone();
     let (mut connections, mut packet_events, mut disconnect_events, mut commands) =
         state.get_mut(world);
 
@@ -204,6 +307,20 @@ fn run_packet_event_loop(
                     HandshakeState::Play => ClientPlayPacket::read(cursor).map(|p| ClientPacket::Play(p))
                 };
 
+                #[cfg(feature = "forwarding")]
+                let packet = match packet {
+                    Ok(packet) => match forward_bungeecord(&shared, &mut connection, packet) {
+                        Ok(packet) => Ok(packet),
+                        Err(e) => {
+                            warn!("invalid forwarding from {}: {e:#}", connection.remote_addr);
+                            disconnect_events.send(DisconnectEvent { connection: entity });
+                            commands.entity(entity).despawn();
+                            continue;
+                        }
+                    },
+                    Err(e) => Err(e),
+                };
+
                 match packet {
                     Ok(packet) => packet_events.send(ClientPacketReceivedEvent {
                         connection: entity,
@@ -226,22 +343,179 @@ fn run_packet_event_loop(
     state.apply(world);
 }
 
+/// Takes the player BungeeCord forwarded out of the handshake of a login, so
+/// the handshake only has the address the player connected to.
+#[cfg(feature = "forwarding")]
+fn forward_bungeecord(
+    shared: &SharedNetworkState,
+    connection: &mut RemoteConnection,
+    mut packet: ClientPacket,
+) -> Result<ClientPacket, ForwardingError> {
+    if let (
+        Forwarding::BungeeCord,
+        ClientPacket::Handshake(ClientHandshakePacket::Handshake(handshake)),
+    ) = (shared.forwarding(), &mut packet)
+    {
+        if handshake.next_state == HandshakeState::Login {
+            let (host, player) = forwarding::parse_bungeecord(&handshake.server_address)?;
+            handshake.server_address = host;
+            connection.forward(player);
+        }
+    }
+    Ok(packet)
+}
+
 #[derive(Resource, Clone)]
 pub struct SharedNetworkState(Arc<SharedNetworkStateInner>);
 
 struct SharedNetworkStateInner {
+    settings: NetworkSettings,
+    handle: Handle,
     new_connections_send: Sender<RemoteConnection>,
     new_connections_recv: Receiver<RemoteConnection>,
+    #[cfg(feature = "online-mode")]
+    authentications_send: Sender<AuthenticationEvent>,
+    #[cfg(feature = "online-mode")]
+    authentications_recv: Receiver<AuthenticationEvent>,
+    connections: Arc<Mutex<ConnectionCounts>>,
+    local_addrs: Mutex<Vec<SocketAddr>>,
+}
+
+impl SharedNetworkState {
+    /// Returns the addresses the server listens on, once it has started.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.0.local_addrs.lock().unwrap().clone()
+    }
+
+    /// Returns the number of open connections.
+    pub fn connection_count(&self) -> usize {
+        self.0.connections.lock().unwrap().total
+    }
+
+    /// Returns how a proxy in front of the server forwards players.
+    #[cfg(feature = "forwarding")]
+    pub fn forwarding(&self) -> &Forwarding {
+        &self.0.settings.forwarding
+    }
+
+    /// Returns whether players are authenticated with a session service.
+    #[cfg(feature = "online-mode")]
+    pub fn online_mode(&self) -> bool {
+        self.0.settings.session_service.is_some()
+    }
+
+    /// Asks the session service whether `username` joined with the
+    /// [`server_hash`](auth::server_hash) of its encryption handshake. The
+    /// answer is sent as an [`AuthenticationEvent`] for `connection`.
+    ///
+    /// Without a session service, the player gets an offline profile right
+    /// away.
+    #[cfg(feature = "online-mode")]
+    pub fn authenticate(
+        &self,
+        connection: Entity,
+        username: &str,
+        server_hash: String,
+        ip: Option<IpAddr>,
+    ) {
+        let send = self.0.authentications_send.clone();
+        let Some(service) = self.0.settings.session_service.clone() else {
+            let profile = GameProfile::new(offline_uuid(username), username.to_string());
+            let _ = send.send(AuthenticationEvent {
+                connection,
+                result: Ok(Some(profile)),
+            });
+            return;
+        };
+
+        let username = username.to_string();
+        self.0.handle.spawn_blocking(move || {
+            let result = service.has_joined(&username, &server_hash, ip);
+            let _ = send.send(AuthenticationEvent { connection, result });
+        });
+    }
+
+    /// Counts a new connection from `ip`, unless it would go over the limits
+    /// of the [`NetworkSettings`].
+    fn acquire(&self, ip: IpAddr) -> Option<ConnectionPermit> {
+        let settings = &self.0.settings;
+        let mut counts = self.0.connections.lock().unwrap();
+        let per_ip = counts.per_ip.get(&ip).copied().unwrap_or(0);
+        if counts.total >= settings.max_connections
+            || settings.max_connections_per_ip.is_some_and(|max| per_ip >= max)
+        {
+            return None;
+        }
+        counts.total += 1;
+        counts.per_ip.insert(ip, per_ip + 1);
+        Some(ConnectionPermit {
+            counts: self.0.connections.clone(),
+            ip,
+        })
+    }
+}
+
+#[derive(Default)]
+struct ConnectionCounts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Keeps a connection counted in [`ConnectionCounts`] until dropped.
+struct ConnectionPermit {
+    counts: Arc<Mutex<ConnectionCounts>>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(count) = counts.per_ip.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// A change to how frames of a connection are written and read.
+#[derive(Clone, Debug)]
+pub(crate) enum CodecChange {
+    #[cfg(feature = "compression")]
+    Compression(i32),
+    #[cfg(feature = "encryption")]
+    Encryption([u8; 16]),
+}
+
+/// Sent to the task writing to a connection, in order.
+enum Outgoing {
+    Frame(Bytes),
+    #[cfg_attr(not(any(feature = "compression", feature = "encryption")), allow(dead_code))]
+    Change(CodecChange),
 }
 
 #[derive(Component)]
 pub struct RemoteConnection {
     pub remote_addr: SocketAddr,
     recv: Receiver<PacketFrame>,
-    send: Sender<Bytes>,
+    send: Sender<Outgoing>,
+    #[cfg_attr(not(any(feature = "compression", feature = "encryption")), allow(dead_code))]
+    decoder: Sender<CodecChange>,
     recv_task: JoinHandle<()>,
     send_task: JoinHandle<()>,
     pub state: HandshakeState,
+    /// Token of the encryption request sent to the client, if any.
+    #[cfg(feature = "encryption")]
+    verify_token: Option<[u8; 4]>,
+    /// Player a proxy forwarded, if any.
+    #[cfg(feature = "forwarding")]
+    forwarded: Option<ForwardedPlayer>,
+    /// Message id of the forwarding request sent to Velocity, if any.
+    #[cfg(feature = "forwarding")]
+    forwarding_message_id: Option<i32>,
+    _permit: ConnectionPermit,
 }
 
 impl RemoteConnection {
@@ -250,7 +524,7 @@ impl RemoteConnection {
     }
 
     pub fn send_raw_packet(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
-        self.send.try_send(Bytes::from(bytes.to_vec()))?;
+        self.send.try_send(Outgoing::Frame(Bytes::from(bytes.to_vec())))?;
         Ok(())
     }
 
@@ -262,6 +536,110 @@ impl RemoteConnection {
         packet.write(&mut buf)?;
         self.send_raw_packet(&buf)
     }
+
+    /// Changes how frames are read before the next one is.
+    #[cfg(any(feature = "compression", feature = "encryption"))]
+    fn change_decoder(&mut self, change: CodecChange) -> anyhow::Result<()> {
+        self.decoder.try_send(change)?;
+        Ok(())
+    }
+
+    /// Changes how frames are written. Packets sent before are written the
+    /// old way, those sent after the new way.
+    #[cfg(any(feature = "compression", feature = "encryption"))]
+    fn change_encoder(&mut self, change: CodecChange) -> anyhow::Result<()> {
+        self.send.try_send(Outgoing::Change(change))?;
+        Ok(())
+    }
+
+    /// Tells the client to compress packets of at least `threshold` bytes,
+    /// and does so for both directions from now on. A negative threshold
+    /// turns compression off.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&mut self, threshold: i32) -> anyhow::Result<()> {
+        // The client compresses everything after getting the packet, so the
+        // decoder has to be switched before it is sent.
+        self.change_decoder(CodecChange::Compression(threshold))?;
+        self.send_packet(ServerLoginPacket::SetCompression(SetCompression { threshold }))?;
+        self.change_encoder(CodecChange::Compression(threshold))
+    }
+
+    /// Sends an encryption request, which the client answers with an
+    /// [`EncryptionResponse`] to pass to [`finish_encryption`].
+    ///
+    /// [`finish_encryption`]: RemoteConnection::finish_encryption
+    #[cfg(feature = "encryption")]
+    pub fn request_encryption(&mut self, key: &ServerKey) -> anyhow::Result<()> {
+        let (request, verify_token) = key.request();
+        self.send_packet(ServerLoginPacket::EncryptionRequest(request))?;
+        self.verify_token = Some(verify_token);
+        Ok(())
+    }
+
+    /// Checks the response to [`request_encryption`] and encrypts the
+    /// connection from now on. Returns the shared secret, which the session
+    /// server needs to authenticate the client.
+    ///
+    /// [`request_encryption`]: RemoteConnection::request_encryption
+    #[cfg(feature = "encryption")]
+    pub fn finish_encryption(
+        &mut self,
+        key: &ServerKey,
+        response: &EncryptionResponse,
+    ) -> anyhow::Result<[u8; 16]> {
+        let verify_token = self.verify_token.take().ok_or(EncryptionError::NotRequested)?;
+        let shared_secret = key.decrypt_response(response, &verify_token)?;
+        self.change_decoder(CodecChange::Encryption(shared_secret))?;
+        self.change_encoder(CodecChange::Encryption(shared_secret))?;
+        Ok(shared_secret)
+    }
+
+    /// Returns the player a proxy forwarded, if any.
+    #[cfg(feature = "forwarding")]
+    pub fn forwarded(&self) -> Option<&ForwardedPlayer> {
+        self.forwarded.as_ref()
+    }
+
+    /// Sends a login plugin request asking Velocity to forward the player,
+    /// which it answers with a [`LoginPluginResponse`] to pass to
+    /// [`finish_forwarding`].
+    ///
+    /// [`finish_forwarding`]: RemoteConnection::finish_forwarding
+    #[cfg(feature = "forwarding")]
+    pub fn request_forwarding(&mut self) -> anyhow::Result<()> {
+        let message_id = self.forwarding_message_id.unwrap_or_default();
+        let request = forwarding::velocity_request(message_id);
+        self.send_packet(ServerLoginPacket::LoginPluginRequest(request))?;
+        self.forwarding_message_id = Some(message_id);
+        Ok(())
+    }
+
+    /// Checks the response to [`request_forwarding`] against the secret
+    /// shared with Velocity and takes the address of the player from now on.
+    ///
+    /// [`request_forwarding`]: RemoteConnection::request_forwarding
+    #[cfg(feature = "forwarding")]
+    pub fn finish_forwarding(
+        &mut self,
+        secret: &[u8],
+        response: &LoginPluginResponse,
+    ) -> anyhow::Result<&ForwardedPlayer> {
+        if self.forwarding_message_id != Some(response.message_id) {
+            return Err(ForwardingError::NotRequested.into());
+        }
+        self.forwarding_message_id = None;
+        if !response.successful {
+            return Err(ForwardingError::NotForwarded.into());
+        }
+        let player = forwarding::parse_velocity(secret, &response.data)?;
+        Ok(self.forward(player))
+    }
+
+    #[cfg(feature = "forwarding")]
+    fn forward(&mut self, player: ForwardedPlayer) -> &ForwardedPlayer {
+        self.remote_addr = SocketAddr::new(player.addr, self.remote_addr.port());
+        self.forwarded.insert(player)
+    }
 }
 
 impl Drop for RemoteConnection {
@@ -318,3 +696,693 @@ impl HandshakeEvent {
 pub struct ClientStatusRequestEvent {
     pub connection: Entity,
 }
+
+/// The answer to [`SharedNetworkState::authenticate`].
+#[cfg(feature = "online-mode")]
+#[derive(Event, Debug)]
+pub struct AuthenticationEvent {
+    pub connection: Entity,
+    /// The profile of the player, or `None` if the client didn't join with
+    /// the session service.
+    pub result: Result<Option<GameProfile>, SessionError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+    use std::time::Duration;
+
+    use bevy_app::App;
+    use tokio::runtime::Runtime;
+
+    use crate::settings::NetworkSettings;
+    use crate::{NetworkPlugin, RemoteConnection, SharedNetworkState};
+
+    fn server(runtime: &Runtime, max_connections_per_ip: Option<usize>) -> App {
+        let mut app = App::new();
+        app.insert_resource(NetworkSettings {
+            addresses: vec![SocketAddr::from((Ipv4Addr::LOCALHOST, 0))],
+            max_connections_per_ip,
+            runtime: Some(runtime.handle().clone()),
+            ..Default::default()
+        });
+        app.add_plugins(NetworkPlugin);
+        app.update();
+        app
+    }
+
+    fn local_addr(app: &App) -> SocketAddr {
+        app.world.resource::<SharedNetworkState>().local_addrs()[0]
+    }
+
+    fn wait_for_connections(app: &mut App, count: usize) {
+        for _ in 0..500 {
+            app.update();
+            if app.world.query::<&RemoteConnection>().iter(&app.world).count() == count {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("expected {count} connections");
+    }
+
+    #[test]
+    fn limits_connections_per_ip() {
+        let runtime = Runtime::new().unwrap();
+        let mut limited = server(&runtime, Some(1));
+        let mut unlimited = server(&runtime, None);
+        assert_ne!(local_addr(&limited), local_addr(&unlimited));
+
+        let _accepted = TcpStream::connect(local_addr(&limited)).unwrap();
+        wait_for_connections(&mut limited, 1);
+        let mut rejected = TcpStream::connect(local_addr(&limited)).unwrap();
+        rejected.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(rejected.read(&mut [0; 1]).unwrap(), 0);
+        assert_eq!(limited.world.resource::<SharedNetworkState>().connection_count(), 1);
+
+        let _first = TcpStream::connect(local_addr(&unlimited)).unwrap();
+        let _second = TcpStream::connect(local_addr(&unlimited)).unwrap();
+        wait_for_connections(&mut unlimited, 2);
+    }
+
+    #[cfg(feature = "compression")]
+    mod compression {
+        use std::net::SocketAddr;
+
+        use bevy_ecs::event::{Events, ManualEventReader};
+        use tokio::net::TcpStream;
+        use tokio::runtime::Runtime;
+
+        use cellophanemc_protocol::packets::client::{
+            ClientHandshakePacket, Handshake, HandshakeState,
+        };
+        use cellophanemc_protocol::packets::server::ServerLoginPacket;
+        use cellophanemc_protocol::{Decoder, Encoder};
+
+        use crate::decode::PacketDecoder;
+        use crate::encode::PacketEncoder;
+        use crate::{ClientPacket, ClientPacketReceivedEvent, RemoteConnection};
+
+        use super::{local_addr, server, wait_for_connections};
+
+        async fn client(addr: SocketAddr, server_address: String) -> anyhow::Result<()> {
+            let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+            let mut decoder = PacketDecoder::new();
+            let mut encoder = PacketEncoder::new();
+
+            let frame = decoder.read_frame(&mut reader).await?;
+            let ServerLoginPacket::SetCompression(packet) =
+                ServerLoginPacket::read(&mut &frame[..])?
+            else {
+                anyhow::bail!("expected set compression");
+            };
+            encoder.set_compression(packet.threshold);
+            decoder.set_compression(packet.threshold);
+
+            let handshake = Handshake {
+                protocol_version: 765,
+                server_address,
+                server_port: addr.port(),
+                next_state: HandshakeState::Login,
+            };
+            let mut buf = Vec::new();
+            ClientHandshakePacket::Handshake(handshake).write(&mut buf)?;
+            encoder.write_frame(&mut writer, &buf).await?;
+            // Keeps the connection open until the server got the packet.
+            decoder.read_frame(&mut reader).await.ok();
+            Ok(())
+        }
+
+        #[test]
+        fn compressed_handshake() {
+            let runtime = Runtime::new().unwrap();
+            let mut app = server(&runtime, None);
+            let server_address = "a".repeat(300);
+
+            let client = runtime.spawn(client(local_addr(&app), server_address.clone()));
+            wait_for_connections(&mut app, 1);
+            let mut connections = app.world.query::<&mut RemoteConnection>();
+            connections
+                .single_mut(&mut app.world)
+                .set_compression(256)
+                .unwrap();
+
+            let mut reader = ManualEventReader::<ClientPacketReceivedEvent>::default();
+            let mut received = None;
+            for _ in 0..500 {
+                app.update();
+                let events = app.world.resource::<Events<ClientPacketReceivedEvent>>();
+                if let Some(event) = reader.read(events).next() {
+                    received = Some(event.packet.clone());
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+
+            let Some(ClientPacket::Handshake(ClientHandshakePacket::Handshake(handshake))) =
+                received
+            else {
+                panic!("expected a handshake, got {received:?}");
+            };
+            assert_eq!(handshake.server_address, server_address);
+            drop(app);
+            runtime.block_on(client).unwrap().unwrap();
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    mod encryption {
+        use std::net::SocketAddr;
+
+        use bevy_app::Update;
+        use bevy_ecs::prelude::{EventReader, IntoSystemConfigs, Query, Res};
+        use rsa::pkcs8::DecodePublicKey;
+        use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
+        use tokio::net::tcp::OwnedWriteHalf;
+        use tokio::net::TcpStream;
+        use tokio::runtime::Runtime;
+        use uuid::Uuid;
+
+        use cellophanemc_protocol::packets::client::{
+            ClientHandshakePacket, ClientLoginPacket, EncryptionResponse, Handshake,
+            HandshakeState, LoginAck, LoginStart,
+        };
+        use cellophanemc_protocol::packets::server::{
+            EncryptionRequest, LoginSuccess, ServerLoginPacket,
+        };
+        use cellophanemc_protocol::{Decoder, Encoder};
+
+        use crate::decode::PacketDecoder;
+        use crate::encode::PacketEncoder;
+        use crate::encryption::ServerKey;
+        use crate::{
+            run_packet_event_loop, ClientPacket, ClientPacketReceivedEvent, RemoteConnection,
+        };
+
+        use super::{local_addr, server};
+
+        fn login(
+            key: Res<ServerKey>,
+            mut events: EventReader<ClientPacketReceivedEvent>,
+            mut connections: Query<&mut RemoteConnection>,
+        ) {
+            for event in events.read() {
+                let mut connection = connections.get_mut(event.connection).unwrap();
+                match &event.packet {
+                    ClientPacket::Handshake(ClientHandshakePacket::Handshake(p)) => {
+                        connection.state = p.next_state;
+                    }
+                    ClientPacket::Login(ClientLoginPacket::LoginStart(_)) => {
+                        connection.request_encryption(&key).unwrap();
+                    }
+                    ClientPacket::Login(ClientLoginPacket::EncryptionResponse(p)) => {
+                        connection.finish_encryption(&key, p).unwrap();
+                        let success = LoginSuccess {
+                            uuid: Uuid::nil(),
+                            username: "Steve".to_string(),
+                            properties: vec![],
+                        };
+                        connection
+                            .send_packet(ServerLoginPacket::LoginSuccess(success))
+                            .unwrap();
+                    }
+                    ClientPacket::Login(ClientLoginPacket::LoginAck(_)) => {
+                        connection.state = HandshakeState::Configuration;
+                    }
+                    packet => panic!("unexpected packet {packet:?}"),
+                }
+            }
+        }
+
+        async fn send(
+            encoder: &mut PacketEncoder,
+            writer: &mut OwnedWriteHalf,
+            packet: impl Encoder,
+        ) -> anyhow::Result<()> {
+            let mut buf = Vec::new();
+            packet.write(&mut buf)?;
+            encoder.write_frame(writer, &buf).await
+        }
+
+        /// Logs in with encryption, calling `join` before answering the
+        /// encryption request.
+        pub(super) async fn client(
+            addr: SocketAddr,
+            join: impl FnOnce(&EncryptionRequest, &[u8; 16]) + Send,
+        ) -> anyhow::Result<LoginSuccess> {
+            let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+            let mut decoder = PacketDecoder::new();
+            let mut encoder = PacketEncoder::new();
+
+            let handshake = Handshake {
+                protocol_version: 765,
+                server_address: "localhost".to_string(),
+                server_port: addr.port(),
+                next_state: HandshakeState::Login,
+            };
+            send(
+                &mut encoder,
+                &mut writer,
+                ClientHandshakePacket::Handshake(handshake),
+            )
+            .await?;
+            let login_start = LoginStart {
+                username: "Steve".to_string(),
+                uuid: Uuid::nil(),
+            };
+            send(
+                &mut encoder,
+                &mut writer,
+                ClientLoginPacket::LoginStart(login_start),
+            )
+            .await?;
+
+            let frame = decoder.read_frame(&mut reader).await?;
+            let ServerLoginPacket::EncryptionRequest(request) =
+                ServerLoginPacket::read(&mut &frame[..])?
+            else {
+                anyhow::bail!("expected an encryption request");
+            };
+            let shared_secret = *b"0123456789abcdef";
+            join(&request, &shared_secret);
+            let response = {
+                let public_key = RsaPublicKey::from_public_key_der(&request.public_key)?;
+                let mut rng = rand::thread_rng();
+                EncryptionResponse {
+                    shared_secret: public_key.encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret)?,
+                    verify_token: public_key.encrypt(
+                        &mut rng,
+                        Pkcs1v15Encrypt,
+                        &request.verify_token,
+                    )?,
+                }
+            };
+            send(
+                &mut encoder,
+                &mut writer,
+                ClientLoginPacket::EncryptionResponse(response),
+            )
+            .await?;
+            encoder.enable_encryption(&shared_secret);
+            decoder.enable_encryption(&shared_secret);
+
+            let frame = decoder.read_frame(&mut reader).await?;
+            let ServerLoginPacket::LoginSuccess(success) =
+                ServerLoginPacket::read(&mut &frame[..])?
+            else {
+                anyhow::bail!("expected login success");
+            };
+            send(
+                &mut encoder,
+                &mut writer,
+                ClientLoginPacket::LoginAck(LoginAck {}),
+            )
+            .await?;
+            Ok(success)
+        }
+
+        #[test]
+        fn encrypted_login() {
+            let runtime = Runtime::new().unwrap();
+            let mut app = server(&runtime, None);
+            app.add_systems(Update, login.after(run_packet_event_loop));
+
+            let client = runtime.spawn(client(local_addr(&app), |_, _| {}));
+            let mut state = HandshakeState::Handshaking;
+            for _ in 0..500 {
+                app.update();
+                let mut connections = app.world.query::<&RemoteConnection>();
+                if let Ok(connection) = connections.get_single(&app.world) {
+                    state = connection.state;
+                }
+                if state == HandshakeState::Configuration {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            assert_eq!(state, HandshakeState::Configuration);
+
+            let success = runtime.block_on(client).unwrap().unwrap();
+            assert_eq!(success.username, "Steve");
+        }
+    }
+
+    #[cfg(feature = "online-mode")]
+    mod online_mode {
+        use std::collections::HashMap;
+        use std::net::{Ipv4Addr, SocketAddr};
+        use std::sync::Arc;
+
+        use bevy_app::{App, Update};
+        use bevy_ecs::prelude::{Entity, EventReader, IntoSystemConfigs, Local, Query, Res};
+        use tokio::runtime::Runtime;
+        use uuid::Uuid;
+
+        use cellophanemc_profile::{GameProfile, Property};
+        use cellophanemc_protocol::packets::client::{
+            ClientHandshakePacket, ClientLoginPacket, HandshakeState,
+        };
+        use cellophanemc_protocol::packets::server::{
+            LoginSuccess, LoginSuccessProperty, ServerLoginPacket,
+        };
+
+        use crate::auth::{server_hash, LocalSessionService};
+        use crate::encryption::ServerKey;
+        use crate::settings::NetworkSettings;
+        use crate::{
+            run_packet_event_loop, AuthenticationEvent, ClientPacket, ClientPacketReceivedEvent,
+            NetworkPlugin, RemoteConnection, SharedNetworkState,
+        };
+
+        use super::encryption::client;
+        use super::local_addr;
+
+        fn login(
+            shared: Res<SharedNetworkState>,
+            key: Res<ServerKey>,
+            mut packets: EventReader<ClientPacketReceivedEvent>,
+            mut authentications: EventReader<AuthenticationEvent>,
+            mut connections: Query<&mut RemoteConnection>,
+            mut usernames: Local<HashMap<Entity, String>>,
+        ) {
+            for event in packets.read() {
+                let mut connection = connections.get_mut(event.connection).unwrap();
+                match &event.packet {
+                    ClientPacket::Handshake(ClientHandshakePacket::Handshake(p)) => {
+                        connection.state = p.next_state;
+                    }
+                    ClientPacket::Login(ClientLoginPacket::LoginStart(p)) => {
+                        usernames.insert(event.connection, p.username.clone());
+                        connection.request_encryption(&key).unwrap();
+                    }
+                    ClientPacket::Login(ClientLoginPacket::EncryptionResponse(p)) => {
+                        let shared_secret = connection.finish_encryption(&key, p).unwrap();
+                        let hash = server_hash("", &shared_secret, key.public_key_der());
+                        let username = &usernames[&event.connection];
+                        shared.authenticate(event.connection, username, hash, None);
+                    }
+                    ClientPacket::Login(ClientLoginPacket::LoginAck(_)) => {
+                        connection.state = HandshakeState::Configuration;
+                    }
+                    packet => panic!("unexpected packet {packet:?}"),
+                }
+            }
+
+            for event in authentications.read() {
+                let profile = event.result.as_ref().unwrap().clone().unwrap();
+                let success = LoginSuccess {
+                    uuid: profile.id(),
+                    username: profile.name().to_string(),
+                    properties: profile
+                        .properties()
+                        .iter()
+                        .map(|property| LoginSuccessProperty {
+                            name: property.name().to_string(),
+                            value: property.value().to_string(),
+                            signature: property.signature().map(str::to_string),
+                        })
+                        .collect(),
+                };
+                let mut connection = connections.get_mut(event.connection).unwrap();
+                connection
+                    .send_packet(ServerLoginPacket::LoginSuccess(success))
+                    .unwrap();
+            }
+        }
+
+        #[test]
+        fn authenticated_login() {
+            let runtime = Runtime::new().unwrap();
+            let sessions = Arc::new(LocalSessionService::new());
+            let mut app = App::new();
+            app.insert_resource(NetworkSettings {
+                addresses: vec![SocketAddr::from((Ipv4Addr::LOCALHOST, 0))],
+                runtime: Some(runtime.handle().clone()),
+                session_service: Some(sessions.clone()),
+                ..Default::default()
+            });
+            app.add_plugins(NetworkPlugin);
+            app.add_systems(Update, login.after(run_packet_event_loop));
+            app.update();
+
+            let textures = Property::new(
+                "textures".to_string(),
+                "e30=".to_string(),
+                Some("c2ln".to_string()),
+            );
+            let profile = GameProfile::new(Uuid::from_u128(7), "Steve".to_string())
+                .with_properties(vec![textures]);
+            let client = runtime.spawn(client(local_addr(&app), move |request, shared_secret| {
+                let hash = server_hash(&request.server_id, shared_secret, &request.public_key);
+                sessions.join(profile, hash);
+            }));
+            while !client.is_finished() {
+                app.update();
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+
+            let success = runtime.block_on(client).unwrap().unwrap();
+            assert_eq!(success.uuid, Uuid::from_u128(7));
+            assert_eq!(success.properties.len(), 1);
+            assert_eq!(success.properties[0].name, "textures");
+            assert_eq!(success.properties[0].signature.as_deref(), Some("c2ln"));
+        }
+    }
+
+    #[cfg(feature = "forwarding")]
+    mod forwarding {
+        use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+        use bevy_app::{App, Update};
+        use bevy_ecs::prelude::{
+            Commands, EventReader, IntoSystemConfigs, Query, Res, ResMut, Resource,
+        };
+        use tokio::net::tcp::OwnedWriteHalf;
+        use tokio::net::TcpStream;
+        use tokio::runtime::Runtime;
+        use uuid::Uuid;
+
+        use cellophanemc_protocol::packets::client::{
+            ClientHandshakePacket, ClientLoginPacket, Handshake, HandshakeState,
+            LoginPluginResponse, LoginStart,
+        };
+        use cellophanemc_protocol::packets::server::{
+            LoginSuccess, LoginSuccessProperty, ServerLoginPacket,
+        };
+        use cellophanemc_protocol::{Decoder, Encoder};
+
+        use crate::decode::PacketDecoder;
+        use crate::encode::PacketEncoder;
+        use crate::forwarding::tests::velocity_data;
+        use crate::forwarding::Forwarding;
+        use crate::settings::NetworkSettings;
+        use crate::{
+            run_packet_event_loop, ClientPacket, ClientPacketReceivedEvent, NetworkPlugin,
+            RemoteConnection, SharedNetworkState,
+        };
+
+        use super::local_addr;
+
+        /// Server addresses of handshakes and addresses of players that
+        /// logged in.
+        #[derive(Resource, Default)]
+        struct Logins {
+            hosts: Vec<String>,
+            addrs: Vec<IpAddr>,
+        }
+
+        fn server(runtime: &Runtime, forwarding: Forwarding) -> App {
+            let mut app = App::new();
+            app.insert_resource(NetworkSettings {
+                addresses: vec![SocketAddr::from((Ipv4Addr::LOCALHOST, 0))],
+                runtime: Some(runtime.handle().clone()),
+                forwarding,
+                ..Default::default()
+            });
+            app.init_resource::<Logins>();
+            app.add_plugins(NetworkPlugin);
+            app.add_systems(Update, login.after(run_packet_event_loop));
+            app.update();
+            app
+        }
+
+        fn login(
+            shared: Res<SharedNetworkState>,
+            mut logins: ResMut<Logins>,
+            mut events: EventReader<ClientPacketReceivedEvent>,
+            mut connections: Query<&mut RemoteConnection>,
+            mut commands: Commands,
+        ) {
+            for event in events.read() {
+                let mut connection = connections.get_mut(event.connection).unwrap();
+                let profile = match (&event.packet, shared.forwarding()) {
+                    (ClientPacket::Handshake(ClientHandshakePacket::Handshake(p)), _) => {
+                        logins.hosts.push(p.server_address.clone());
+                        connection.state = p.next_state;
+                        continue;
+                    }
+                    (
+                        ClientPacket::Login(ClientLoginPacket::LoginStart(_)),
+                        Forwarding::Velocity { .. },
+                    ) => {
+                        connection.request_forwarding().unwrap();
+                        continue;
+                    }
+                    (ClientPacket::Login(ClientLoginPacket::LoginStart(p)), _) => {
+                        connection.forwarded().unwrap().profile(&p.username)
+                    }
+                    (
+                        ClientPacket::Login(ClientLoginPacket::LoginPluginResponse(p)),
+                        Forwarding::Velocity { secret },
+                    ) => match connection.finish_forwarding(secret, p) {
+                        Ok(player) => player.profile("Steve"),
+                        Err(_) => {
+                            commands.entity(event.connection).despawn();
+                            continue;
+                        }
+                    },
+                    (packet, _) => panic!("unexpected packet {packet:?}"),
+                };
+
+                logins.addrs.push(connection.remote_addr.ip());
+                let success = LoginSuccess {
+                    uuid: profile.id(),
+                    username: profile.name().to_string(),
+                    properties: profile
+                        .properties()
+                        .iter()
+                        .map(|property| LoginSuccessProperty {
+                            name: property.name().to_string(),
+                            value: property.value().to_string(),
+                            signature: property.signature().map(str::to_string),
+                        })
+                        .collect(),
+                };
+                connection
+                    .send_packet(ServerLoginPacket::LoginSuccess(success))
+                    .unwrap();
+            }
+        }
+
+        async fn send(
+            encoder: &mut PacketEncoder,
+            writer: &mut OwnedWriteHalf,
+            packet: impl Encoder,
+        ) -> anyhow::Result<()> {
+            let mut buf = Vec::new();
+            packet.write(&mut buf)?;
+            encoder.write_frame(writer, &buf).await
+        }
+
+        /// Logs in like a proxy, answering a forwarding request with
+        /// `velocity_data` if the server sends one.
+        async fn proxy(
+            addr: SocketAddr,
+            server_address: String,
+            velocity_data: Vec<u8>,
+        ) -> anyhow::Result<LoginSuccess> {
+            let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+            let mut decoder = PacketDecoder::new();
+            let mut encoder = PacketEncoder::new();
+
+            let handshake = Handshake {
+                protocol_version: 765,
+                server_address,
+                server_port: addr.port(),
+                next_state: HandshakeState::Login,
+            };
+            send(
+                &mut encoder,
+                &mut writer,
+                ClientHandshakePacket::Handshake(handshake),
+            )
+            .await?;
+            let login_start = LoginStart {
+                username: "Steve".to_string(),
+                uuid: Uuid::nil(),
+            };
+            send(
+                &mut encoder,
+                &mut writer,
+                ClientLoginPacket::LoginStart(login_start),
+            )
+            .await?;
+
+            loop {
+                let frame = decoder.read_frame(&mut reader).await?;
+                match ServerLoginPacket::read(&mut &frame[..])? {
+                    ServerLoginPacket::LoginPluginRequest(request) => {
+                        anyhow::ensure!(request.channel == "velocity:player_info");
+                        let response = LoginPluginResponse {
+                            message_id: request.message_id,
+                            successful: true,
+                            data: velocity_data.clone(),
+                        };
+                        send(
+                            &mut encoder,
+                            &mut writer,
+                            ClientLoginPacket::LoginPluginResponse(response),
+                        )
+                        .await?;
+                    }
+                    ServerLoginPacket::LoginSuccess(success) => return Ok(success),
+                    packet => anyhow::bail!("unexpected packet {packet:?}"),
+                }
+            }
+        }
+
+        fn run(
+            runtime: &Runtime,
+            app: &mut App,
+            server_address: &str,
+            velocity_data: Vec<u8>,
+        ) -> anyhow::Result<LoginSuccess> {
+            let client = runtime.spawn(proxy(
+                local_addr(app),
+                server_address.to_string(),
+                velocity_data,
+            ));
+            while !client.is_finished() {
+                app.update();
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            runtime.block_on(client).unwrap()
+        }
+
+        #[test]
+        fn bungeecord() {
+            let runtime = Runtime::new().unwrap();
+            let mut app = server(&runtime, Forwarding::BungeeCord);
+
+            let server_address = "localhost\u{0}10.0.0.1\u{0}00000000000000000000000000000007\u{0}\
+                [{\"name\":\"textures\",\"value\":\"e30=\"}]";
+            let success = run(&runtime, &mut app, server_address, Vec::new()).unwrap();
+            assert_eq!(success.uuid, Uuid::from_u128(7));
+            assert_eq!(success.username, "Steve");
+            assert_eq!(success.properties[0].name, "textures");
+
+            let logins = app.world.resource::<Logins>();
+            assert_eq!(logins.hosts, ["localhost"]);
+            assert_eq!(logins.addrs, [IpAddr::from([10, 0, 0, 1])]);
+        }
+
+        #[test]
+        fn velocity() {
+            let runtime = Runtime::new().unwrap();
+            let secret = b"secret".to_vec();
+            let mut app = server(&runtime, Forwarding::Velocity { secret });
+
+            let addr = IpAddr::from([10, 0, 0, 2]);
+            let data = velocity_data(b"secret", addr, Uuid::from_u128(7), "Alex");
+            let success = run(&runtime, &mut app, "localhost", data).unwrap();
+            assert_eq!(success.uuid, Uuid::from_u128(7));
+            assert_eq!(success.username, "Alex");
+            assert_eq!(success.properties[0].signature.as_deref(), Some("c2ln"));
+            assert_eq!(app.world.resource::<Logins>().addrs, [addr]);
+
+            let data = velocity_data(b"other", addr, Uuid::from_u128(7), "Alex");
+            assert!(run(&runtime, &mut app, "localhost", data).is_err());
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::net::{Ipv4Addr, SocketAddr};
+#[cfg(feature = "online-mode")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use bevy_ecs::system::Resource;
+use tokio::runtime::Handle;
+
+#[cfg(feature = "online-mode")]
+use crate::auth::SessionService;
+#[cfg(feature = "forwarding")]
+use crate::forwarding::Forwarding;
+
+/// Configuration of [`NetworkPlugin`](crate::NetworkPlugin), read when the
+/// plugin is added. The defaults are used if the resource doesn't exist by
+/// then.
+#[derive(Resource, Clone, Debug)]
+pub struct NetworkSettings {
+    /// Addresses to listen on. Use `[::]` to accept IPv6 connections, which
+    /// on most systems accepts IPv4 connections as well.
+    ///
+    /// With a port of 0, a free port is picked, see
+    /// [`SharedNetworkState::local_addrs`](crate::SharedNetworkState::local_addrs).
+    pub addresses: Vec<SocketAddr>,
+    /// Number of open connections above which new ones are closed.
+    pub max_connections: usize,
+    /// Number of open connections from a single IP address above which new
+    /// ones from that address are closed, or `None` for no limit.
+    pub max_connections_per_ip: Option<usize>,
+    /// Time a new connection has to be set up before it is dropped.
+    pub connection_timeout: Duration,
+    /// Number of accepted connections waiting to be spawned as entities.
+    pub max_pending_connections: usize,
+    /// Runtime to run the connections on, or `None` to start a new one.
+    pub runtime: Option<Handle>,
+    /// Service that authenticates players, such as
+    /// [`HttpSessionService`](crate::auth::HttpSessionService), or `None`
+    /// to trust the name clients claim, as in offline mode.
+    #[cfg(feature = "online-mode")]
+    pub session_service: Option<Arc<dyn SessionService>>,
+    /// How a proxy in front of the server forwards players.
+    #[cfg(feature = "forwarding")]
+    pub forwarding: Forwarding,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            addresses: vec![SocketAddr::from((Ipv4Addr::UNSPECIFIED, 25565))],
+            max_connections: 1024,
+            max_connections_per_ip: None,
+            connection_timeout: Duration::from_secs(5),
+            max_pending_connections: 64,
+            runtime: None,
+            #[cfg(feature = "online-mode")]
+            session_service: None,
+            #[cfg(feature = "forwarding")]
+            forwarding: Forwarding::None,
+        }
+    }
+}
@@ -5,11 +5,21 @@ use uuid::Uuid;
 pub struct GameProfile {
     id: Uuid,
     name: String,
+    properties: Vec<Property>,
 }
 
 impl GameProfile {
     pub fn new(id: Uuid, name: String) -> Self {
-        Self { id, name }
+        Self {
+            id,
+            name,
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn with_properties(mut self, properties: Vec<Property>) -> Self {
+        self.properties = properties;
+        self
     }
 
     pub fn id(&self) -> Uuid {
@@ -19,6 +29,18 @@ impl GameProfile {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Properties the session server signed for the player, such as the
+    /// `textures` of its skin and cape.
+    pub fn properties(&self) -> &[Property] {
+        &self.properties
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
 }
 
 pub fn offline_uuid(username: &str) -> Uuid {
@@ -36,7 +58,7 @@ pub fn offline_uuid(username: &str) -> Uuid {
     builder.into_uuid()
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Reflect, Default)]
 pub struct Property {
     name: String,
     value: String,
@@ -12,6 +12,8 @@ pub enum Error {
     InvalidDiscriminant(i32),
     #[error("Invalid UTF-8")]
     Utf8(#[from] Utf8Error),
+    #[error("NBT error")]
+    Nbt(#[from] cellophanemc_nbt::error::Error),
     #[error("BlockPos out of range")]
     BlockPosOutOfRange(#[from] cellophanemc_core::block_pos::Error),
     #[error("failed to decode field `{field}` of packet `{packet}`")]
@@ -12,6 +12,9 @@ use uuid::Uuid;
 use cellophanemc_core::block_pos::PackedBlockPos;
 use cellophanemc_core::chunk_pos::{ChunkSection, DynPalette, PaletteContainer};
 use cellophanemc_core::palette::Palette;
+use cellophanemc_nbt::binary::decode::{
+    from_network_binary_with_limits, value_from_network_binary_with_limits, NbtReadLimits,
+};
 use cellophanemc_nbt::Compound;
 
 use crate::error::{Error, Result};
@@ -249,85 +252,30 @@ impl Encoder for PackedBlockPos {
 
 impl Encoder for cellophanemc_nbt::Value {
     fn write(&self, writer: &mut impl Write) -> Result<()> {
-        writer.write_i8(self.id() as i8).map_err(Error::from)?;
-
-        fn write_raw(nbt: &cellophanemc_nbt::Value, dst: &mut impl Write) -> io::Result<()> {
-            match *nbt {
-                cellophanemc_nbt::Value::Byte(val) => dst.write_i8(val),
-                cellophanemc_nbt::Value::Short(val) => dst.write_i16::<BigEndian>(val),
-                cellophanemc_nbt::Value::Int(val) => dst.write_i32::<BigEndian>(val),
-                cellophanemc_nbt::Value::Long(val) => dst.write_i64::<BigEndian>(val),
-                cellophanemc_nbt::Value::Float(val) => dst.write_f32::<BigEndian>(val),
-                cellophanemc_nbt::Value::Double(val) => dst.write_f64::<BigEndian>(val),
-                cellophanemc_nbt::Value::ByteArray(ref val) => {
-                    dst.write_i32::<BigEndian>(val.len() as i32)?;
-                    for &i in val {
-                        dst.write_i8(i)?;
-                    }
-                    Ok(())
-                }
-                cellophanemc_nbt::Value::String(ref val) => {
-                    let encoded = cesu8::to_java_cesu8(val.as_str());
-                    dst.write_i16::<BigEndian>(encoded.len() as i16)?;
-                    dst.write_all(&encoded)?;
-                    Ok(())
-                }
-                cellophanemc_nbt::Value::List(ref vals) => {
-                    if vals.is_empty() {
-                        dst.write_u8(0)?; // TAG_End
-                        dst.write_i32::<BigEndian>(0)?;
-                    } else {
-                        let first_id = vals[0].id();
-                        dst.write_u8(first_id)?;
-                        dst.write_i32::<BigEndian>(vals.len() as i32)?;
-                        for val in vals {
-                            write_raw(val, dst)?;
-                        }
-                    }
-                    Ok(())
-                }
-                cellophanemc_nbt::Value::Compound(ref vals) => {
-                    for (name, ref val) in vals.iter() {
-                        dst.write_u8(val.id())?;
-                        let name = cesu8::to_java_cesu8(name.as_str());
-                        dst.write_i16::<BigEndian>(name.len() as i16)?;
-                        dst.write_all(&name)?;
-                        write_raw(val, dst)?;
-                    }
-                    dst.write_u8(0)?; // TAG_End
-                    Ok(())
-                }
-                cellophanemc_nbt::Value::IntArray(ref vals) => {
-                    dst.write_i32::<BigEndian>(vals.len() as i32)?;
-                    for &val in vals {
-                        dst.write_i32::<BigEndian>(val)?;
-                    }
-                    Ok(())
-                }
-                cellophanemc_nbt::Value::LongArray(ref vals) => {
-                    dst.write_i32::<BigEndian>(vals.len() as i32)?;
-                    for &val in vals {
-                        dst.write_i64::<BigEndian>(val)?;
-                    }
-                    Ok(())
-                }
-            }
-        }
-
-        write_raw(self, writer).map_err(Error::from)
+        cellophanemc_nbt::binary::encode::value_to_network_binary(Some(self), writer)?;
+        Ok(())
     }
 }
 
 impl Decoder for cellophanemc_nbt::Value {
     fn read(reader: &mut impl Read) -> Result<Self> {
-        todo!()
+        match value_from_network_binary_with_limits(reader, NbtReadLimits::network())? {
+            Some(value) => Ok(value),
+            None => Err(cellophanemc_nbt::error::Error::EndTagInValue.into()),
+        }
+    }
+}
+
+impl Encoder for Compound {
+    fn write(&self, writer: &mut impl Write) -> Result<()> {
+        cellophanemc_nbt::binary::encode::to_network_binary(self, writer)?;
+        Ok(())
     }
 }
 
-impl<S> Decoder for Compound<S> {
+impl Decoder for Compound {
     fn read(reader: &mut impl Read) -> Result<Self> {
-        let (compound, _) = cellophanemc_nbt::binary::decode::from_binary(reader)?;
-        Ok(compound)
+        Ok(from_network_binary_with_limits(reader, NbtReadLimits::network())?)
     }
 }
 
@@ -1,7 +1,7 @@
 use bit_set::BitSet;
 use glam::IVec3;
 
-use cellophanemc_nbt::aa::Value;
+use cellophanemc_nbt::Compound;
 
 use super::*;
 
@@ -32,7 +32,7 @@ packets!(
     ChunkData {
         chunk_x i32;
         chunk_z i32;
-        heightmaps Value;
+        heightmaps Compound;
         data VarIntPrefixedVec<u8>;
         block_entities VarIntPrefixedVec<ChunkDataBlockEntity>;
         sky_light_mask BitSet;
@@ -101,7 +101,7 @@ packets!(
         packed_xz u8;
         y u16;
         kind VarInt;
-        data Value;
+        data Compound;
     },
 
 
@@ -109,3 +109,46 @@ packets!(
         blocks VarIntPrefixedVec<u8>
     }
 );
+
+#[cfg(test)]
+mod tests {
+    use bit_set::BitSet;
+
+    use cellophanemc_nbt::{Compound, Value};
+
+    use crate::packets::server::{ChunkData, ChunkDataBlockEntity};
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn chunk_data_heightmaps() {
+        let mut heightmaps = Compound::new();
+        heightmaps.insert("MOTION_BLOCKING", Value::LongArray(vec![1, 2, 3]));
+        let mut sign = Compound::new();
+        sign.insert("is_waxed", 1i8);
+        let packet = ChunkData {
+            chunk_x: -2,
+            chunk_z: 5,
+            heightmaps,
+            data: vec![0; 4],
+            block_entities: vec![ChunkDataBlockEntity {
+                packed_xz: 0x12,
+                y: 64,
+                kind: 7,
+                data: sign,
+            }],
+            sky_light_mask: BitSet::new(),
+            block_light_mask: BitSet::new(),
+            empty_sky_light_mask: BitSet::new(),
+            empty_block_light_mask: BitSet::new(),
+            sky_updates: Vec::new(),
+            block_updates: Vec::new(),
+        };
+
+        let mut buf = Vec::new();
+        packet.write(&mut buf).unwrap();
+        // The nameless root compound directly follows the chunk position.
+        assert_eq!(buf[8..12], [0x0a, 0x0c, 0x00, 0x0f]);
+        assert_eq!(&buf[12..27], b"MOTION_BLOCKING");
+        assert_eq!(ChunkData::read(&mut buf.as_slice()).unwrap(), packet);
+    }
+}
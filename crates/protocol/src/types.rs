@@ -2,6 +2,10 @@ use std::io::{Read, Write};
 
 use byteorder::{ReadBytesExt, WriteBytesExt};
 
+use cellophanemc_nbt::binary::decode::{value_from_network_binary_with_limits, NbtReadLimits};
+use cellophanemc_nbt::binary::encode::value_to_network_binary;
+use cellophanemc_nbt::{Compound, Value};
+
 use crate::{Decoder, Encoder, VarInt};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd)]
@@ -64,7 +68,7 @@ pub enum Slot {
     Data {
         item_id: usize,
         count: u8,
-        nbt: cellophanemc_nbt::aa::Value,
+        nbt: Option<Compound>,
     },
 }
 
@@ -75,7 +79,16 @@ impl Decoder for Slot {
         } else {
             let item_id = VarInt::read(reader)?.0 as usize;
             let count = reader.read_u8()?;
-            let nbt = cellophanemc_nbt::aa::Value::read(reader)?;
+            let limits = NbtReadLimits::network();
+            let nbt = match value_from_network_binary_with_limits(reader, limits)? {
+                Some(Value::Compound(compound)) => Some(compound),
+                Some(value) => {
+                    return Err(
+                        cellophanemc_nbt::error::Error::RootTagNotCompound(value.tag()).into(),
+                    )
+                }
+                None => None,
+            };
             Ok(Slot::Data {
                 item_id,
                 count,
@@ -99,7 +112,10 @@ impl Encoder for Slot {
                 writer.write_u8(1)?;
                 VarInt(*item_id as i32).write(writer)?;
                 writer.write_u8(*count)?;
-                nbt.write(writer)?;
+                match nbt {
+                    Some(compound) => compound.write(writer)?,
+                    None => value_to_network_binary::<_, String>(None, writer)?,
+                }
             }
         }
         Ok(())
@@ -133,3 +149,66 @@ impl Encoder for Hand {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cellophanemc_nbt::{Compound, Value};
+
+    use crate::error::Error;
+    use crate::types::Slot;
+    use crate::{Decoder, Encoder};
+
+    fn round_trip(slot: &Slot) -> Vec<u8> {
+        let mut buf = Vec::new();
+        slot.write(&mut buf).unwrap();
+        assert_eq!(&Slot::read(&mut buf.as_slice()).unwrap(), slot);
+        buf
+    }
+
+    #[test]
+    fn slot_without_nbt() {
+        assert_eq!(round_trip(&Slot::Empty), [0]);
+        let slot = Slot::Data {
+            item_id: 300,
+            count: 64,
+            nbt: None,
+        };
+        // Present, item id as a VarInt, count, then TAG_End for no NBT.
+        assert_eq!(round_trip(&slot), [1, 0xac, 0x02, 64, 0]);
+    }
+
+    #[test]
+    fn slot_with_nbt() {
+        let mut nbt = Compound::new();
+        nbt.insert("a", 1i8);
+        let slot = Slot::Data {
+            item_id: 1,
+            count: 1,
+            nbt: Some(nbt),
+        };
+        // Since 1.20.2 the root compound has no name.
+        assert_eq!(
+            round_trip(&slot),
+            [1, 1, 1, 0x0a, 0x01, 0x00, 0x01, b'a', 1, 0x00]
+        );
+    }
+
+    #[test]
+    fn slot_nbt_is_limited() {
+        let mut nbt = Compound::new();
+        nbt.insert("data", Value::ByteArray(vec![0; 1024 * 1024]));
+        let slot = Slot::Data {
+            item_id: 1,
+            count: 1,
+            nbt: Some(nbt),
+        };
+        let mut buf = Vec::new();
+        slot.write(&mut buf).unwrap();
+        assert!(matches!(
+            Slot::read(&mut buf.as_slice()),
+            Err(Error::Nbt(
+                cellophanemc_nbt::error::Error::ElementLimitExceeded(_)
+            ))
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 use glam::{IVec3, ivec3};
 
+use cellophanemc_anvil::chunk as anvil;
 use cellophanemc_core::biome::Biome;
 use cellophanemc_core::block_state::BlockStateId;
 use cellophanemc_core::chunk_pos::{ChunkPos, ChunkSection, PaletteContainer, Strategy};
@@ -9,6 +10,10 @@ pub struct Chunk {
     chunk_pos: ChunkPos,
     pub sections: Vec<ChunkSection>,
     min_build_height: i32,
+    /// The NBT this chunk was loaded from, without block states and biomes,
+    /// so that saving keeps what isn't modeled here, such as its generation
+    /// status, heightmaps, structures, block entities and light.
+    pub metadata: Option<anvil::Chunk>,
 }
 
 impl Chunk {
@@ -32,16 +37,21 @@ impl Chunk {
             chunk_pos,
             sections,
             min_build_height: -64,
+            metadata: None,
         }
     }
 
+    pub fn chunk_pos(&self) -> ChunkPos {
+        self.chunk_pos
+    }
+
     #[inline(always)]
     fn block_to_section(y: i32) -> i32 {
         y >> 4
     }
 
     #[inline(always)]
-    fn min_section_y(&self) -> i32 {
+    pub fn min_section_y(&self) -> i32 {
         Chunk::block_to_section(self.min_build_height)
     }
 
@@ -0,0 +1,365 @@
+//! Loading and saving chunks off the main thread.
+//!
+//! [`ChunkIo`] hands requests to a pool of worker threads, each owning the
+//! region files of a fixed set of regions, so all requests for a region run
+//! one after another in the order they were made. Results come back as
+//! [`ChunkLoadedEvent`] and [`ChunkSavedEvent`] in [`PreUpdate`].
+//!
+//! Chunks are passed around as NBT, see
+//! [`chunk_serializer`](crate::chunk_serializer) for the conversion to
+//! [`Chunk`](crate::chunk::Chunk). Chunks read from disk are first upgraded
+//! to the current data version by the [`DataFixer`] of their dimension.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use bevy_app::{App, Plugin, PreUpdate};
+use bevy_ecs::event::{Event, EventWriter};
+use bevy_ecs::system::{ResMut, Resource};
+use flume::{Receiver, Sender};
+
+use cellophanemc_anvil::cache::{RegionCache, RegionPos, DEFAULT_MAX_OPEN_REGIONS};
+use cellophanemc_anvil::error::Error;
+use cellophanemc_anvil::fixer::DataFixer;
+use cellophanemc_core::chunk_pos::ChunkPos;
+use cellophanemc_nbt::Compound;
+
+pub const DEFAULT_WORKERS: usize = 2;
+
+pub struct ChunkIoPlugin {
+    /// Folder of the region files, such as `world/region`.
+    pub region_dir: PathBuf,
+    /// Dimension of the chunks, such as `minecraft:overworld`, which decides
+    /// how old chunks are upgraded.
+    pub dimension: String,
+    pub workers: usize,
+}
+
+impl Default for ChunkIoPlugin {
+    fn default() -> Self {
+        Self {
+            region_dir: PathBuf::from("world/region"),
+            dimension: "minecraft:overworld".to_string(),
+            workers: DEFAULT_WORKERS,
+        }
+    }
+}
+
+impl Plugin for ChunkIoPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ChunkIo::new(
+            self.region_dir.clone(),
+            &self.dimension,
+            self.workers,
+        ));
+        app.add_event::<ChunkLoadedEvent>();
+        app.add_event::<ChunkSavedEvent>();
+        app.add_systems(PreUpdate, send_chunk_io_events);
+    }
+}
+
+/// Sent once a chunk requested with [`ChunkIo::load`] has been read.
+#[derive(Event, Debug)]
+pub struct ChunkLoadedEvent {
+    pub pos: ChunkPos,
+    /// The chunk NBT, or `None` if the chunk has never been saved.
+    ///
+    /// Chunks without a `DataVersion`, or with one that can't be upgraded,
+    /// fail with [`Error::MissingDataVersion`] and
+    /// [`Error::UnsupportedDataVersion`].
+    pub result: Result<Option<Compound>, Error>,
+}
+
+/// Sent once a chunk passed to [`ChunkIo::save`] has been written.
+#[derive(Event, Debug)]
+pub struct ChunkSavedEvent {
+    pub pos: ChunkPos,
+    pub result: Result<(), Error>,
+}
+
+enum Request {
+    Load(ChunkPos),
+    Save(ChunkPos),
+}
+
+enum Response {
+    Loaded(ChunkLoadedEvent),
+    Saved(ChunkSavedEvent),
+}
+
+/// Chunks passed to [`ChunkIo::save`] that haven't been written yet.
+type PendingSaves = Arc<Mutex<HashMap<ChunkPos, Compound>>>;
+
+#[derive(Resource)]
+pub struct ChunkIo {
+    workers: Vec<Sender<Request>>,
+    threads: Vec<JoinHandle<()>>,
+    pending_loads: HashSet<ChunkPos>,
+    pending_saves: PendingSaves,
+    results: Receiver<Response>,
+}
+
+impl ChunkIo {
+    /// Starts `workers` threads serving the region files in `region_dir`,
+    /// which holds the chunks of `dimension`.
+    pub fn new(region_dir: impl Into<PathBuf>, dimension: &str, workers: usize) -> Self {
+        let region_dir = region_dir.into();
+        let fixer = Arc::new(DataFixer::chunks(dimension));
+        let workers = workers.max(1);
+        let max_open = (DEFAULT_MAX_OPEN_REGIONS / workers).max(1);
+        let pending_saves = PendingSaves::default();
+        let (results_send, results) = flume::unbounded();
+
+        let mut senders = Vec::with_capacity(workers);
+        let mut threads = Vec::with_capacity(workers);
+        for index in 0..workers {
+            let (send, recv) = flume::unbounded();
+            let cache = RegionCache::new(&region_dir).with_max_open(max_open);
+            let fixer = fixer.clone();
+            let pending_saves = pending_saves.clone();
+            let results_send = results_send.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("Chunk IO #{index}"))
+                .spawn(move || run_worker(cache, &fixer, recv, pending_saves, results_send))
+                .expect("failed to spawn chunk IO thread");
+            senders.push(send);
+            threads.push(thread);
+        }
+
+        Self {
+            workers: senders,
+            threads,
+            pending_loads: HashSet::new(),
+            pending_saves,
+            results,
+        }
+    }
+
+    /// Requests the chunk at `pos` to be read.
+    ///
+    /// Returns `false` without making another request if the chunk is
+    /// already being loaded. If the chunk is waiting to be saved, the
+    /// pending data is returned instead of what is on disk.
+    pub fn load(&mut self, pos: ChunkPos) -> bool {
+        if !self.pending_loads.insert(pos) {
+            return false;
+        }
+        self.send(pos, Request::Load(pos));
+        true
+    }
+
+    /// Requests `data` to be saved as the chunk at `pos`.
+    ///
+    /// Saving a chunk that is still waiting for a previous save replaces the
+    /// pending data, so only the latest version is written.
+    pub fn save(&mut self, pos: ChunkPos, data: Compound) {
+        let queued = self.pending_saves.lock().unwrap().insert(pos, data).is_some();
+        if !queued {
+            self.send(pos, Request::Save(pos));
+        }
+    }
+
+    pub fn is_loading(&self, pos: ChunkPos) -> bool {
+        self.pending_loads.contains(&pos)
+    }
+
+    pub fn is_saving(&self, pos: ChunkPos) -> bool {
+        self.pending_saves.lock().unwrap().contains_key(&pos)
+    }
+
+    fn send(&self, pos: ChunkPos, request: Request) {
+        let region = RegionPos::from_chunk(pos.x, pos.z);
+        let hash = region.x as i64 * 31 + region.z as i64;
+        let worker = hash.rem_euclid(self.workers.len() as i64) as usize;
+        // Workers only stop once the senders are dropped.
+        let _ = self.workers[worker].send(request);
+    }
+}
+
+impl Drop for ChunkIo {
+    /// Waits for all requested saves to be written.
+    fn drop(&mut self) {
+        self.workers.clear();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_worker(
+    mut cache: RegionCache,
+    fixer: &DataFixer,
+    requests: Receiver<Request>,
+    pending_saves: PendingSaves,
+    results: Sender<Response>,
+) {
+    while let Ok(request) = requests.recv() {
+        let response = match request {
+            Request::Load(pos) => {
+                let pending = pending_saves.lock().unwrap().get(&pos).cloned();
+                let result = match pending {
+                    Some(data) => Ok(Some(data)),
+                    None => read_chunk(&mut cache, fixer, pos),
+                };
+                Response::Loaded(ChunkLoadedEvent { pos, result })
+            }
+            Request::Save(pos) => {
+                // Requests for a region all go to the same worker, so no load
+                // of this chunk can run until it is written.
+                let Some(data) = pending_saves.lock().unwrap().remove(&pos) else {
+                    continue;
+                };
+                let result = cache.write_chunk(pos.x, pos.z, &data);
+                Response::Saved(ChunkSavedEvent { pos, result })
+            }
+        };
+        let _ = results.send(response);
+    }
+    let _ = cache.close();
+}
+
+/// Reads the chunk at `pos` from disk, upgraded to the current data version.
+fn read_chunk(
+    cache: &mut RegionCache,
+    fixer: &DataFixer,
+    pos: ChunkPos,
+) -> Result<Option<Compound>, Error> {
+    let Some(mut chunk) = cache.read_chunk(pos.x, pos.z)? else {
+        return Ok(None);
+    };
+    fixer.upgrade(&mut chunk.data)?;
+    Ok(Some(chunk.data))
+}
+
+fn send_chunk_io_events(
+    mut chunk_io: ResMut<ChunkIo>,
+    mut loaded_events: EventWriter<ChunkLoadedEvent>,
+    mut saved_events: EventWriter<ChunkSavedEvent>,
+) {
+    while let Ok(response) = chunk_io.results.try_recv() {
+        match response {
+            Response::Loaded(event) => {
+                chunk_io.pending_loads.remove(&event.pos);
+                loaded_events.send(event);
+            }
+            Response::Saved(event) => saved_events.send(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy_app::App;
+    use bevy_ecs::event::Events;
+
+    use cellophanemc_anvil::error::Error;
+    use cellophanemc_anvil::DATA_VERSION;
+    use cellophanemc_core::chunk_pos::ChunkPos;
+    use cellophanemc_nbt::{Compound, Value};
+
+    use crate::chunk_io::{ChunkIo, ChunkIoPlugin, ChunkLoadedEvent, ChunkSavedEvent, Response};
+
+    fn chunk(version: i32) -> Compound {
+        let mut chunk = Compound::new();
+        chunk.insert("DataVersion", DATA_VERSION);
+        chunk.insert("version", version);
+        chunk
+    }
+
+    fn next_loaded(chunk_io: &ChunkIo) -> ChunkLoadedEvent {
+        loop {
+            match chunk_io.results.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Response::Loaded(event) => return event,
+                Response::Saved(event) => event.result.unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn deduplicates_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut chunk_io = ChunkIo::new(dir.path(), "minecraft:overworld", 2);
+        let pos = ChunkPos::new(1, -40);
+
+        for version in 0..10 {
+            chunk_io.save(pos, chunk(version));
+        }
+        assert!(chunk_io.load(pos));
+        assert!(!chunk_io.load(pos));
+
+        let event = next_loaded(&chunk_io);
+        assert_eq!(event.pos, pos);
+        assert_eq!(event.result.unwrap().unwrap().get("version"), Some(&Value::Int(9)));
+        assert!(chunk_io.results.try_recv().is_err());
+
+        drop(chunk_io);
+        let mut chunk_io = ChunkIo::new(dir.path(), "minecraft:overworld", 1);
+        chunk_io.load(pos);
+        chunk_io.load(ChunkPos::new(0, 0));
+        let event = next_loaded(&chunk_io);
+        assert_eq!(event.result.unwrap().unwrap().get("version"), Some(&Value::Int(9)));
+        assert!(next_loaded(&chunk_io).result.unwrap().is_none());
+    }
+
+    #[test]
+    fn sends_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = App::new();
+        app.add_plugins(ChunkIoPlugin {
+            region_dir: dir.path().to_path_buf(),
+            dimension: "minecraft:overworld".to_string(),
+            workers: 1,
+        });
+
+        let pos = ChunkPos::new(3, 3);
+        let mut chunk_io = app.world.resource_mut::<ChunkIo>();
+        chunk_io.save(pos, chunk(1));
+        chunk_io.load(pos);
+
+        let mut loaded = None;
+        for _ in 0..500 {
+            app.update();
+            if let Some(event) = app.world.resource_mut::<Events<ChunkLoadedEvent>>().drain().next() {
+                loaded = Some(event);
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let loaded = loaded.expect("chunk was not loaded");
+        assert_eq!(loaded.pos, pos);
+        assert!(loaded.result.unwrap().is_some());
+        assert!(!app.world.resource::<ChunkIo>().is_loading(pos));
+        assert!(app.world.resource::<Events<ChunkSavedEvent>>().len() <= 1);
+    }
+
+    #[test]
+    fn upgrades_chunks_read_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut chunk_io = ChunkIo::new(dir.path(), "minecraft:the_nether", 1);
+        let mut old = Compound::new();
+        old.insert("DataVersion", 3600);
+        old.insert("Status", "minecraft:full");
+        chunk_io.save(ChunkPos::new(0, 0), old);
+        let mut future = Compound::new();
+        future.insert("DataVersion", DATA_VERSION + 1);
+        chunk_io.save(ChunkPos::new(1, 0), future);
+        chunk_io.save(ChunkPos::new(2, 0), Compound::new());
+        drop(chunk_io);
+
+        let mut chunk_io = ChunkIo::new(dir.path(), "minecraft:the_nether", 1);
+        for x in 0..3 {
+            chunk_io.load(ChunkPos::new(x, 0));
+        }
+        let chunk = next_loaded(&chunk_io).result.unwrap().unwrap();
+        assert_eq!(chunk.get("DataVersion"), Some(&Value::Int(DATA_VERSION)));
+        assert!(matches!(
+            next_loaded(&chunk_io).result,
+            Err(Error::UnsupportedDataVersion(version)) if version == DATA_VERSION + 1
+        ));
+        assert!(matches!(next_loaded(&chunk_io).result, Err(Error::MissingDataVersion)));
+    }
+}
@@ -0,0 +1,464 @@
+//! Conversion between chunks stored in region files and the in-memory
+//! [`Chunk`].
+//!
+//! Region files refer to block states and biomes by name, with a per-section
+//! palette and the entries packed into long arrays. [`ChunkRegistries`] maps
+//! those names to the ids used at runtime.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use thiserror::Error;
+
+use cellophanemc_anvil::chunk as anvil;
+use cellophanemc_core::biome::Biome;
+use cellophanemc_core::block::Block;
+use cellophanemc_core::block_state::BlockStateId;
+use cellophanemc_core::chunk_pos::{ChunkPos, PaletteContainer, Strategy};
+use cellophanemc_core::packed_array::PackedArray;
+use cellophanemc_nbt::{Compound, Value};
+
+use crate::chunk::Chunk;
+
+pub use cellophanemc_anvil::DATA_VERSION;
+
+#[derive(Debug, Error)]
+pub enum ChunkSerializerError {
+    #[error("section {section_y} has an empty palette")]
+    EmptyPalette { section_y: i8 },
+    #[error("section {section_y} has {actual} longs of data, expected {expected}")]
+    InvalidDataLength {
+        section_y: i8,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("block state {0} is not registered")]
+    UnknownBlockState(u16),
+    #[error("biome {0} is not registered")]
+    UnknownBiome(u16),
+    #[error("failed to serialize chunk NBT")]
+    Anvil(#[from] cellophanemc_anvil::error::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ChunkSerializerError>;
+
+struct BlockStates {
+    default: BTreeMap<String, String>,
+    states: HashMap<BTreeMap<String, String>, BlockStateId>,
+}
+
+/// Name lookups for the block states and biomes saved in chunks.
+pub struct ChunkRegistries {
+    blocks: HashMap<String, BlockStates>,
+    block_states: HashMap<BlockStateId, (String, BTreeMap<String, String>)>,
+    biomes: Vec<String>,
+    biome_ids: HashMap<String, Biome>,
+}
+
+impl ChunkRegistries {
+    /// Creates the lookups from the block registry and the biome names,
+    /// ordered by their network id.
+    pub fn new<B: Into<String>>(
+        block_registry: &BTreeMap<String, Block>,
+        biomes: impl IntoIterator<Item=B>,
+    ) -> Self {
+        let mut blocks = HashMap::with_capacity(block_registry.len());
+        let mut block_states = HashMap::new();
+        for (name, block) in block_registry {
+            let default = block.default_block_state()
+                .map(|state| state.properties.clone())
+                .unwrap_or_default();
+            let mut states = HashMap::with_capacity(block.states().len());
+            for state in block.states() {
+                states.insert(state.properties.clone(), state.id);
+                block_states.insert(state.id, (name.clone(), state.properties.clone()));
+            }
+            blocks.insert(name.clone(), BlockStates { default, states });
+        }
+
+        let biomes = biomes.into_iter().map(Into::into).collect::<Vec<String>>();
+        let biome_ids = biomes.iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), Biome(id as u16)))
+            .collect();
+
+        Self {
+            blocks,
+            block_states,
+            biomes,
+            biome_ids,
+        }
+    }
+
+    /// Looks up the state of block `name` with `properties`.
+    ///
+    /// Properties that are missing or have no matching state keep the value of
+    /// the block's default state.
+    pub fn block_state_id(&self, name: &str, properties: &BTreeMap<String, String>) -> Option<BlockStateId> {
+        let block = self.blocks.get(name)?;
+        let mut state = block.default.clone();
+        for (key, value) in properties {
+            if let Some(current) = state.get_mut(key) {
+                let previous = std::mem::replace(current, value.clone());
+                if !block.states.contains_key(&state) {
+                    state.insert(key.clone(), previous);
+                }
+            }
+        }
+        block.states.get(&state).copied()
+    }
+
+    /// Returns the block name and properties of `id`.
+    pub fn block_state(&self, id: BlockStateId) -> Option<(&str, &BTreeMap<String, String>)> {
+        self.block_states.get(&id)
+            .map(|(name, properties)| (name.as_str(), properties))
+    }
+
+    pub fn biome(&self, name: &str) -> Option<Biome> {
+        self.biome_ids.get(name).copied()
+    }
+
+    pub fn biome_name(&self, biome: Biome) -> Option<&str> {
+        self.biomes.get(biome.0 as usize).map(String::as_str)
+    }
+
+    fn block_state_from_nbt(&self, state: &Compound) -> BlockStateId {
+        let Some(Value::String(name)) = state.get("Name") else {
+            return BlockStateId(0);
+        };
+        let properties = match state.get("Properties") {
+            Some(Value::Compound(properties)) => properties.iter()
+                .filter_map(|(key, value)| match value {
+                    Value::String(value) => Some((key.clone(), value.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+        self.block_state_id(name, &properties).unwrap_or_default()
+    }
+
+    fn block_state_to_nbt(&self, id: BlockStateId) -> Result<Compound> {
+        let (name, properties) = self.block_state(id)
+            .ok_or(ChunkSerializerError::UnknownBlockState(id.0))?;
+        let mut state = Compound::new();
+        state.insert("Name", name);
+        if !properties.is_empty() {
+            let mut nbt = Compound::new();
+            for (key, value) in properties {
+                nbt.insert(key.as_str(), value.as_str());
+            }
+            state.insert("Properties", nbt);
+        }
+        Ok(state)
+    }
+}
+
+/// Builds a [`Chunk`] from a chunk read from a region file.
+///
+/// Like vanilla, block states and biomes that aren't registered are loaded as
+/// `BlockStateId(0)` and `Biome(0)`, and sections outside the height of the
+/// chunk are ignored. Data pointing past the end of its palette reads as the
+/// first palette entry. Everything but block states and biomes is kept in
+/// [`Chunk::metadata`] for [`write`].
+pub fn read(registries: &ChunkRegistries, nbt: &anvil::Chunk) -> Result<Chunk> {
+    let mut chunk = Chunk::new(ChunkPos::new(nbt.x_pos, nbt.z_pos));
+    let min_section_y = chunk.min_section_y();
+
+    for section in &nbt.sections {
+        let Some(target) = usize::try_from(section.y as i32 - min_section_y)
+            .ok()
+            .and_then(|index| chunk.sections.get_mut(index)) else {
+            continue;
+        };
+
+        if let Some(block_states) = &section.block_states {
+            let palette = block_states.palette.iter()
+                .map(|state| registries.block_state_from_nbt(state))
+                .collect();
+            target.block_states = unpack(Strategy::Chunk, section.y, palette, block_states.data.as_deref())?;
+        }
+        if let Some(biomes) = &section.biomes {
+            let palette = biomes.palette.iter()
+                .map(|name| registries.biome(name).unwrap_or_default())
+                .collect();
+            target.biomes = unpack(Strategy::Biome, section.y, palette, biomes.data.as_deref())?;
+        }
+    }
+
+    let mut metadata = nbt.clone();
+    for section in &mut metadata.sections {
+        section.block_states = None;
+        section.biomes = None;
+    }
+    chunk.metadata = Some(metadata);
+    Ok(chunk)
+}
+
+/// Converts `chunk` into the NBT stored in region files.
+///
+/// Everything but block states and biomes comes from [`Chunk::metadata`], so
+/// it is saved the way it was loaded. Chunks that weren't loaded are saved
+/// as fully generated.
+pub fn write(registries: &ChunkRegistries, chunk: &Chunk) -> Result<Compound> {
+    let pos = chunk.chunk_pos();
+    let min_section_y = chunk.min_section_y();
+
+    let mut nbt = chunk.metadata.clone().unwrap_or_else(|| anvil::Chunk {
+        status: anvil::ChunkStatus::Full,
+        post_processing: vec![Vec::new(); chunk.sections.len()],
+        ..anvil::Chunk::default()
+    });
+    nbt.data_version = DATA_VERSION;
+    nbt.x_pos = pos.x;
+    nbt.z_pos = pos.z;
+    nbt.y_pos = min_section_y;
+
+    for (index, section) in chunk.sections.iter().enumerate() {
+        let y = (min_section_y + index as i32) as i8;
+        let target = match nbt.sections.iter().position(|section| section.y == y) {
+            Some(i) => &mut nbt.sections[i],
+            None => {
+                nbt.sections.push(anvil::Section { y, ..anvil::Section::default() });
+                nbt.sections.last_mut().unwrap()
+            }
+        };
+        target.block_states = Some(pack(&section.block_states, |id| {
+            registries.block_state_to_nbt(id)
+        })?);
+        target.biomes = Some(pack(&section.biomes, |biome| {
+            registries.biome_name(biome)
+                .map(str::to_string)
+                .ok_or(ChunkSerializerError::UnknownBiome(biome.0))
+        })?);
+    }
+    nbt.sections.sort_by_key(|section| section.y);
+
+    Ok(nbt.to_nbt()?)
+}
+
+/// Bits per entry vanilla uses to save a palette of `len` entries, where
+/// `len` is at least 2.
+fn serialized_bits(strategy: Strategy, len: usize) -> usize {
+    let bits = (usize::BITS - (len - 1).leading_zeros()) as usize;
+    bits.max(strategy.min_bits_per_entry())
+}
+
+fn unpack<T: Copy + Eq + Hash + Debug>(
+    strategy: Strategy,
+    section_y: i8,
+    palette: Vec<T>,
+    data: Option<&[i64]>,
+) -> Result<PaletteContainer<T>> {
+    match palette.len() {
+        0 => return Err(ChunkSerializerError::EmptyPalette { section_y }),
+        1 => return Ok(PaletteContainer::new(strategy, palette[0])),
+        _ => {}
+    }
+
+    let mut storage = PackedArray::new(strategy.max_entries(), serialized_bits(strategy, palette.len()));
+    let data = data.unwrap_or_default();
+    if data.len() != storage.bits.len() {
+        return Err(ChunkSerializerError::InvalidDataLength {
+            section_y,
+            expected: storage.bits.len(),
+            actual: data.len(),
+        });
+    }
+    for (bits, &long) in storage.bits.iter_mut().zip(data) {
+        *bits = long as u64;
+    }
+
+    // Out of range entries read as the first one, as in `values()`.
+    for i in 0..storage.len() {
+        if storage.get(i).is_some_and(|index| index as usize >= palette.len()) {
+            storage.set(i, 0);
+        }
+    }
+
+    Ok(PaletteContainer::from_palette(strategy, palette, storage))
+}
+
+/// Packs `container` with a palette of only the entries it uses.
+fn pack<T, E>(
+    container: &PaletteContainer<T>,
+    encode: impl FnMut(T) -> Result<E>,
+) -> Result<anvil::PackedPalettedContainer<E>>
+where
+    T: Copy + Default + Eq + Hash + Debug,
+{
+    let mut palette = Vec::new();
+    let mut ids = HashMap::new();
+    let indices = container.values()
+        .map(|value| {
+            *ids.entry(value).or_insert_with(|| {
+                palette.push(value);
+                palette.len() as u64 - 1
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let len = palette.len();
+    let data = (len > 1).then(|| {
+        let mut storage = PackedArray::new(indices.len(), serialized_bits(container.strategy, len));
+        for (i, index) in indices.into_iter().enumerate() {
+            storage.set(i, index);
+        }
+        storage.bits.into_iter().map(|long| long as i64).collect()
+    });
+    Ok(anvil::PackedPalettedContainer {
+        palette: palette.into_iter().map(encode).collect::<Result<_>>()?,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use cellophanemc_anvil::chunk as anvil;
+    use cellophanemc_core::biome::Biome;
+    use cellophanemc_core::block::Block;
+    use cellophanemc_core::block_state::BlockStateId;
+    use cellophanemc_core::chunk_pos::ChunkPos;
+    use cellophanemc_core::volume::{BiomeVolume, BiomeVolumeMut, BlockVolume, BlockVolumeMut};
+    use cellophanemc_nbt::{Compound, List, Value};
+
+    use crate::chunk::Chunk;
+    use crate::chunk_serializer::{read, write, ChunkRegistries, DATA_VERSION};
+
+    fn registries() -> ChunkRegistries {
+        let blocks: BTreeMap<String, Block> = serde_json::from_str(r#"{
+            "minecraft:air": {"states": [{"id": 0, "default": true}]},
+            "minecraft:stone": {"states": [{"id": 1, "default": true}]},
+            "minecraft:oak_log": {
+                "properties": {"axis": ["x", "y", "z"]},
+                "states": [
+                    {"id": 2, "properties": {"axis": "x"}},
+                    {"id": 3, "default": true, "properties": {"axis": "y"}},
+                    {"id": 4, "properties": {"axis": "z"}}
+                ]
+            }
+        }"#).unwrap();
+        ChunkRegistries::new(&blocks, ["minecraft:plains", "minecraft:desert"])
+    }
+
+    #[test]
+    fn resolves_block_states() {
+        let registries = registries();
+        let properties = |axis: &str| BTreeMap::from([("axis".to_string(), axis.to_string())]);
+
+        assert_eq!(registries.block_state_id("minecraft:oak_log", &properties("z")), Some(BlockStateId(4)));
+        assert_eq!(registries.block_state_id("minecraft:oak_log", &properties("w")), Some(BlockStateId(3)));
+        assert_eq!(registries.block_state_id("minecraft:oak_log", &BTreeMap::new()), Some(BlockStateId(3)));
+        assert_eq!(registries.block_state_id("minecraft:dirt", &BTreeMap::new()), None);
+        assert_eq!(registries.block_state(BlockStateId(2)), Some(("minecraft:oak_log", &properties("x"))));
+    }
+
+    #[test]
+    fn round_trip() {
+        let registries = registries();
+        let mut chunk = Chunk::new(ChunkPos::new(3, -7));
+        for x in 0..16 {
+            for z in 0..16 {
+                chunk.set_block_at(x, -64, z, BlockStateId(1));
+                chunk.set_block_at(x, 20, z, BlockStateId(2 + ((x + z) % 3) as u16));
+            }
+        }
+        chunk.set_biome(0, 100, 0, Biome(1));
+
+        let nbt = write(&registries, &chunk).unwrap();
+        assert_eq!(nbt.get("DataVersion"), Some(&Value::Int(DATA_VERSION)));
+
+        let loaded = read(&registries, &anvil::Chunk::from_nbt(nbt).unwrap()).unwrap();
+        assert_eq!(loaded.chunk_pos(), ChunkPos::new(3, -7));
+        for (loaded, section) in loaded.sections.iter().zip(&chunk.sections) {
+            assert!(loaded.block_states.values().eq(section.block_states.values()));
+            assert!(loaded.biomes.values().eq(section.biomes.values()));
+        }
+        assert_eq!(loaded.block_at(5, 20, 7), BlockStateId(2));
+        assert_eq!(loaded.sections[10].biomes.get(0, 0, 0), Some(Biome(1)));
+    }
+
+    #[test]
+    fn unknown_entries_load_as_defaults() {
+        let registries = registries();
+        let state = |name: &str| {
+            let mut state = Compound::new();
+            state.insert("Name", name);
+            state
+        };
+
+        let mut block_states = Compound::new();
+        block_states.insert("palette", List::from(vec![state("minecraft:stone"), state("minecraft:unknown")]));
+        // Four bits per entry, alternating between both palette entries.
+        block_states.insert("data", vec![0x1010_1010_1010_1010i64; 256]);
+        let mut biomes = Compound::new();
+        biomes.insert("palette", List::from(vec!["minecraft:unknown".to_string()]));
+
+        let mut section = Compound::new();
+        section.insert("Y", 0i8);
+        section.insert("block_states", block_states);
+        section.insert("biomes", biomes);
+        let mut light_only = Compound::new();
+        light_only.insert("Y", -5i8);
+
+        let mut nbt = write(&registries, &Chunk::new(ChunkPos::new(0, 0))).unwrap();
+        nbt.insert("sections", List::from(vec![light_only, section]));
+
+        let chunk = read(&registries, &anvil::Chunk::from_nbt(nbt).unwrap()).unwrap();
+        assert_eq!(chunk.block_at(0, 0, 0), BlockStateId(1));
+        assert_eq!(chunk.block_at(1, 0, 0), BlockStateId(0));
+        assert_eq!(chunk.biome(1, 1, 1), Biome(0));
+    }
+
+    #[test]
+    fn out_of_range_indices_read_as_first_entry() {
+        let registries = registries();
+        let state = |name: &str| {
+            let mut state = Compound::new();
+            state.insert("Name", name);
+            state
+        };
+
+        let mut block_states = Compound::new();
+        block_states.insert("palette", List::from(vec![state("minecraft:stone"), state("minecraft:air")]));
+        // Four bits per entry, alternating between 0 and 3.
+        block_states.insert("data", vec![0x3030_3030_3030_3030i64; 256]);
+        let mut section = Compound::new();
+        section.insert("Y", 0i8);
+        section.insert("block_states", block_states);
+
+        let mut nbt = write(&registries, &Chunk::new(ChunkPos::new(0, 0))).unwrap();
+        nbt.insert("sections", List::from(vec![section]));
+
+        let chunk = read(&registries, &anvil::Chunk::from_nbt(nbt).unwrap()).unwrap();
+        assert_eq!(chunk.block_at(0, 0, 0), BlockStateId(1));
+        assert_eq!(chunk.block_at(1, 0, 0), BlockStateId(1));
+    }
+
+    #[test]
+    fn keeps_metadata() {
+        let registries = registries();
+        let nbt = write(&registries, &Chunk::new(ChunkPos::new(1, 2))).unwrap();
+        let mut source = anvil::Chunk::from_nbt(nbt).unwrap();
+        source.status = anvil::ChunkStatus::Features;
+        source.inhabited_time = 40;
+        source.heightmaps.ocean_floor_wg = Some(vec![5; 37]);
+        let mut chest = Compound::new();
+        chest.insert("id", "minecraft:chest");
+        source.block_entities.push(chest);
+        source.structures.references.insert("minecraft:village_plains", vec![42i64]);
+        source.sections.insert(0, anvil::Section {
+            y: -5,
+            sky_light: Some(vec![-1; 2048]),
+            ..anvil::Section::default()
+        });
+        source.extra.insert("ToBeTicked", List::from(vec![List::from(vec![5i16])]));
+        let nbt = source.to_nbt().unwrap();
+
+        let chunk = read(&registries, &source).unwrap();
+        assert_eq!(write(&registries, &chunk).unwrap(), nbt);
+    }
+}
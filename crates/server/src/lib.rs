@@ -8,7 +8,7 @@ use cellophanemc_core::block_state::BlockStateId;
 use cellophanemc_core::chunk_pos::ChunkPos;
 use cellophanemc_core::palette::Palette;
 use cellophanemc_core::volume::BlockVolumeMut;
-use cellophanemc_nbt::aa::Value::Compound;
+use cellophanemc_nbt::Compound;
 use cellophanemc_network::RemoteConnection;
 use cellophanemc_protocol::Encoder;
 use cellophanemc_protocol::packets::server::{ChunkData, JoinGame, MovePlayer, ServerPlayPacket, SetChunkCacheCenter, SetSpawn};
@@ -22,8 +22,9 @@ pub mod chunk_map;
 pub mod world;
 pub mod chunk_generator;
 pub mod chunk;
+pub mod chunk_io;
+pub mod chunk_serializer;
 pub mod biome_generator;
-mod chunk_storage;
 
 #[derive(Component)]
 struct Player;
@@ -103,7 +104,7 @@ impl From<&Chunk> for ChunkData {
         ChunkData {
             chunk_x: 0,
             chunk_z: 0,
-            heightmaps: Compound(Default::default()),
+            heightmaps: Compound::new(),
             data,
             block_entities: vec![],
             sky_light_mask: BitSet::new(),
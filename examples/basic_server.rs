@@ -11,9 +11,13 @@ use serde::de::DeserializeOwned;
 use serde_json::json;
 
 use cellophanemc_network::*;
+#[cfg(feature = "forwarding")]
+use cellophanemc_network::forwarding::Forwarding;
+#[cfg(any(feature = "online-mode", feature = "forwarding"))]
+use cellophanemc_network::settings::NetworkSettings;
 use cellophanemc_profile::GameProfile;
 use cellophanemc_protocol::packets::client::{ClientConfigurationPacket, ClientHandshakePacket, ClientLoginPacket, ClientPacket, ClientStatusPacket, HandshakeState};
-use cellophanemc_protocol::packets::server::{FinishConfiguration, LoginSuccess, Pong, RegistryData, Response, ServerConfigurationPacket, ServerLoginPacket, ServerStatusPacket};
+use cellophanemc_protocol::packets::server::{FinishConfiguration, LoginSuccess, LoginSuccessProperty, Pong, RegistryData, Response, ServerConfigurationPacket, ServerLoginPacket, ServerStatusPacket};
 use cellophanemc_server::chunk_view::PlayerChunkLoaderPlugin;
 use cellophanemc_server::keepalive::{KeepAlivePlugin, KeepAliveState};
 use cellophanemc_server::place_new_player;
@@ -26,7 +30,10 @@ use cellophanemc_world::dimension::DimensionType;
 
 fn main() {
     println!("start server");
-    App::new()
+    let mut app = App::new();
+    #[cfg(any(feature = "online-mode", feature = "forwarding"))]
+    app.insert_resource(network_settings());
+    app
         .add_plugins(DefaultPlugins)
         .add_plugins(WorldInspectorPlugin::new())
         .register_type::<ClientProtocolVersion>()
@@ -39,6 +46,7 @@ fn main() {
         .add_systems(Update, handle_handshake)
         .add_systems(Update, handle_status)
         .add_systems(Update, handle_login)
+        .add_systems(Update, handle_authentication)
         .add_systems(Update, handle_configuration_start)
         .add_systems(Update, handle_configuration_ack)
         .register_type::<Player>()
@@ -47,6 +55,25 @@ fn main() {
         .run();
 }
 
+#[cfg(any(feature = "online-mode", feature = "forwarding"))]
+fn network_settings() -> NetworkSettings {
+    #[allow(unused_mut)]
+    let mut settings = NetworkSettings::default();
+    #[cfg(feature = "online-mode")]
+    {
+        settings.session_service = Some(std::sync::Arc::new(cellophanemc_network::auth::HttpSessionService::default()));
+    }
+    #[cfg(feature = "forwarding")]
+    {
+        // Behind Velocity if its secret is given, BungeeCord otherwise.
+        settings.forwarding = match std::env::var("VELOCITY_SECRET") {
+            Ok(secret) => Forwarding::Velocity { secret: secret.into_bytes() },
+            Err(_) => Forwarding::BungeeCord,
+        };
+    }
+    settings
+}
+
 #[derive(Event, Debug, Copy, Clone)]
 struct ConfigurationStartEvent {
     connection: Entity,
@@ -125,6 +152,9 @@ fn handle_status(
 fn handle_login(
     mut configuration_start_events: EventWriter<ConfigurationStartEvent>,
     mut connections: Query<(Entity, &mut RemoteConnection)>,
+    #[cfg(any(feature = "encryption", feature = "forwarding"))] players: Query<&Player>,
+    #[cfg(feature = "encryption")] server_key: Res<cellophanemc_network::encryption::ServerKey>,
+    #[cfg(any(feature = "online-mode", feature = "forwarding"))] shared: Res<SharedNetworkState>,
     mut events: EventReader<ClientPacketReceivedEvent>,
     mut commands: Commands,
 ) {
@@ -136,25 +166,76 @@ fn handle_login(
                         println!("login start {}", p.username);
 
                         if let Ok((entity, mut connection)) = connections.get_mut(event.connection) {
-                            commands.entity(entity).insert(
-                                cellophanemc_server::player::Player::new(
-                                    cellophanemc_profile::GameProfile::new(
-                                        p.uuid,
-                                        p.username.clone(),
-                                    )
-                                )
-                            );
+                            let profile = GameProfile::new(p.uuid, p.username.clone());
+                            #[cfg(feature = "forwarding")]
+                            let profile = connection.forwarded().map_or(profile, |player| player.profile(&p.username));
+                            commands.entity(entity).insert(Player::new(profile.clone()));
                             println!("added world to player");
-                            let _ = connection.send_packet(ServerLoginPacket::LoginSuccess(
-                                LoginSuccess {
-                                    uuid: p.uuid,
-                                    username: p.username.clone(),
-                                    properties: vec![],
+
+                            // Proxies authenticate players themselves, so there is no
+                            // encryption behind them.
+                            #[cfg(feature = "forwarding")]
+                            match shared.forwarding() {
+                                Forwarding::None => {}
+                                Forwarding::BungeeCord => {
+                                    send_login_success(&mut connection, &profile);
+                                    continue;
+                                }
+                                Forwarding::Velocity { .. } => {
+                                    let _ = connection.request_forwarding();
+                                    continue;
+                                }
+                            }
+
+                            #[cfg(feature = "encryption")]
+                            let _ = connection.request_encryption(&server_key);
+                            #[cfg(not(feature = "encryption"))]
+                            send_login_success(&mut connection, &profile);
+                        }
+                    }
+                    #[cfg(feature = "encryption")]
+                    ClientLoginPacket::EncryptionResponse(p) => {
+                        if let Ok((entity, mut connection)) = connections.get_mut(event.connection) {
+                            #[cfg_attr(not(feature = "online-mode"), allow(unused_variables))]
+                            let shared_secret = match connection.finish_encryption(&server_key, p) {
+                                Ok(shared_secret) => shared_secret,
+                                Err(e) => {
+                                    println!("failed to enable encryption: {e}");
+                                    commands.entity(entity).despawn();
+                                    continue;
                                 }
-                            ));
+                            };
+                            let Ok(player) = players.get(entity) else { continue };
+                            #[cfg(feature = "online-mode")]
+                            {
+                                let hash = cellophanemc_network::auth::server_hash("", &shared_secret, server_key.public_key_der());
+                                shared.authenticate(entity, player.profile.name(), hash, Some(connection.remote_addr.ip()));
+                            }
+                            #[cfg(not(feature = "online-mode"))]
+                            send_login_success(&mut connection, &player.profile);
                         }
                     }
+                    #[cfg(not(feature = "encryption"))]
                     ClientLoginPacket::EncryptionResponse(_) => {}
+                    #[cfg(feature = "forwarding")]
+                    ClientLoginPacket::LoginPluginResponse(p) => {
+                        if let (Ok((entity, mut connection)), Forwarding::Velocity { secret }) =
+                            (connections.get_mut(event.connection), shared.forwarding()) {
+                            let Ok(player) = players.get(entity) else { continue };
+                            match connection.finish_forwarding(secret, p) {
+                                Ok(forwarded) => {
+                                    let profile = forwarded.profile(player.profile.name());
+                                    commands.entity(entity).insert(Player::new(profile.clone()));
+                                    send_login_success(&mut connection, &profile);
+                                }
+                                Err(e) => {
+                                    println!("failed to forward player: {e}");
+                                    commands.entity(entity).despawn();
+                                }
+                            }
+                        }
+                    }
+                    #[cfg(not(feature = "forwarding"))]
                     ClientLoginPacket::LoginPluginResponse(_) => {}
                     ClientLoginPacket::LoginAck(_) => {
                         println!("Configuration ack");
@@ -170,6 +251,52 @@ fn handle_login(
     }
 }
 
+#[cfg(feature = "online-mode")]
+fn handle_authentication(
+    mut connections: Query<(Entity, &mut RemoteConnection, &mut Player)>,
+    mut events: EventReader<AuthenticationEvent>,
+    mut commands: Commands,
+) {
+    for event in events.iter() {
+        if let Ok((entity, mut connection, mut player)) = connections.get_mut(event.connection) {
+            match &event.result {
+                Ok(Some(profile)) => {
+                    println!("authenticated {}", profile.name());
+                    player.profile = profile.clone();
+                    send_login_success(&mut connection, profile);
+                }
+                Ok(None) => {
+                    println!("{} is not logged in", player.profile.name());
+                    commands.entity(entity).despawn();
+                }
+                Err(e) => {
+                    println!("failed to authenticate {}: {e}", player.profile.name());
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "online-mode"))]
+fn handle_authentication() {}
+
+fn send_login_success(connection: &mut RemoteConnection, profile: &GameProfile) {
+    #[cfg(feature = "compression")]
+    let _ = connection.set_compression(256);
+    let _ = connection.send_packet(ServerLoginPacket::LoginSuccess(
+        LoginSuccess {
+            uuid: profile.id(),
+            username: profile.name().to_string(),
+            properties: profile.properties().iter().map(|property| LoginSuccessProperty {
+                name: property.name().to_string(),
+                value: property.value().to_string(),
+                signature: property.signature().map(str::to_string),
+            }).collect(),
+        }
+    ));
+}
+
 const DIMENSION_TYPE_OVERWORLD: &str = include_str!("../assets/minecraft/dimension_type/overworld.json");
 
 fn handle_configuration_start(